@@ -0,0 +1,197 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::vec3::Point3;
+
+/// Axis-aligned bounding box.
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+
+    pub const UNIVERSE: Aabb = Aabb {
+        x: Interval::UNIVERSE,
+        y: Interval::UNIVERSE,
+        z: Interval::UNIVERSE,
+    };
+
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        let mut bbox = Aabb { x, y, z };
+        bbox.pad_to_minimums();
+        bbox
+    }
+
+    /// Box with `a` and `b` as opposite corners, in any order.
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        Aabb::new(
+            Interval::new(a.x().min(b.x()), a.x().max(b.x())),
+            Interval::new(a.y().min(b.y()), a.y().max(b.y())),
+            Interval::new(a.z().min(b.z()), a.z().max(b.z())),
+        )
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        Aabb {
+            x: Interval::enclosing(&a.x, &b.x),
+            y: Interval::enclosing(&a.y, &b.y),
+            z: Interval::enclosing(&a.z, &b.z),
+        }
+    }
+
+    /// The region inside both boxes, or `EMPTY` if they do not meet.
+    pub fn overlap(a: &Aabb, b: &Aabb) -> Self {
+        let bbox = Aabb {
+            x: Interval::overlap(&a.x, &b.x),
+            y: Interval::overlap(&a.y, &b.y),
+            z: Interval::overlap(&a.z, &b.z),
+        };
+        if bbox.is_empty() {
+            Aabb::EMPTY
+        } else {
+            bbox
+        }
+    }
+
+    pub fn axis_interval(&self, n: usize) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.min > self.x.max || self.y.min > self.y.max || self.z.min > self.z.max
+    }
+
+    pub fn min(&self) -> Point3 {
+        Point3::new(self.x.min, self.y.min, self.z.min)
+    }
+
+    pub fn max(&self) -> Point3 {
+        Point3::new(self.x.max, self.y.max, self.z.max)
+    }
+
+    /// Box center. Unbounded axes (e.g. of an infinite plane) use their finite end, or
+    /// zero, so the result stays usable for BVH construction.
+    pub fn centroid(&self) -> Point3 {
+        let mid = |i: &Interval| match (i.min.is_finite(), i.max.is_finite()) {
+            (true, true) => 0.5 * (i.min + i.max),
+            (true, false) => i.min,
+            (false, true) => i.max,
+            (false, false) => 0.0,
+        };
+        Point3::new(mid(&self.x), mid(&self.y), mid(&self.z))
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        [self.x, self.y, self.z]
+            .iter()
+            .all(|i| i.min.is_finite() && i.max.is_finite())
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    /// Box enclosing this one after `transform`, from its eight transformed corners.
+    pub fn transformed(&self, transform: &Transform) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        if !self.is_bounded() {
+            return Aabb::UNIVERSE;
+        }
+
+        let mut bbox = Aabb::EMPTY;
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { self.x.min } else { self.x.max },
+                if i & 2 == 0 { self.y.min } else { self.y.max },
+                if i & 4 == 0 { self.z.min } else { self.z.max },
+            );
+            let p = transform.point(&corner);
+            bbox = Aabb::surrounding(&bbox, &Aabb::from_points(p, p));
+        }
+        bbox
+    }
+
+    pub fn hit(&self, r: &Ray, ray_t: &Interval) -> bool {
+        self.clip(r, ray_t).is_some()
+    }
+
+    /// Part of `ray_t` over which the ray is inside the box, if any.
+    pub fn clip(&self, r: &Ray, ray_t: &Interval) -> Option<Interval> {
+        let origin = r.origin();
+        let direction = r.direction();
+        let mut t_min = ray_t.min;
+        let mut t_max = ray_t.max;
+
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let (o, d) = match axis {
+                0 => (origin.x(), direction.x()),
+                1 => (origin.y(), direction.y()),
+                _ => (origin.z(), direction.z()),
+            };
+            let adinv = 1.0 / d;
+
+            let t0 = (ax.min - o) * adinv;
+            let t1 = (ax.max - o) * adinv;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            // NaN from 0 * inf on a slab boundary leaves the bound unchanged.
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some(Interval::new(t_min, t_max))
+    }
+
+    // Avoids zero-thickness boxes around axis-aligned flat geometry.
+    fn pad_to_minimums(&mut self) {
+        let delta = 0.0001;
+        if self.x.size() < delta {
+            self.x = self.x.expand(delta);
+        }
+        if self.y.size() < delta {
+            self.y = self.y.expand(delta);
+        }
+        if self.z.size() < delta {
+            self.z = self.z.expand(delta);
+        }
+    }
+}
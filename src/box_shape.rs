@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::vec3::{Point3, Vec3};
+
+/// Axis-aligned box between two corners, intersected with a slab test. Rotated or
+/// sheared boxes are made by wrapping one in an `Instance`. Each face is textured
+/// over the full [0, 1] square.
+pub struct BoxShape {
+    min: Point3,
+    max: Point3,
+    mat: Arc<dyn Material>,
+}
+
+impl BoxShape {
+    pub fn new(a: Point3, b: Point3, mat: Arc<dyn Material>) -> Self {
+        BoxShape {
+            min: Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+            mat,
+        }
+    }
+
+    /// Cube of side `size` with its minimum corner at the origin.
+    pub fn cube(size: f64, mat: Arc<dyn Material>) -> Self {
+        BoxShape::new(Point3::zero(), Point3::new(size, size, size), mat)
+    }
+
+    pub fn min(&self) -> Point3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Point3 {
+        self.max
+    }
+
+    // Axes spanning u and v on the faces perpendicular to `axis`.
+    fn face_axes(axis: usize) -> (usize, usize) {
+        match axis {
+            0 => (2, 1),
+            1 => (0, 2),
+            _ => (0, 1),
+        }
+    }
+
+    // Fills `rec` for a hit at `t` on the face perpendicular to `axis`.
+    fn hit_face(&self, r: &Ray, t: f64, axis: usize, positive: bool, rec: &mut HitRecord) -> bool {
+        let p = r.at(t);
+        let (ua, va) = Self::face_axes(axis);
+        let along = |a: usize| {
+            let (lo, hi) = (component(&self.min, a), component(&self.max, a));
+            if hi > lo {
+                ((component(&p, a) - lo) / (hi - lo)).clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        let (u, v) = (along(ua), along(va));
+        if self.mat.masked(u, v, &p) {
+            return false;
+        }
+
+        let sign = if positive { 1.0 } else { -1.0 };
+        let outward_normal = unit_axis(axis) * sign;
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        rec.set_normal_face(r, &outward_normal);
+        rec.set_shading_frame(r, &outward_normal, &unit_axis(ua));
+        rec.mat = self.mat.clone();
+        true
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let origin = r.origin();
+        let direction = r.direction();
+
+        // Entry and exit distances, with the axis and side of the face crossed at each.
+        let mut entry = (f64::NEG_INFINITY, 0, false);
+        let mut exit = (f64::INFINITY, 0, false);
+        for axis in 0..3 {
+            let (o, d) = (component(&origin, axis), component(&direction, axis));
+            let (lo, hi) = (component(&self.min, axis), component(&self.max, axis));
+            if d == 0.0 {
+                if o < lo || o > hi {
+                    return false;
+                }
+                continue;
+            }
+
+            let (t_lo, t_hi) = ((lo - o) / d, (hi - o) / d);
+            let (near, far) = if d > 0.0 {
+                ((t_lo, axis, false), (t_hi, axis, true))
+            } else {
+                ((t_hi, axis, true), (t_lo, axis, false))
+            };
+            if near.0 > entry.0 {
+                entry = near;
+            }
+            if far.0 < exit.0 {
+                exit = far;
+            }
+        }
+        if entry.0 > exit.0 {
+            return false;
+        }
+
+        for (t, axis, positive) in [entry, exit] {
+            if ray_t.surronds(t) && self.hit_face(r, t, axis, positive, rec) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.min, self.max)
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Box {
+            min: self.min.into(),
+            max: self.max.into(),
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+fn component(v: &Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn unit_axis(axis: usize) -> Vec3 {
+    match axis {
+        0 => Vec3::new(1.0, 0.0, 0.0),
+        1 => Vec3::new(0.0, 1.0, 0.0),
+        _ => Vec3::new(0.0, 0.0, 1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn hit(shape: &BoxShape, origin: [f64; 3], direction: [f64; 3]) -> Option<HitRecord> {
+        let r = Ray::new(
+            Point3::new(origin[0], origin[1], origin[2]),
+            Vec3::new(direction[0], direction[1], direction[2]),
+        );
+        let mut rec = HitRecord::default();
+        shape
+            .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn hits_entry_and_exit_faces() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let shape = BoxShape::new(Point3::new(1.0, 2.0, 3.0), Point3::new(-1.0, 0.0, 1.0), mat);
+
+        let rec = hit(&shape, [0.0, 1.0, 10.0], [0.0, 0.0, -2.0]).unwrap();
+        assert!((rec.t - 3.5).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert_eq!(<[f64; 3]>::from(rec.normal), [0.0, 0.0, 1.0]);
+
+        // From inside the exit face is hit from its back.
+        let rec = hit(&shape, [0.0, 1.0, 2.0], [-1.0, 0.0, 0.0]).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(!rec.front_face);
+        assert_eq!(<[f64; 3]>::from(rec.normal), [1.0, 0.0, 0.0]);
+
+        // Diagonally through the corner region, and past it.
+        let rec = hit(&shape, [-3.0, -2.0, 2.0], [1.0, 1.0, 0.0]).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(hit(&shape, [-3.0, 0.5, 2.0], [1.0, 10.0, 0.0]).is_none());
+        assert!(hit(&shape, [0.0, 3.0, 2.0], [1.0, 0.0, 0.0]).is_none());
+    }
+}
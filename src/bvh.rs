@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::vec3::Point3;
+
+const MAX_LEAF_SIZE: usize = 4;
+const SAH_BINS: usize = 12;
+// Traversal keeps at most one pending node per level, so trees must stay shallower than
+// the stack. Below `MAX_SAH_DEPTH` nodes are split at the median, which halves them.
+const STACK_SIZE: usize = 64;
+const MAX_SAH_DEPTH: usize = 32;
+
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+    bbox: Aabb,
+    // Interior nodes: index of the right child (the left child follows the node).
+    // Leaves: index of the first primitive in `Bvh::indices`.
+    offset: usize,
+    count: usize, // Primitive count, zero for interior nodes
+    axis: usize,  // Split axis of interior nodes; the left child lies toward -axis
+}
+
+/// Flat bounding volume hierarchy over primitive indices, built with a binned surface
+/// area heuristic. It only knows primitive boxes, so the same structure serves as the
+/// bottom level inside a mesh and as the top level over instances.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(boxes: &[Aabb]) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * boxes.len()),
+            indices: (0..boxes.len()).collect(),
+        };
+        if !boxes.is_empty() {
+            let centroids: Vec<_> = boxes.iter().map(|b| b.centroid()).collect();
+            bvh.build_node(boxes, &centroids, 0, boxes.len(), 0);
+        }
+        bvh
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Visits the primitives whose boxes the ray reaches, nearest subtree first.
+    /// `hit_primitive` must only write `rec` when it reports a hit.
+    pub fn hit<F>(
+        &self,
+        r: &Ray,
+        ray_t: &Interval,
+        rec: &mut HitRecord,
+        mut hit_primitive: F,
+    ) -> bool
+    where
+        F: FnMut(usize, &Interval, &mut HitRecord) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let direction = r.direction();
+        let negative = [
+            direction.x() < 0.0,
+            direction.y() < 0.0,
+            direction.z() < 0.0,
+        ];
+
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+        let mut stack = [0; STACK_SIZE];
+        let mut pending = 1;
+
+        while pending > 0 {
+            pending -= 1;
+            let index = stack[pending];
+            let node = &self.nodes[index];
+            if !node.bbox.hit(r, &Interval::new(ray_t.min, closest_so_far)) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &primitive in &self.indices[node.offset..node.offset + node.count] {
+                    let interval = Interval::new(ray_t.min, closest_so_far);
+                    if hit_primitive(primitive, &interval, rec) {
+                        hit_anything = true;
+                        closest_so_far = rec.t;
+                    }
+                }
+            } else {
+                // Push the far child first so the near child is visited first.
+                let (left, right) = (index + 1, node.offset);
+                let (near, far) = if negative[node.axis] {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                stack[pending] = far;
+                stack[pending + 1] = near;
+                pending += 2;
+            }
+        }
+
+        hit_anything
+    }
+
+    fn build_node(
+        &mut self,
+        boxes: &[Aabb],
+        centroids: &[Point3],
+        start: usize,
+        end: usize,
+        depth: usize,
+    ) -> usize {
+        let index = self.nodes.len();
+        let bbox = self.indices[start..end]
+            .iter()
+            .fold(Aabb::EMPTY, |acc, &i| Aabb::surrounding(&acc, &boxes[i]));
+        self.nodes.push(BvhNode {
+            bbox,
+            offset: start,
+            count: end - start,
+            axis: 0,
+        });
+
+        let count = end - start;
+        if count <= 1 {
+            return index;
+        }
+
+        let centroid_bounds = self.indices[start..end]
+            .iter()
+            .fold(Aabb::EMPTY, |acc, &i| {
+                Aabb::surrounding(&acc, &Aabb::from_points(centroids[i], centroids[i]))
+            });
+        let axis = centroid_bounds.longest_axis();
+        let extent = *centroid_bounds.axis_interval(axis);
+        let coord = |i: usize| match axis {
+            0 => centroids[i].x(),
+            1 => centroids[i].y(),
+            _ => centroids[i].z(),
+        };
+
+        let median = |indices: &mut [usize]| {
+            indices.select_nth_unstable_by(count / 2, |&a, &b| coord(a).total_cmp(&coord(b)));
+            start + count / 2
+        };
+        let mid = if extent.size() <= 1e-9 {
+            if count <= MAX_LEAF_SIZE {
+                return index;
+            }
+            start + count / 2
+        } else if depth >= MAX_SAH_DEPTH {
+            if count <= MAX_LEAF_SIZE {
+                return index;
+            }
+            median(&mut self.indices[start..end])
+        } else {
+            match self.sah_split(boxes, start, end, &extent, &coord, bbox.surface_area()) {
+                Some(threshold) => {
+                    let mid = partition(&mut self.indices[start..end], |&i| coord(i) < threshold);
+                    if mid == 0 || mid == count {
+                        start + count / 2
+                    } else {
+                        start + mid
+                    }
+                }
+                None if count <= MAX_LEAF_SIZE => return index,
+                None => median(&mut self.indices[start..end]),
+            }
+        };
+
+        self.build_node(boxes, centroids, start, mid, depth + 1);
+        let right = self.build_node(boxes, centroids, mid, end, depth + 1);
+        self.nodes[index].offset = right;
+        self.nodes[index].count = 0;
+        self.nodes[index].axis = axis;
+        index
+    }
+
+    // Best bin boundary along the split axis, or `None` if keeping a leaf is cheaper.
+    fn sah_split<C>(
+        &self,
+        boxes: &[Aabb],
+        start: usize,
+        end: usize,
+        extent: &Interval,
+        coord: &C,
+        parent_area: f64,
+    ) -> Option<f64>
+    where
+        C: Fn(usize) -> f64,
+    {
+        // Unbounded primitives make every area infinite; fall back to a median split.
+        if !parent_area.is_finite() {
+            return None;
+        }
+
+        let mut bin_boxes = [Aabb::EMPTY; SAH_BINS];
+        let mut bin_counts = [0usize; SAH_BINS];
+        let scale = SAH_BINS as f64 / extent.size();
+
+        for &i in &self.indices[start..end] {
+            let b = (((coord(i) - extent.min) * scale) as usize).min(SAH_BINS - 1);
+            bin_counts[b] += 1;
+            bin_boxes[b] = Aabb::surrounding(&bin_boxes[b], &boxes[i]);
+        }
+
+        let mut best: Option<(f64, usize)> = None;
+        for split in 1..SAH_BINS {
+            let (mut left_box, mut right_box) = (Aabb::EMPTY, Aabb::EMPTY);
+            let (mut left_count, mut right_count) = (0, 0);
+            for b in 0..split {
+                left_box = Aabb::surrounding(&left_box, &bin_boxes[b]);
+                left_count += bin_counts[b];
+            }
+            for b in split..SAH_BINS {
+                right_box = Aabb::surrounding(&right_box, &bin_boxes[b]);
+                right_count += bin_counts[b];
+            }
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+
+            let cost = 0.125
+                + (left_count as f64 * left_box.surface_area()
+                    + right_count as f64 * right_box.surface_area())
+                    / parent_area.max(1e-12);
+            if best.is_none_or(|(c, _)| cost < c) {
+                best = Some((cost, split));
+            }
+        }
+
+        let (cost, split) = best?;
+        let count = end - start;
+        if count <= MAX_LEAF_SIZE && cost >= count as f64 {
+            return None;
+        }
+        Some(extent.min + split as f64 / scale)
+    }
+}
+
+// Moves the elements matching `pred` to the front and returns how many there are.
+fn partition<T, P: Fn(&T) -> bool>(items: &mut [T], pred: P) -> usize {
+    let mut first = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first, i);
+            first += 1;
+        }
+    }
+    first
+}
+
+/// A BVH over arbitrary hittables, e.g. the top of a scene built with `HittableList`.
+/// Over `Instance`s of meshes, which carry their own BVHs, it is the top level of a
+/// two-level hierarchy: moving an instance only means building a new `BvhAccel`.
+pub struct BvhAccel {
+    objects: Vec<Arc<dyn Hittable>>,
+    bvh: Bvh,
+}
+
+impl BvhAccel {
+    pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Self {
+        let boxes: Vec<_> = objects.iter().map(|o| o.bounding_box()).collect();
+        BvhAccel {
+            bvh: Bvh::build(&boxes),
+            objects,
+        }
+    }
+
+    pub fn from_list(list: HittableList) -> Self {
+        BvhAccel::new(list.objects)
+    }
+}
+
+impl Hittable for BvhAccel {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(r, ray_t, rec, |i, interval, rec| {
+            self.objects[i].hit(r, interval, rec)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Group {
+            objects: self
+                .objects
+                .iter()
+                .map(|object| exporter.object(object.as_ref()))
+                .collect::<Result<_, _>>()?,
+            transform: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::util::{random_double_range, seed_rng};
+    use crate::vec3::{random_unit_vector, Vec3};
+
+    fn depth(bvh: &Bvh, index: usize) -> usize {
+        let node = &bvh.nodes[index];
+        if node.count > 0 {
+            0
+        } else {
+            1 + depth(bvh, index + 1).max(depth(bvh, node.offset))
+        }
+    }
+
+    #[test]
+    fn finds_the_same_hits_as_a_list() {
+        seed_rng(3);
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for _ in 0..500 {
+            let center = Point3::new(
+                random_double_range(-10.0, 10.0),
+                random_double_range(-10.0, 10.0),
+                random_double_range(-10.0, 10.0),
+            );
+            let radius = random_double_range(0.05, 0.8);
+            list.add(Arc::new(Sphere::new(center, radius, mat.clone())));
+        }
+        let bvh = BvhAccel::new(list.objects.clone());
+
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = 15.0 * random_unit_vector();
+            let target = Vec3::new(
+                random_double_range(-8.0, 8.0),
+                random_double_range(-8.0, 8.0),
+                random_double_range(-8.0, 8.0),
+            );
+            let r = Ray::new(origin, target - origin);
+            let (mut a, mut b) = (HitRecord::default(), HitRecord::default());
+            let found = list.hit(&r, &interval, &mut a);
+            assert_eq!(bvh.hit(&r, &interval, &mut b), found);
+            if found {
+                hits += 1;
+                assert_eq!(a.t, b.t);
+            }
+        }
+        assert!(hits > 500, "only {} rays hit", hits);
+    }
+
+    #[test]
+    fn skewed_scenes_stay_within_the_traversal_stack() {
+        // Each box is twice as far out as the last, so SAH peels them off one at a time.
+        let boxes: Vec<_> = (0..300)
+            .map(|i| {
+                let x = 2.0f64.powi(i);
+                Aabb::from_points(Point3::new(x, 0.0, 0.0), Point3::new(1.1 * x, 1.0, 1.0))
+            })
+            .collect();
+        let bvh = Bvh::build(&boxes);
+        assert!(depth(&bvh, 0) < STACK_SIZE - 1, "depth {}", depth(&bvh, 0));
+
+        // The nearest box is peeled off last, so it sits deepest in the tree.
+        let first = boxes[0].centroid();
+        let r = Ray::new(Point3::new(first.x(), 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut rec = HitRecord::default();
+        let mut visited = Vec::new();
+        bvh.hit(&r, &Interval::new(0.0, 10.0), &mut rec, |i, _, _| {
+            visited.push(i);
+            false
+        });
+        assert!(visited.contains(&0));
+    }
+}
@@ -1,16 +1,18 @@
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
 
-use crate::color::write_color;
 use crate::color::Color;
+use crate::film::{Film, ToneMap};
 use crate::interval::Interval;
+use crate::motion::MotionPath;
+use crate::output::Output;
 use crate::ray::Ray;
 use crate::util;
 use crate::vec3::unit_vector;
 use crate::vec3::Point3;
 use crate::vec3::Vec3;
-use image::{ImageBuffer, Rgba};
-use std::io::Write;
+use std::io::{self, Write};
 
 use rayon::prelude::*;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -30,6 +32,27 @@ pub struct CameraParams {
     pub focus_dist: f64,
 }
 
+/// What rays that leave the scene see.
+#[derive(Copy, Clone, Debug)]
+pub enum Background {
+    /// Blend by ray height from `horizon` (looking down) to `zenith` (looking up).
+    Gradient {
+        horizon: Color,
+        zenith: Color,
+    },
+    Solid(Color),
+}
+
+impl Default for Background {
+    /// The white-to-blue sky.
+    fn default() -> Self {
+        Background::Gradient {
+            horizon: Color::new(1.0, 1.0, 1.0),
+            zenith: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,      // Aspect ratio of the image
     pub img_width: usize,       // Rendered image width
@@ -42,17 +65,24 @@ pub struct Camera {
     pub vup: Vec3,              // Up vector of the camera
     pub defocus_angle: f64,     // Defocus angle
     pub focus_dist: f64,        // Distance to focus plane
-
-    pixel_samples_scale: f64, // Color scale factor for pixel samples
-    center: Point3,           // Camera center
-    pixel00_loc: Point3,      // Location of pixel (0,0)
-    pixel_delta_u: Vec3,      // Offset to pixel to the right
-    pixel_delta_v: Vec3,      // Offset to pixel below
-    u: Vec3,                  // Camera frame basis vector u
-    v: Vec3,                  // Camera frame basis vector v
-    w: Vec3,                  // Camera frame basis vector w
-    defocus_disk_u: Vec3,     // Horizontal defocus disk radius
-    defocus_disk_v: Vec3,     // Vertical defocus disk radius
+    pub shutter_open: f64,      // Time the shutter opens
+    pub shutter_close: f64,     // Time the shutter closes
+    pub background: Background, // Color of rays that escape the scene
+    pub seed: Option<u64>,      // Seed for a reproducible render, random if unset
+    pub lights: HittableList,   // Emitters sampled directly at diffuse bounces
+
+    lookfrom_path: Option<MotionPath>, // Camera position over time, if moving
+    lookat_path: Option<MotionPath>,   // Look-at point over time, if moving
+    pixel_samples_scale: f64,          // Color scale factor for pixel samples
+    center: Point3,                    // Camera center
+    pixel00_loc: Point3,               // Location of pixel (0,0)
+    pixel_delta_u: Vec3,               // Offset to pixel to the right
+    pixel_delta_v: Vec3,               // Offset to pixel below
+    u: Vec3,                           // Camera frame basis vector u
+    v: Vec3,                           // Camera frame basis vector v
+    w: Vec3,                           // Camera frame basis vector w
+    defocus_disk_u: Vec3,              // Horizontal defocus disk radius
+    defocus_disk_v: Vec3,              // Vertical defocus disk radius
 }
 
 impl Camera {
@@ -69,6 +99,13 @@ impl Camera {
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            background: Background::default(),
+            seed: None,
+            lights: HittableList::new(),
+            lookfrom_path: None,
+            lookat_path: None,
             pixel_samples_scale: 1.0,
             center: Point3::new(0.0, 0.0, 0.0),
             pixel00_loc: Point3::new(0.0, 0.0, 0.0),
@@ -95,48 +132,81 @@ impl Camera {
         self.focus_dist = params.focus_dist;
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
+    /// Rays are spread uniformly over `[open, close]`; moving objects and a moving
+    /// camera blur over that interval.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    /// Moves the camera over time. `lookfrom` and `lookat` replace the static
+    /// positions; `vup`, `vfov` and focus settings stay fixed.
+    pub fn set_motion(&mut self, lookfrom: MotionPath, lookat: MotionPath) {
+        self.lookfrom_path = Some(lookfrom);
+        self.lookat_path = Some(lookat);
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.lookfrom_path.is_some() || self.lookat_path.is_some()
+    }
+
+    /// Renders `world` and writes it to `output`, through `tone_map` for 8-bit formats.
+    /// Returns the film as well.
+    pub fn render_to(
+        &mut self,
+        world: &dyn Hittable,
+        output: &Output,
+        tone_map: &ToneMap,
+    ) -> io::Result<Film> {
+        let film = self.render(world);
+        output.write(&film, tone_map)?;
+        Ok(film)
+    }
+
+    pub fn render(&mut self, world: &dyn Hittable) -> Film {
         self.initialize();
-        println!("\nP3");
-        println!("{} {}", self.img_width, self.img_height);
-        println!("255");
+        let seed = self.seed.unwrap_or_else(rand::random);
 
-        let pixel_data = Arc::new(Mutex::new(vec![0u8; self.img_width * self.img_height * 4]));
+        let film = Mutex::new(Film::new(self.img_width, self.img_height));
         let completed_rows = Arc::new(AtomicUsize::new(0));
         let total_rows = self.img_height;
 
         (0..self.img_height).into_par_iter().for_each(|j| {
+            // Each row gets its own sequence, so a seeded render doesn't depend on
+            // which thread draws it.
+            util::seed_rng(seed ^ (j as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
             let mut row_data = Vec::with_capacity(self.img_width);
-            
+
             for i in 0..self.img_width {
                 let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
-                
-                for _ in 0..self.samples_per_pixel {
+                let mut depth = f32::INFINITY;
+
+                for sample in 0..self.samples_per_pixel {
                     let r = self.get_ray(i, j);
+                    if sample == 0 {
+                        depth = self.depth(&r, world);
+                    }
                     pixel_color = pixel_color + self.ray_color(&r, self.max_depth, world);
                 }
-                
-                row_data.push((i, self.pixel_samples_scale * pixel_color));
+
+                row_data.push((i, self.pixel_samples_scale * pixel_color, depth));
             }
-        
+
             {
-                let mut data = pixel_data.lock().unwrap();
-                for (i, color) in row_data {
-                    write_color(color, &mut data, self.img_width, j, i);
+                let mut film = film.lock().unwrap();
+                for (i, color, depth) in row_data {
+                    film.set_color(i, j, color);
+                    film.set_depth(i, j, depth);
                 }
             }
-            
+
             let completed = completed_rows.fetch_add(1, Ordering::Relaxed) + 1;
             eprint!("\rRows completed: {}/{}", completed, total_rows);
             std::io::stderr().flush().unwrap();
         });
         eprintln!("\rDone. ");
 
-        let final_data = Arc::try_unwrap(pixel_data).unwrap().into_inner().unwrap();
-        let img_buffer: ImageBuffer<Rgba<u8>, _> =
-        ImageBuffer::from_raw(self.img_width as u32, self.img_height as u32, final_data)
-            .unwrap();
-    img_buffer.save("img/output.png").unwrap();
+        film.into_inner().unwrap()
     }
 
     pub fn initialize(&mut self) {
@@ -146,48 +216,104 @@ impl Camera {
         }
         self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
 
-        self.center = self.lookfrom;
+        let frame = self.frame_at(self.lookfrom, self.lookat);
+        self.center = frame.center;
+        self.pixel00_loc = frame.pixel00_loc;
+        self.pixel_delta_u = frame.pixel_delta_u;
+        self.pixel_delta_v = frame.pixel_delta_v;
+        self.u = frame.u;
+        self.v = frame.v;
+        self.w = frame.w;
+        self.defocus_disk_u = frame.defocus_disk_u;
+        self.defocus_disk_v = frame.defocus_disk_v;
+    }
+
+    fn frame_at(&self, lookfrom: Point3, lookat: Point3) -> ViewFrame {
+        let center = lookfrom;
         let theta = util::degrees_to_radians(self.vfov);
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h * self.focus_dist;
         let viewport_width = viewport_height * (self.img_width as f64 / self.img_height as f64);
 
-        self.w = unit_vector(self.lookfrom - self.lookat);
-        self.u = unit_vector(self.vup.cross(&self.w));
-        self.v = self.w.cross(&self.u);
+        let w = unit_vector(lookfrom - lookat);
+        let u = unit_vector(self.vup.cross(&w));
+        let v = w.cross(&u);
 
-        let viewport_u = viewport_width * self.u;
-        let viewport_v = viewport_height * -self.v;
+        let viewport_u = viewport_width * u;
+        let viewport_v = viewport_height * -v;
 
-        self.pixel_delta_u = viewport_u / self.img_width as f64;
-        self.pixel_delta_v = viewport_v / self.img_height as f64;
+        let pixel_delta_u = viewport_u / self.img_width as f64;
+        let pixel_delta_v = viewport_v / self.img_height as f64;
 
         let viewport_upper_left =
-            self.center - (self.focus_dist * self.w) - viewport_u / 2.0 - viewport_v / 2.0;
+            center - (self.focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
 
-        self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
         let defocus_radius =
             self.focus_dist * (util::degrees_to_radians(self.defocus_angle / 2.0)).tan();
-        self.defocus_disk_u = self.u * defocus_radius;
-        self.defocus_disk_v = self.v * defocus_radius;
+
+        ViewFrame {
+            center,
+            pixel00_loc,
+            pixel_delta_u,
+            pixel_delta_v,
+            u,
+            v,
+            w,
+            defocus_disk_u: u * defocus_radius,
+            defocus_disk_v: v * defocus_radius,
+        }
     }
 
     pub fn get_ray(&self, i: usize, j: usize) -> Ray {
+        let ray_time = if self.shutter_close > self.shutter_open {
+            util::random_double_range(self.shutter_open, self.shutter_close)
+        } else {
+            self.shutter_open
+        };
+
+        let moving_frame = match (&self.lookfrom_path, &self.lookat_path) {
+            (Some(lookfrom), Some(lookat)) => {
+                Some(self.frame_at(lookfrom.at(ray_time), lookat.at(ray_time)))
+            }
+            _ => None,
+        };
+        let (center, pixel00_loc, pixel_delta_u, pixel_delta_v, disk_u, disk_v) =
+            match &moving_frame {
+                Some(f) => (
+                    f.center,
+                    f.pixel00_loc,
+                    f.pixel_delta_u,
+                    f.pixel_delta_v,
+                    f.defocus_disk_u,
+                    f.defocus_disk_v,
+                ),
+                None => (
+                    self.center,
+                    self.pixel00_loc,
+                    self.pixel_delta_u,
+                    self.pixel_delta_v,
+                    self.defocus_disk_u,
+                    self.defocus_disk_v,
+                ),
+            };
+
         let offset = self.sample_square();
-        let pixel_sample = self.pixel00_loc
-            + ((i as f64 + offset.x()) * self.pixel_delta_u)
-            + ((j as f64 + offset.y()) * self.pixel_delta_v);
+        let pixel_sample = pixel00_loc
+            + ((i as f64 + offset.x()) * pixel_delta_u)
+            + ((j as f64 + offset.y()) * pixel_delta_v);
 
         let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+            center
         } else {
-            self.defocus_disk_sample()
+            let p = self.random_in_unit_disk();
+            center + (p.x() * disk_u) + (p.y() * disk_v)
         };
 
         let ray_direction = pixel_sample - ray_origin;
 
-        Ray::new(ray_origin, ray_direction)
+        Ray::new_with_time(ray_origin, ray_direction, ray_time)
     }
 
     pub fn sample_square(&self) -> Vec3 {
@@ -220,6 +346,16 @@ impl Camera {
         self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
     }
 
+    // Distance from the camera to the first surface along `r`.
+    fn depth(&self, r: &Ray, world: &dyn Hittable) -> f32 {
+        let mut rec = HitRecord::default();
+        if world.hit(r, &Interval::new(0.001, f64::INFINITY), &mut rec) {
+            (rec.t * r.direction().length()) as f32
+        } else {
+            f32::INFINITY
+        }
+    }
+
     pub fn ray_color(&self, r: &Ray, depth: u32, world: &dyn Hittable) -> Color {
         if depth == 0 {
             return Color::new(0.0, 0.0, 0.0);
@@ -229,16 +365,58 @@ impl Camera {
         if world.hit(r, &Interval::new(0.001, f64::INFINITY), &mut rec) {
             let mut scattered = Ray::default();
             let mut attenuation = Color::default();
+            let emitted = rec.mat.emitted(rec.u, rec.v, &rec.p);
 
-            if rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-                return attenuation * self.ray_color(&scattered, depth - 1, world);
+            if !rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
+                return emitted;
             }
 
-            return Color::new(0.0, 0.0, 0.0);
+            // Materials that report a density mix light samples with their own, weighted
+            // by the average of both densities so either choice is unbiased.
+            let material_pdf = rec.mat.scattering_pdf(r, &rec, &scattered);
+            if material_pdf <= 0.0 || self.lights.objects.is_empty() {
+                return emitted + attenuation * self.ray_color(&scattered, depth - 1, world);
+            }
+
+            if util::random_double() < 0.5 {
+                let direction = self.lights.random(&rec.p, r.time());
+                scattered = Ray::new_with_time(rec.p, direction, r.time());
+            }
+            let scattering_pdf = rec.mat.scattering_pdf(r, &rec, &scattered);
+            let pdf = 0.5
+                * self
+                    .lights
+                    .pdf_value(&rec.p, &scattered.direction(), r.time())
+                + 0.5 * scattering_pdf;
+            if scattering_pdf <= 0.0 || pdf <= 0.0 {
+                return emitted;
+            }
+
+            return emitted
+                + scattering_pdf * attenuation * self.ray_color(&scattered, depth - 1, world)
+                    / pdf;
         }
 
-        let unit_direction = r.direction().unit_vector();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        match self.background {
+            Background::Gradient { horizon, zenith } => {
+                let unit_direction = r.direction().unit_vector();
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * horizon + a * zenith
+            }
+            Background::Solid(color) => color,
+        }
     }
 }
+
+// Camera placement derived from the look-at parameters at one instant.
+struct ViewFrame {
+    center: Point3,
+    pixel00_loc: Point3,
+    pixel_delta_u: Vec3,
+    pixel_delta_v: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
+}
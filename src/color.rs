@@ -14,7 +14,7 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
 
 pub fn write_color(
     pixel_color: Color,
-    pixel_data: &mut Vec<u8>,
+    pixel_data: &mut [u8],
     img_width: usize,
     y: usize,
     x: usize,
@@ -33,12 +33,11 @@ pub fn write_color(
     let g_byte = (256.0 * intensity.clamp(g)) as u8;
     let b_byte = (256.0 * intensity.clamp(b)) as u8;
 
-    let index = (y * img_width + x) * 4;
+    let index = (y * img_width + x) * 3;
 
-    if index + 3 < pixel_data.len() {
+    if index + 2 < pixel_data.len() {
         pixel_data[index] = r_byte;
         pixel_data[index + 1] = g_byte;
         pixel_data[index + 2] = b_byte;
-        pixel_data[index + 3] = 255;
     }
 }
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::scene_desc::{CsgOpDesc, ObjectDesc, SceneExporter};
+use crate::util::INFINITY;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference, // Left minus right
+}
+
+impl CsgOp {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Boolean combination of two closed solids. The ray walks both children's surface
+/// crossings in order, tracking whether it is inside each (from which side it crosses),
+/// and reports the first crossing where it enters or leaves the combined solid.
+pub struct Csg {
+    op: CsgOp,
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+}
+
+impl Csg {
+    // Guards against children that are not closed and never settle.
+    const MAX_CROSSINGS: usize = 256;
+
+    pub fn new(op: CsgOp, left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        Csg { op, left, right }
+    }
+
+    pub fn union(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        Csg::new(CsgOp::Union, left, right)
+    }
+
+    pub fn intersection(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        Csg::new(CsgOp::Intersection, left, right)
+    }
+
+    /// `left` with `right` carved out of it.
+    pub fn difference(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        Csg::new(CsgOp::Difference, left, right)
+    }
+
+    pub fn op(&self) -> CsgOp {
+        self.op
+    }
+
+    fn next_crossing(object: &Arc<dyn Hittable>, r: &Ray, after: f64) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        object
+            .hit(r, &Interval::new(after, INFINITY), &mut rec)
+            .then_some(rec)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let mut left = Csg::next_crossing(&self.left, r, ray_t.min);
+        let mut right = Csg::next_crossing(&self.right, r, ray_t.min);
+
+        // Leaving through a back face means the ray started inside.
+        let mut in_left = left.as_ref().is_some_and(|h| !h.front_face);
+        let mut in_right = right.as_ref().is_some_and(|h| !h.front_face);
+        let inside = self.op.inside(in_left, in_right);
+
+        for _ in 0..Self::MAX_CROSSINGS {
+            let from_left = match (&left, &right) {
+                (Some(l), Some(r)) => l.t <= r.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return false,
+            };
+            let crossing = if from_left { &left } else { &right };
+            let crossing = crossing.as_ref().unwrap();
+            if crossing.t >= ray_t.max {
+                return false;
+            }
+
+            if from_left {
+                in_left = crossing.front_face;
+            } else {
+                in_right = crossing.front_face;
+            }
+            let now_inside = self.op.inside(in_left, in_right);
+
+            if now_inside != inside {
+                *rec = crossing.clone();
+                if self.op == CsgOp::Difference && !from_left {
+                    // The carved-out solid bounds the result from its inside, so entering
+                    // it means leaving the result. The normal already faces the ray.
+                    rec.front_face = !rec.front_face;
+                }
+                return true;
+            }
+
+            let t = crossing.t;
+            if from_left {
+                left = Csg::next_crossing(&self.left, r, t);
+            } else {
+                right = Csg::next_crossing(&self.right, r, t);
+            }
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+        match self.op {
+            CsgOp::Union => Aabb::surrounding(&left, &right),
+            CsgOp::Intersection => Aabb::overlap(&left, &right),
+            CsgOp::Difference => left,
+        }
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Csg {
+            op: match self.op {
+                CsgOp::Union => CsgOpDesc::Union,
+                CsgOp::Intersection => CsgOpDesc::Intersection,
+                CsgOp::Difference => CsgOpDesc::Difference,
+            },
+            left: Box::new(exporter.object(self.left.as_ref())?),
+            right: Box::new(exporter.object(self.right.as_ref())?),
+            transform: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    // Unit spheres overlapping between x = -0.5 and x = 0.5.
+    fn solid(op: CsgOp) -> Csg {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = |x| Arc::new(Sphere::new(Point3::new(x, 0.0, 0.0), 1.0, mat.clone()));
+        Csg::new(op, sphere(-0.5), sphere(0.5))
+    }
+
+    fn hit(csg: &Csg, x: f64, dx: f64) -> Option<HitRecord> {
+        let r = Ray::new(Point3::new(x, 0.0, 0.0), Vec3::new(dx, 0.0, 0.0));
+        let mut rec = HitRecord::default();
+        csg.hit(&r, &Interval::new(0.001, INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn hits_the_combined_surface() {
+        let cases = [
+            (CsgOp::Union, -5.0, 1.0, 3.5, true),
+            (CsgOp::Union, -1.0, 1.0, 2.5, false),
+            (CsgOp::Intersection, -5.0, 1.0, 4.5, true),
+            (CsgOp::Intersection, 5.0, -1.0, 4.5, true),
+            (CsgOp::Difference, -5.0, 1.0, 3.5, true),
+            // From the carved side the ray crosses the right sphere's far wall.
+            (CsgOp::Difference, 5.0, -1.0, 5.5, true),
+            (CsgOp::Difference, -1.0, 1.0, 0.5, false),
+        ];
+        for (op, x, dx, t, front_face) in cases {
+            let rec = hit(&solid(op), x, dx).unwrap();
+            assert!(
+                (rec.t - t).abs() < 1e-9,
+                "{:?} from {}: t = {}",
+                op,
+                x,
+                rec.t
+            );
+            assert_eq!(rec.front_face, front_face, "{:?} from {}", op, x);
+            // The normal always faces the ray.
+            assert!(rec.normal.x() * dx < 0.0);
+        }
+        assert!(hit(&solid(CsgOp::Intersection), -5.0, -1.0).is_none());
+    }
+}
@@ -0,0 +1,506 @@
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::ray::Ray;
+use crate::util::random_double;
+use crate::vec3::{orthonormal_basis, Point3, Vec3};
+
+/// Cross-section of a curve.
+#[derive(Copy, Clone, Debug)]
+pub enum CurveShape {
+    /// Flat strip that always faces the ray, the usual choice for thin hair.
+    Flat,
+    /// Flat strip with a fixed orientation, given by normals at the two ends (grass).
+    Ribbon([Vec3; 2]),
+    /// Round tube.
+    Tube,
+}
+
+/// Cubic Bézier curve whose width varies linearly from the first to the last control
+/// point. It is intersected by recursively splitting it in a frame where the ray runs
+/// down the z axis until each piece is close to a straight segment. u runs along the
+/// curve and v across it.
+pub struct Curve {
+    points: [Point3; 4],
+    widths: [f64; 2],
+    shape: CurveShape,
+    mat: Arc<dyn Material>,
+}
+
+impl Curve {
+    const MAX_DEPTH: i32 = 10;
+
+    pub fn new(points: [Point3; 4], widths: [f64; 2], mat: Arc<dyn Material>) -> Self {
+        Curve {
+            points,
+            widths: [widths[0].abs(), widths[1].abs()],
+            shape: CurveShape::Flat,
+            mat,
+        }
+    }
+
+    pub fn with_shape(mut self, shape: CurveShape) -> Self {
+        if let CurveShape::Ribbon([n0, n1]) = shape {
+            self.shape = CurveShape::Ribbon([n0.unit_vector(), n1.unit_vector()]);
+        } else {
+            self.shape = shape;
+        }
+        self
+    }
+
+    pub fn points(&self) -> &[Point3; 4] {
+        &self.points
+    }
+
+    pub fn widths(&self) -> [f64; 2] {
+        self.widths
+    }
+
+    pub fn shape(&self) -> CurveShape {
+        self.shape
+    }
+
+    pub fn point_at(&self, u: f64) -> Point3 {
+        bezier(&self.points, u)
+    }
+
+    pub fn tangent_at(&self, u: f64) -> Vec3 {
+        bezier_derivative(&self.points, u)
+    }
+
+    fn width_at(&self, u: f64) -> f64 {
+        self.widths[0] + u * (self.widths[1] - self.widths[0])
+    }
+
+    // Subdivision depth after which a piece is flat to within a fraction of the width.
+    fn max_depth(&self, cp: &[Vec3; 4]) -> i32 {
+        let mut l0: f64 = 0.0;
+        for i in 0..2 {
+            let second = cp[i] - 2.0 * cp[i + 1] + cp[i + 2];
+            l0 = l0.max(second.x().abs().max(second.y().abs()).max(second.z().abs()));
+        }
+        let eps = self.widths[0].max(self.widths[1]) * 0.05;
+        if eps <= 0.0 {
+            return 0;
+        }
+        let ratio = std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps);
+        let log2 = if ratio < 1.0 {
+            0
+        } else {
+            ratio.log2().floor() as i32
+        };
+        (log2 / 2).clamp(0, Self::MAX_DEPTH)
+    }
+
+    // Nearest crossing of the ray-space curve piece `cp`, spanning [u0, u1] of the
+    // curve, as (z, u, v). `z_max` shrinks as closer crossings are found.
+    fn intersect(
+        &self,
+        ray: &RayFrame,
+        cp: &[Vec3; 4],
+        (u0, u1): (f64, f64),
+        depth: i32,
+        z_max: &mut f64,
+    ) -> Option<(f64, f64, f64)> {
+        let half_width = 0.5 * self.width_at(u0).max(self.width_at(u1));
+        let inf = f64::INFINITY;
+        let (lo, hi) = cp.iter().fold(
+            (Vec3::new(inf, inf, inf), Vec3::new(-inf, -inf, -inf)),
+            |(lo, hi), p| {
+                (
+                    Vec3::new(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z())),
+                    Vec3::new(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z())),
+                )
+            },
+        );
+        if lo.x() - half_width > 0.0
+            || hi.x() + half_width < 0.0
+            || lo.y() - half_width > 0.0
+            || hi.y() + half_width < 0.0
+            || lo.z() - half_width > *z_max
+            || hi.z() + half_width < ray.z_min
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let (left, right) = split_bezier(cp);
+            let mid = 0.5 * (u0 + u1);
+            let near = self.intersect(ray, &left, (u0, mid), depth - 1, z_max);
+            let far = self.intersect(ray, &right, (mid, u1), depth - 1, z_max);
+            return far.or(near);
+        }
+
+        // The ray must pass between the perpendiculars at both ends of the segment.
+        let edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        if edge < 0.0 {
+            return None;
+        }
+        let edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if edge < 0.0 {
+            return None;
+        }
+
+        let (sx, sy) = (cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y());
+        let denom = sx * sx + sy * sy;
+        if denom == 0.0 {
+            return None;
+        }
+        let w = ((-cp[0].x() * sx - cp[0].y() * sy) / denom).clamp(0.0, 1.0);
+        let u = (u0 + w * (u1 - u0)).clamp(u0, u1);
+        let mut width = self.width_at(u);
+        if let CurveShape::Ribbon([n0, n1]) = self.shape {
+            let normal = (n0 + u * (n1 - n0)).unit_vector();
+            width *= normal.dot(&ray.w).abs();
+        }
+
+        let pc = bezier(cp, w);
+        let dist_squared = pc.x() * pc.x() + pc.y() * pc.y();
+        if dist_squared > 0.25 * width * width {
+            return None;
+        }
+        let mut z = pc.z();
+        if let CurveShape::Tube = self.shape {
+            // Move from the curve's axis out to the near side of the tube.
+            z -= (0.25 * width * width - dist_squared).max(0.0).sqrt();
+        }
+        if z <= ray.z_min || z > *z_max {
+            return None;
+        }
+
+        let dist = dist_squared.sqrt();
+        let dpdw = bezier_derivative(cp, w);
+        let side = dpdw.x() * -pc.y() + pc.x() * dpdw.y();
+        let v = if side > 0.0 {
+            0.5 + dist / width
+        } else {
+            0.5 - dist / width
+        };
+        *z_max = z;
+        Some((z, u, v))
+    }
+}
+
+// Orthonormal frame with the ray along +w, and the nearest distance along it to accept.
+struct RayFrame {
+    origin: Point3,
+    a: Vec3,
+    b: Vec3,
+    w: Vec3,
+    z_min: f64,
+}
+
+impl RayFrame {
+    fn to_local(&self, p: &Point3) -> Vec3 {
+        let d = *p - self.origin;
+        Vec3::new(d.dot(&self.a), d.dot(&self.b), d.dot(&self.w))
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let length = r.direction().length();
+        if length == 0.0 {
+            return false;
+        }
+        let w = r.direction() / length;
+        let (a, b) = orthonormal_basis(&w);
+        let frame = RayFrame {
+            origin: r.origin(),
+            a,
+            b,
+            w,
+            z_min: ray_t.min * length,
+        };
+        let cp = self.points.map(|p| frame.to_local(&p));
+
+        let mut z_max = ray_t.max * length;
+        let depth = self.max_depth(&cp);
+        let Some((z, u, v)) = self.intersect(&frame, &cp, (0.0, 1.0), depth, &mut z_max) else {
+            return false;
+        };
+        let t = z / length;
+        if !ray_t.surronds(t) {
+            return false;
+        }
+
+        let p = r.at(t);
+        if self.mat.masked(u, v, &p) {
+            return false;
+        }
+
+        let dpdu = self.tangent_at(u);
+        let facing = -w - dpdu * (-w).dot(&dpdu) / dpdu.length_squared().max(1e-300);
+        let outward_normal = match self.shape {
+            CurveShape::Flat => facing,
+            CurveShape::Ribbon([n0, n1]) => n0 + u * (n1 - n0),
+            CurveShape::Tube => {
+                let radial = p - self.point_at(u);
+                let radial = radial - dpdu * radial.dot(&dpdu) / dpdu.length_squared().max(1e-300);
+                if radial.near_zero() {
+                    facing
+                } else {
+                    radial
+                }
+            }
+        };
+        if outward_normal.near_zero() {
+            return false;
+        }
+        let outward_normal = outward_normal.unit_vector();
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        rec.set_normal_face(r, &outward_normal);
+        rec.set_shading_frame(r, &outward_normal, &dpdu);
+        rec.mat = self.mat.clone();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let pad = 0.5 * self.widths[0].max(self.widths[1]);
+        let pad = Vec3::new(pad, pad, pad);
+        self.points.iter().fold(Aabb::EMPTY, |bbox, p| {
+            Aabb::surrounding(&bbox, &Aabb::from_points(*p - pad, *p + pad))
+        })
+    }
+}
+
+fn bezier(cp: &[Vec3; 4], u: f64) -> Vec3 {
+    let lerp = |a: Vec3, b: Vec3| a + u * (b - a);
+    let (a, b, c) = (lerp(cp[0], cp[1]), lerp(cp[1], cp[2]), lerp(cp[2], cp[3]));
+    let (d, e) = (lerp(a, b), lerp(b, c));
+    lerp(d, e)
+}
+
+fn bezier_derivative(cp: &[Vec3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    3.0 * (s * s * (cp[1] - cp[0]) + 2.0 * u * s * (cp[2] - cp[1]) + u * u * (cp[3] - cp[2]))
+}
+
+fn split_bezier(cp: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let mid = |a: Vec3, b: Vec3| 0.5 * (a + b);
+    let (a, b, c) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let f = mid(d, e);
+    ([cp[0], a, d, f], [f, e, c, cp[3]])
+}
+
+/// Loads curves from a text file with one curve per line: the twelve coordinates of the
+/// four control points, then the root width and optionally the tip width (defaulting to
+/// the root width). Blank lines and lines starting with `#` are skipped.
+pub fn load_curves(path: &str, mat: Arc<dyn Material>) -> io::Result<Vec<Curve>> {
+    let text = fs::read_to_string(path)?;
+    let mut curves = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path, number + 1, message),
+            )
+        };
+        let values = line
+            .split_whitespace()
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| invalid(format!("invalid number '{}'", token)))
+            })
+            .collect::<io::Result<Vec<f64>>>()?;
+        if values.len() != 13 && values.len() != 14 {
+            return Err(invalid(format!(
+                "expected 13 or 14 numbers, found {}",
+                values.len()
+            )));
+        }
+
+        let point = |i: usize| Point3::new(values[3 * i], values[3 * i + 1], values[3 * i + 2]);
+        let root = values[12];
+        let tip = values.get(13).copied().unwrap_or(root);
+        curves.push(Curve::new(
+            [point(0), point(1), point(2), point(3)],
+            [root, tip],
+            mat.clone(),
+        ));
+    }
+
+    Ok(curves)
+}
+
+/// How `grow_on_mesh` shapes the strands.
+#[derive(Copy, Clone, Debug)]
+pub struct GrowthParams {
+    pub count: usize,
+    pub length: f64,
+    pub root_width: f64,
+    pub tip_width: f64,
+    pub droop: f64,  // How far tips sag along -y, as a fraction of the length
+    pub jitter: f64, // Random bend of the middle control points, as a fraction of the length
+}
+
+impl Default for GrowthParams {
+    fn default() -> Self {
+        GrowthParams {
+            count: 1000,
+            length: 0.2,
+            root_width: 0.004,
+            tip_width: 0.001,
+            droop: 0.3,
+            jitter: 0.1,
+        }
+    }
+}
+
+/// Grows strands from random points on `mesh`, spread evenly by area, along the
+/// interpolated surface normal.
+pub fn grow_on_mesh(
+    mesh: &TriangleMesh,
+    params: &GrowthParams,
+    mat: Arc<dyn Material>,
+) -> Vec<Curve> {
+    let positions = mesh.positions();
+    let normals = mesh.normals();
+    let faces = mesh.indices();
+    if faces.is_empty() || params.count == 0 {
+        return Vec::new();
+    }
+
+    let mut cumulative_area = Vec::with_capacity(faces.len());
+    let mut total = 0.0;
+    for &[a, b, c] in faces {
+        total += 0.5
+            * (positions[b] - positions[a])
+                .cross(&(positions[c] - positions[a]))
+                .length();
+        cumulative_area.push(total);
+    }
+    if total <= 0.0 {
+        return Vec::new();
+    }
+
+    let jitter = |scale: f64| {
+        scale
+            * Vec3::new(
+                random_double() - 0.5,
+                random_double() - 0.5,
+                random_double() - 0.5,
+            )
+    };
+
+    (0..params.count)
+        .map(|_| {
+            let target = random_double() * total;
+            let face = cumulative_area
+                .partition_point(|&area| area < target)
+                .min(faces.len() - 1);
+            let [a, b, c] = faces[face];
+
+            let (mut s, mut t) = (random_double(), random_double());
+            if s + t > 1.0 {
+                (s, t) = (1.0 - s, 1.0 - t);
+            }
+            let root = positions[a]
+                + s * (positions[b] - positions[a])
+                + t * (positions[c] - positions[a]);
+            let normal = if normals.is_empty() {
+                (positions[b] - positions[a]).cross(&(positions[c] - positions[a]))
+            } else {
+                (1.0 - s - t) * normals[a] + s * normals[b] + t * normals[c]
+            }
+            .unit_vector();
+
+            let length = params.length;
+            let sag = Vec3::new(0.0, -params.droop * length, 0.0);
+            let spread = params.jitter * length;
+            let p1 = root + normal * (length / 3.0) + jitter(spread);
+            let p2 = root + normal * (2.0 * length / 3.0) + sag * 0.5 + jitter(spread);
+            let p3 = root + normal * length + sag + jitter(spread);
+            Curve::new(
+                [root, p1, p2, p3],
+                [params.root_width, params.tip_width],
+                mat.clone(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    // Straight curve along x from -1 to 1, tapering from `widths[0]` to `widths[1]`.
+    fn straight(widths: [f64; 2], shape: CurveShape) -> Curve {
+        let points = [-1.0, -1.0 / 3.0, 1.0 / 3.0, 1.0].map(|x| Point3::new(x, 0.0, 0.0));
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Curve::new(points, widths, mat).with_shape(shape)
+    }
+
+    fn hit_down(curve: &Curve, x: f64, y: f64) -> Option<HitRecord> {
+        let r = Ray::new(Point3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        let mut rec = HitRecord::default();
+        curve
+            .hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn hits_flat_and_tube_cross_sections() {
+        let flat = straight([0.2, 0.2], CurveShape::Flat);
+        let rec = hit_down(&flat, 0.3, 0.0).unwrap();
+        assert!((rec.t - 2.5).abs() < 1e-9);
+        assert!((rec.u - 0.65).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+        assert!((rec.normal.z() - 1.0).abs() < 1e-9);
+        // v runs across the strip, a quarter of the width off center here.
+        let v = hit_down(&flat, 0.3, 0.05).unwrap().v;
+        assert!((v - 0.5).abs() > 0.24 && (v - 0.5).abs() < 0.26);
+        assert!(hit_down(&flat, 0.3, 0.11).is_none());
+        assert!(hit_down(&flat, 1.2, 0.0).is_none());
+
+        // A tube is hit on its near side, 0.1 above the axis.
+        let tube = straight([0.2, 0.2], CurveShape::Tube);
+        let rec = hit_down(&tube, 0.3, 0.0).unwrap();
+        assert!((rec.t - 2.45).abs() < 1e-9);
+        assert!((rec.normal.z() - 1.0).abs() < 1e-9);
+
+        // A tapered curve thins toward its tip.
+        let tapered = straight([0.2, 0.0], CurveShape::Flat);
+        assert!(hit_down(&tapered, -0.9, 0.08).is_some());
+        assert!(hit_down(&tapered, 0.9, 0.08).is_none());
+    }
+
+    #[test]
+    fn hits_land_on_bent_curves() {
+        let points = [
+            Point3::new(-1.0, 0.0, 0.0),
+            Point3::new(-0.5, 1.0, 0.0),
+            Point3::new(0.5, 1.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+        ];
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let curve = Curve::new(points, [0.05, 0.05], mat);
+        for u in [0.1, 0.3, 0.5, 0.8] {
+            let on_curve = curve.point_at(u);
+            let rec = hit_down(&curve, on_curve.x(), on_curve.y()).unwrap();
+            assert!((rec.u - u).abs() < 0.02, "u = {} for {}", rec.u, u);
+            assert!((rec.p - on_curve).length() < 0.025);
+        }
+        assert!(hit_down(&curve, 0.0, 0.0).is_none());
+    }
+}
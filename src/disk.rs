@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::util::{random_double, PI};
+use crate::vec3::{orthonormal_basis, Point3, Vec3};
+
+/// Flat disk facing along `normal`. The texture u coordinate runs around the disk and
+/// v from the rim (0) to the center (1).
+pub struct Disk {
+    center: Point3,
+    normal: Vec3,
+    radius: f64,
+    tangent: Vec3, // In-plane axis where u = 0
+    bitangent: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        let normal = normal.unit_vector();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Disk {
+            center,
+            normal,
+            radius: radius.abs(),
+            tangent,
+            bitangent,
+            mat,
+        }
+    }
+
+    pub fn center(&self) -> Point3 {
+        self.center
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = self.normal.dot(&(self.center - r.origin())) / denom;
+        if !ray_t.surronds(t) {
+            return false;
+        }
+
+        let p = r.at(t);
+        let offset = p - self.center;
+        let dist_squared = offset.length_squared();
+        if dist_squared > self.radius * self.radius {
+            return false;
+        }
+
+        let x = offset.dot(&self.tangent);
+        let y = offset.dot(&self.bitangent);
+        let mut phi = y.atan2(x);
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+        let u = phi / (2.0 * PI);
+        let v = 1.0 - dist_squared.sqrt() / self.radius;
+        if self.mat.masked(u, v, &p) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        rec.mat = self.mat.clone();
+        rec.set_normal_face(r, &self.normal);
+        let around = -phi.sin() * self.tangent + phi.cos() * self.bitangent;
+        rec.set_shading_frame(r, &self.normal, &around);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Extent of the rim along each axis is radius * sin(angle between axis and normal).
+        let n = self.normal;
+        let extent = Vec3::new(
+            self.radius * (1.0 - n.x() * n.x()).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y() * n.y()).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z() * n.z()).max(0.0).sqrt(),
+        );
+        Aabb::from_points(self.center - extent, self.center + extent)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(
+            &Ray::new(*origin, *direction),
+            &Interval::new(0.001, f64::INFINITY),
+            &mut rec,
+        ) {
+            return 0.0;
+        }
+
+        let area = PI * self.radius * self.radius;
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let r = self.radius * random_double().sqrt();
+        let phi = 2.0 * PI * random_double();
+        let p = self.center + (r * phi.cos()) * self.tangent + (r * phi.sin()) * self.bitangent;
+        p - *origin
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Disk {
+            center: self.center.into(),
+            normal: self.normal.into(),
+            radius: self.radius,
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
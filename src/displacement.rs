@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::mesh::TriangleMesh;
+use crate::texture::Texture;
+use crate::vec3::{Point3, Vec3};
+
+// Deepest tessellation level; each level quadruples the triangle count.
+const MAX_LEVELS: usize = 8;
+
+/// Displacement modifier for triangle meshes, applied once at load time. The mesh is
+/// tessellated until no edge is longer than `max_edge_length`, then every vertex is moved
+/// along its normal by `(luminance - midlevel) * scale`, with the luminance read from
+/// `texture` at the vertex's uv and original position. Vertices that share a position,
+/// as along uv seams and hard edges, move together by their average normal and height so
+/// the surface does not crack. Offsets are clamped to `bound`, so the displaced mesh
+/// always fits in `bounding_box`. Smooth normals are recomputed from the displaced
+/// surface.
+pub struct Displacement {
+    texture: Arc<dyn Texture>,
+    scale: f64,
+    midlevel: f64,
+    max_edge_length: f64,
+    bound: f64,
+}
+
+impl Displacement {
+    /// Displacement of up to `scale` outward for white and none for black.
+    pub fn new(texture: Arc<dyn Texture>, scale: f64) -> Self {
+        Displacement {
+            texture,
+            scale,
+            midlevel: 0.0,
+            max_edge_length: f64::INFINITY,
+            bound: scale.abs(),
+        }
+    }
+
+    /// Luminance that leaves the surface in place; darker values push inward.
+    pub fn with_midlevel(mut self, midlevel: f64) -> Self {
+        self.midlevel = midlevel;
+        self.bound = self.scale.abs() * midlevel.max(1.0 - midlevel).max(0.0);
+        self
+    }
+
+    pub fn with_max_edge_length(mut self, max_edge_length: f64) -> Self {
+        self.max_edge_length = max_edge_length;
+        self
+    }
+
+    /// Largest offset allowed along the normal. Defaults to the most a texture with
+    /// values in [0, 1] can displace.
+    pub fn with_bound(mut self, bound: f64) -> Self {
+        self.bound = bound.abs();
+        self
+    }
+
+    pub fn bound(&self) -> f64 {
+        self.bound
+    }
+
+    /// Box that holds `mesh` once displaced: its own box grown by the bound on each side.
+    pub fn bounding_box(&self, mesh: &TriangleMesh) -> Aabb {
+        let inf = f64::INFINITY;
+        let (lo, hi) = mesh.positions().iter().fold(
+            (Point3::new(inf, inf, inf), Point3::new(-inf, -inf, -inf)),
+            |(lo, hi), p| (min(lo, *p), max(hi, *p)),
+        );
+        if lo.x() > hi.x() {
+            return Aabb::EMPTY;
+        }
+        let pad = Vec3::new(self.bound, self.bound, self.bound);
+        Aabb::from_points(lo - pad, hi + pad)
+    }
+
+    /// Tessellated and displaced copy of `mesh`, with the same material.
+    pub fn apply(&self, mesh: &TriangleMesh) -> TriangleMesh {
+        let mut positions = mesh.positions().to_vec();
+        let mut normals = if mesh.normals().is_empty() {
+            smooth_normals(&positions, mesh.indices())
+        } else {
+            mesh.normals().to_vec()
+        };
+        let has_uvs = !mesh.uvs().is_empty();
+        let mut uvs = if has_uvs {
+            mesh.uvs().to_vec()
+        } else {
+            vec![(0.0, 0.0); positions.len()]
+        };
+        let mut indices = mesh.indices().to_vec();
+
+        for _ in 0..self.levels(&positions, &indices) {
+            indices = split(&mut positions, &mut normals, &mut uvs, &indices);
+        }
+
+        let (welded, count) = weld(&positions);
+        let mut directions = vec![Vec3::zero(); count];
+        let mut heights = vec![(0.0, 0); count];
+        for (i, &w) in welded.iter().enumerate() {
+            let (u, v) = uvs[i];
+            let height =
+                (luminance(&self.texture.value(u, v, &positions[i])) - self.midlevel) * self.scale;
+            directions[w] = directions[w] + normals[i].unit_vector();
+            heights[w] = (heights[w].0 + height, heights[w].1 + 1);
+        }
+
+        let displaced: Vec<Point3> = positions
+            .iter()
+            .zip(&welded)
+            .map(|(p, &w)| {
+                let (sum, samples) = heights[w];
+                let height = (sum / samples as f64).clamp(-self.bound, self.bound);
+                if directions[w].near_zero() {
+                    *p
+                } else {
+                    *p + height * directions[w].unit_vector()
+                }
+            })
+            .collect();
+        let normals = smooth_normals(&displaced, &indices);
+
+        if !has_uvs {
+            uvs.clear();
+        }
+        TriangleMesh::new(displaced, normals, uvs, indices, mesh.material().clone())
+    }
+
+    // Uniform levels needed to bring the longest edge under `max_edge_length`.
+    fn levels(&self, positions: &[Point3], indices: &[[usize; 3]]) -> usize {
+        let longest = indices
+            .iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .map(|(a, b)| (positions[a] - positions[b]).length())
+            .fold(0.0, f64::max);
+        if longest <= self.max_edge_length {
+            return 0;
+        }
+        ((longest / self.max_edge_length).log2().ceil() as usize).min(MAX_LEVELS)
+    }
+}
+
+// Splits every triangle into four at its edge midpoints. Midpoints are shared between
+// neighbouring triangles so the mesh stays watertight.
+fn split(
+    positions: &mut Vec<Point3>,
+    normals: &mut Vec<Vec3>,
+    uvs: &mut Vec<(f64, f64)>,
+    indices: &[[usize; 3]],
+) -> Vec<[usize; 3]> {
+    let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut midpoint = |a: usize, b: usize| {
+        *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            positions.push(0.5 * (positions[a] + positions[b]));
+            normals.push((normals[a] + normals[b]).unit_vector());
+            uvs.push((0.5 * (uvs[a].0 + uvs[b].0), 0.5 * (uvs[a].1 + uvs[b].1)));
+            positions.len() - 1
+        })
+    };
+
+    let mut split = Vec::with_capacity(4 * indices.len());
+    for &[a, b, c] in indices {
+        let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+        split.push([a, ab, ca]);
+        split.push([ab, b, bc]);
+        split.push([ca, bc, c]);
+        split.push([ab, bc, ca]);
+    }
+    split
+}
+
+// Area-weighted vertex normals. Vertices at the same position share their normal, so
+// seams where uvs are split stay smooth.
+fn smooth_normals(positions: &[Point3], indices: &[[usize; 3]]) -> Vec<Vec3> {
+    let (welded, count) = weld(positions);
+    let mut sums = vec![Vec3::zero(); count];
+    for &[a, b, c] in indices {
+        let normal = (positions[b] - positions[a]).cross(&(positions[c] - positions[a]));
+        for i in [a, b, c] {
+            sums[welded[i]] = sums[welded[i]] + normal;
+        }
+    }
+
+    welded
+        .iter()
+        .map(|&w| {
+            if sums[w].near_zero() {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                sums[w]
+            }
+        })
+        .collect()
+}
+
+// Numbers the distinct positions, returning each vertex's number and how many there are.
+// Positions match bit for bit, which shared edge midpoints do since they are computed
+// the same way from the same endpoints.
+fn weld(positions: &[Point3]) -> (Vec<usize>, usize) {
+    let mut ids: HashMap<[u64; 3], usize> = HashMap::new();
+    let welded = positions
+        .iter()
+        .map(|p| {
+            let next = ids.len();
+            *ids.entry([p.x().to_bits(), p.y().to_bits(), p.z().to_bits()])
+                .or_insert(next)
+        })
+        .collect();
+    (welded, ids.len())
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.212671 * c.x() + 0.715160 * c.y() + 0.072169 * c.z()
+}
+
+fn min(a: Point3, b: Point3) -> Point3 {
+    Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()))
+}
+
+fn max(a: Point3, b: Point3) -> Point3 {
+    Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    // Brightness that follows u, so the two sides of a uv seam disagree.
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: &Point3) -> Color {
+            Color::new(u, u, u)
+        }
+    }
+
+    #[test]
+    fn seams_move_together() {
+        // Two triangles that share an edge but not its vertices, with a hard crease and
+        // a uv seam along it.
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 0.5, 1.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        let normals = vec![
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-0.5, 1.0, 0.0),
+            Vec3::new(-0.5, 1.0, 0.0),
+            Vec3::new(-0.5, 1.0, 0.0),
+        ];
+        let uvs = vec![
+            (0.0, 0.0),
+            (0.2, 0.0),
+            (0.0, 0.2),
+            (0.8, 0.0),
+            (1.0, 1.0),
+            (0.8, 1.0),
+        ];
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mesh = TriangleMesh::new(positions, normals, uvs, vec![[0, 1, 2], [3, 4, 5]], mat);
+
+        let displaced = Displacement::new(Arc::new(Ramp), 0.3)
+            .with_max_edge_length(0.4)
+            .apply(&mesh);
+
+        // A zero scale tessellates the same way without moving anything. Every point of
+        // the shared edge x + z = 1 must land in one place, whichever triangle it is in.
+        let original = Displacement::new(Arc::new(Ramp), 0.0)
+            .with_max_edge_length(0.4)
+            .apply(&mesh);
+        let on_edge = |p: &Point3| (p.x() + p.z() - 1.0).abs() < 1e-9 && p.y().abs() < 1e-9;
+        let mut landed: HashMap<(i64, i64), Point3> = HashMap::new();
+        for (before, after) in original.positions().iter().zip(displaced.positions()) {
+            if on_edge(before) {
+                let key = (
+                    (before.x() * 1e6).round() as i64,
+                    (before.z() * 1e6).round() as i64,
+                );
+                let other = landed.entry(key).or_insert(*after);
+                assert!(
+                    (*other - *after).length() < 1e-12,
+                    "{:?} != {:?}",
+                    other,
+                    after
+                );
+            }
+        }
+        assert!(landed.len() >= 5);
+    }
+}
@@ -0,0 +1,115 @@
+use image::RgbImage;
+
+use crate::color::{write_color, Color};
+
+/// Radiance of a finished render in floating point, with the distance to the first
+/// surface seen through each pixel.
+pub struct Film {
+    width: usize,
+    height: usize,
+    color: Vec<[f32; 3]>, // Linear RGB, rows top to bottom
+    depth: Vec<f32>,      // Infinite where a pixel sees the background
+}
+
+impl Film {
+    /// A black film with every pixel at infinite depth.
+    pub fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            color: vec![[0.0; 3]; width * height],
+            depth: vec![f32::INFINITY; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn color(&self, x: usize, y: usize) -> Color {
+        let [r, g, b] = self.color[y * self.width + x];
+        Color::new(r as f64, g as f64, b as f64)
+    }
+
+    pub fn set_color(&mut self, x: usize, y: usize, color: Color) {
+        self.color[y * self.width + x] = [color.x() as f32, color.y() as f32, color.z() as f32];
+    }
+
+    pub fn depth(&self, x: usize, y: usize) -> f32 {
+        self.depth[y * self.width + x]
+    }
+
+    pub fn set_depth(&mut self, x: usize, y: usize, depth: f32) {
+        self.depth[y * self.width + x] = depth;
+    }
+
+    /// Linear RGB triples, rows top to bottom.
+    pub fn pixels(&self) -> &[[f32; 3]] {
+        &self.color
+    }
+
+    /// Depth of each pixel, rows top to bottom.
+    pub fn depths(&self) -> &[f32] {
+        &self.depth
+    }
+
+    /// 8-bit display view of the film through `tone_map`.
+    pub fn to_rgb8(&self, tone_map: &ToneMap) -> RgbImage {
+        let mut data = vec![0u8; self.width * self.height * 3];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                write_color(
+                    tone_map.apply(self.color(x, y)),
+                    &mut data,
+                    self.width,
+                    y,
+                    x,
+                );
+            }
+        }
+        RgbImage::from_raw(self.width as u32, self.height as u32, data).unwrap()
+    }
+}
+
+/// Curve that compresses radiance into the displayable [0, 1] range.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Values above 1 are clipped.
+    #[default]
+    Clamp,
+    /// `x / (1 + x)` per channel.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+/// How the 8-bit view is made from the film: an exposure scale, then a curve, then the
+/// usual gamma.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ToneMap {
+    pub exposure: f64, // In stops; each one doubles the brightness
+    pub operator: ToneMapOperator,
+}
+
+impl ToneMap {
+    pub fn new(exposure: f64, operator: ToneMapOperator) -> Self {
+        ToneMap { exposure, operator }
+    }
+
+    /// Maps linear radiance to linear display values, before gamma.
+    pub fn apply(&self, color: Color) -> Color {
+        let color = 2f64.powf(self.exposure) * color;
+        let curve = |x: f64| match self.operator {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        };
+        Color::new(curve(color.x()), curve(color.y()), curve(color.z()))
+    }
+}
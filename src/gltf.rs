@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::gltf::camera::Projection;
+use ::gltf::khr_lights_punctual::Kind;
+use ::gltf::material::AlphaMode;
+use ::gltf::mesh::Mode;
+use ::gltf::texture::WrappingMode;
+use image::DynamicImage;
+
+use crate::aabb::Aabb;
+use crate::bvh::BvhAccel;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::disk::Disk;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::interval::Interval;
+use crate::material::{AlphaMasked, Dielectric, DiffuseLight, Material, MetallicRoughness};
+use crate::mesh::TriangleMesh;
+use crate::normal_map::NormalMapped;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::sphere::Sphere;
+use crate::texture::{ImageTexture, ScaledTexture, SolidColor, Texture};
+use crate::transform::{Mat4, Transform};
+use crate::util::PI;
+use crate::vec3::{Point3, Vec3};
+
+/// Objects and cameras of a glTF scene.
+pub struct GltfScene {
+    pub world: HittableList,
+    pub cameras: Vec<GltfCamera>,
+    pub lights: HittableList, // The punctual lights' emitters, also in `world`
+}
+
+/// A perspective camera placed by its glTF node.
+#[derive(Copy, Clone, Debug)]
+pub struct GltfCamera {
+    pub vfov: f64, // Vertical field of view in degrees
+    pub aspect_ratio: Option<f64>,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+}
+
+impl GltfCamera {
+    /// Points `camera` like this one. Image size, sampling and focus are left alone.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.vfov = self.vfov;
+        camera.lookfrom = self.lookfrom;
+        camera.lookat = self.lookat;
+        camera.vup = self.vup;
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+    }
+}
+
+/// The objects of a glTF file placed in a world as one node. Saved scenes refer to the
+/// file rather than writing the objects out.
+pub struct GltfModel {
+    objects: BvhAccel,
+    path: String,
+}
+
+impl GltfModel {
+    /// Wraps the `world` of the `GltfScene` loaded from `file_path`.
+    pub fn new(world: HittableList, file_path: &str) -> Self {
+        GltfModel {
+            objects: BvhAccel::from_list(world),
+            path: file_path.to_string(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Hittable for GltfModel {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        self.objects.hit(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.bounding_box()
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Gltf {
+            path: exporter.relative(&self.path),
+            transform: None,
+        })
+    }
+}
+
+// Punctual lights become small spheres of this fraction of the scene's radius, and
+// directional lights become disks of the scene's radius this many radii away.
+const LIGHT_RADIUS_FRACTION: f64 = 0.01;
+const SUN_DISTANCE: f64 = 100.0;
+
+/// Loads a glTF 2.0 file (`.gltf` with external or embedded buffers, or `.glb`).
+///
+/// Each mesh primitive becomes a `TriangleMesh`, shared by every node that uses it
+/// through an `Instance` carrying the node's world transform. Metallic-roughness
+/// materials map to `MetallicRoughness`, with normal maps through `NormalMapped`, alpha
+/// masks through `AlphaMasked` and transmissive materials to `Dielectric`. Perspective
+/// cameras are returned in scene order. `KHR_lights_punctual` point and spot lights
+/// become small emissive spheres (spot cones are ignored) and directional lights distant
+/// emissive disks, since the renderer only knows area lights.
+pub fn load_gltf(file_path: &str) -> io::Result<GltfScene> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", file_path, message),
+        )
+    };
+    let base = Path::new(file_path).parent().unwrap_or(Path::new(""));
+
+    let gltf = ::gltf::Gltf::open(file_path).map_err(|e| invalid(e.to_string()))?;
+    let mut blob = gltf.blob.clone();
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            ::gltf::buffer::Source::Bin => blob
+                .take()
+                .ok_or_else(|| invalid("missing binary chunk".to_string()))?,
+            ::gltf::buffer::Source::Uri(uri) => read_uri(base, uri).map_err(&invalid)?,
+        };
+        if data.len() < buffer.length() {
+            return Err(invalid(format!("buffer {} is too short", buffer.index())));
+        }
+        buffers.push(data);
+    }
+
+    let mut images = Vec::new();
+    for image in gltf.images() {
+        let bytes = match image.source() {
+            ::gltf::image::Source::View { view, .. } => {
+                let start = view.offset();
+                buffers[view.buffer().index()]
+                    .get(start..start.saturating_add(view.length()))
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "buffer view {} of image {} runs past the end of its buffer",
+                            view.index(),
+                            image.index()
+                        ))
+                    })?
+                    .to_vec()
+            }
+            ::gltf::image::Source::Uri { uri, .. } => read_uri(base, uri).map_err(&invalid)?,
+        };
+        let decoded = image::load_from_memory(&bytes)
+            .map_err(|e| invalid(format!("image {}: {}", image.index(), e)))?;
+        images.push(decoded);
+    }
+
+    let materials: Vec<Arc<dyn Material>> = gltf
+        .materials()
+        .map(|m| convert_material(&m, &images))
+        .collect();
+    let default_material: Arc<dyn Material> = Arc::new(MetallicRoughness::new(
+        Arc::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))),
+        1.0,
+        1.0,
+    ));
+
+    let mut meshes: HashMap<usize, Vec<Arc<dyn Hittable>>> = HashMap::new();
+    for mesh in gltf.meshes() {
+        let mut primitives: Vec<Arc<dyn Hittable>> = Vec::new();
+        for primitive in mesh.primitives() {
+            let material = match primitive.material().index() {
+                Some(i) => materials[i].clone(),
+                None => default_material.clone(),
+            };
+            if let Some(triangles) = convert_primitive(&primitive, &buffers, material) {
+                primitives.push(Arc::new(triangles));
+            }
+        }
+        meshes.insert(mesh.index(), primitives);
+    }
+
+    let mut scene = GltfScene {
+        world: HittableList::new(),
+        cameras: Vec::new(),
+        lights: HittableList::new(),
+    };
+    let mut lights = Vec::new();
+    if let Some(root) = gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        let mut stack: Vec<_> = root.nodes().map(|n| (n, Mat4::IDENTITY)).collect();
+        stack.reverse();
+        while let Some((node, parent)) = stack.pop() {
+            let world = parent * node_matrix(&node);
+            let transform = Transform::from_matrix(world);
+
+            if let (Some(mesh), Some(transform)) = (node.mesh(), transform) {
+                for primitive in &meshes[&mesh.index()] {
+                    scene
+                        .world
+                        .add(Arc::new(Instance::new(primitive.clone(), transform)));
+                }
+            }
+            if let (Some(camera), Some(transform)) = (node.camera(), transform) {
+                if let Projection::Perspective(p) = camera.projection() {
+                    let lookfrom = transform.point(&Point3::zero());
+                    let forward = transform.vector(&Vec3::new(0.0, 0.0, -1.0));
+                    scene.cameras.push(GltfCamera {
+                        vfov: (p.yfov() as f64).to_degrees(),
+                        aspect_ratio: p.aspect_ratio().map(|a| a as f64),
+                        lookfrom,
+                        lookat: lookfrom + forward.unit_vector(),
+                        vup: transform.vector(&Vec3::new(0.0, 1.0, 0.0)).unit_vector(),
+                    });
+                }
+            }
+            if let (Some(light), Some(transform)) = (node.light(), transform) {
+                let [r, g, b] = light.color();
+                let power = light.intensity() as f64 * Color::new(r as f64, g as f64, b as f64);
+                let position = transform.point(&Point3::zero());
+                let direction = transform.vector(&Vec3::new(0.0, 0.0, -1.0)).unit_vector();
+                lights.push((light.kind(), power, position, direction));
+            }
+
+            let children: Vec<_> = node.children().map(|c| (c, world)).collect();
+            stack.extend(children.into_iter().rev());
+        }
+    }
+
+    let bbox = scene.world.bounding_box();
+    let (center, radius) = if bbox.is_bounded() && !bbox.is_empty() {
+        (bbox.centroid(), 0.5 * (bbox.max() - bbox.min()).length())
+    } else {
+        (Point3::zero(), 1.0)
+    };
+    for (kind, power, position, direction) in lights {
+        let light: Arc<dyn Hittable> = match kind {
+            Kind::Directional => {
+                // Radiance that gives the light's illuminance (lux) over the disk's solid angle.
+                let distance = SUN_DISTANCE * radius;
+                let solid_angle = PI * radius * radius / (distance * distance);
+                let emit = power / solid_angle;
+                Arc::new(Disk::new(
+                    center - distance * direction,
+                    direction,
+                    radius,
+                    Arc::new(DiffuseLight::new(emit)),
+                ))
+            }
+            Kind::Point | Kind::Spot { .. } => {
+                // A sphere of radiance L has intensity L * pi * r^2 (candela) in every direction.
+                let light_radius = LIGHT_RADIUS_FRACTION * radius;
+                let emit = power / (PI * light_radius * light_radius);
+                Arc::new(Sphere::new(
+                    position,
+                    light_radius,
+                    Arc::new(DiffuseLight::new(emit)),
+                ))
+            }
+        };
+        scene.world.add(light.clone());
+        scene.lights.add(light);
+    }
+
+    Ok(scene)
+}
+
+// glTF matrices are column-major.
+fn node_matrix(node: &::gltf::Node) -> Mat4 {
+    let columns = node.transform().matrix();
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = columns[j][i] as f64;
+        }
+    }
+    Mat4::new(m)
+}
+
+fn convert_primitive(
+    primitive: &::gltf::Primitive,
+    buffers: &[Vec<u8>],
+    material: Arc<dyn Material>,
+) -> Option<TriangleMesh> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| b.as_slice()));
+    let positions: Vec<Point3> = reader
+        .read_positions()?
+        .map(|[x, y, z]| Point3::new(x as f64, y as f64, z as f64))
+        .collect();
+    let normals: Vec<Vec3> = reader
+        .read_normals()
+        .map(|normals| {
+            normals
+                .map(|[x, y, z]| Vec3::new(x as f64, y as f64, z as f64))
+                .collect()
+        })
+        .unwrap_or_default();
+    // glTF puts the uv origin at the top left of the image, we put it at the bottom left.
+    let uvs: Vec<(f64, f64)> = reader
+        .read_tex_coords(0)
+        .map(|uvs| {
+            uvs.into_f32()
+                .map(|[u, v]| (u as f64, 1.0 - v as f64))
+                .collect()
+        })
+        .unwrap_or_default();
+    let order: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    if order.iter().any(|&i| i >= positions.len()) {
+        return None;
+    }
+
+    let indices: Vec<[usize; 3]> = match primitive.mode() {
+        Mode::Triangles => order.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+        Mode::TriangleStrip => (2..order.len())
+            .map(|k| {
+                if k % 2 == 0 {
+                    [order[k - 2], order[k - 1], order[k]]
+                } else {
+                    [order[k - 1], order[k - 2], order[k]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => (2..order.len())
+            .map(|k| [order[0], order[k - 1], order[k]])
+            .collect(),
+        _ => return None,
+    };
+
+    Some(TriangleMesh::new(
+        positions, normals, uvs, indices, material,
+    ))
+}
+
+fn convert_material(material: &::gltf::Material, images: &[DynamicImage]) -> Arc<dyn Material> {
+    // Image textures for each use: color data is sRGB, everything else linear.
+    let texture = |texture: ::gltf::Texture, kind: fn(&DynamicImage) -> ImageTexture| {
+        let image = texture.source().index();
+        let sampler = texture.sampler();
+        let mut tex = kind(&images[image]);
+        if sampler.wrap_s() != WrappingMode::ClampToEdge
+            || sampler.wrap_t() != WrappingMode::ClampToEdge
+        {
+            tex = tex.with_repeat();
+        }
+        Arc::new(tex) as Arc<dyn Texture>
+    };
+    let scaled = |tex: Option<Arc<dyn Texture>>, factor: Color| -> Arc<dyn Texture> {
+        match tex {
+            Some(tex) => Arc::new(ScaledTexture::new(tex, factor)),
+            None => Arc::new(SolidColor::new(factor)),
+        }
+    };
+
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, alpha] = pbr.base_color_factor().map(|c| c as f64);
+    let base_texture = pbr.base_color_texture().map(|info| info.texture());
+    let base_color = scaled(
+        base_texture
+            .clone()
+            .map(|t| texture(t, ImageTexture::from_image)),
+        Color::new(r, g, b),
+    );
+
+    let mut result: Arc<dyn Material> = match material.transmission() {
+        Some(transmission) if transmission.transmission_factor() > 0.5 => {
+            Arc::new(Dielectric::new(material.ior().unwrap_or(1.5) as f64))
+        }
+        _ => {
+            let mut surface = MetallicRoughness::new(
+                base_color,
+                pbr.metallic_factor() as f64,
+                pbr.roughness_factor() as f64,
+            );
+            if let Some(info) = pbr.metallic_roughness_texture() {
+                surface = surface.with_metallic_roughness_texture(texture(
+                    info.texture(),
+                    ImageTexture::data_from_image,
+                ));
+            }
+
+            let [er, eg, eb] = material.emissive_factor().map(|c| c as f64);
+            let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+            let emissive = strength * Color::new(er, eg, eb);
+            if !emissive.near_zero() {
+                let tex = material
+                    .emissive_texture()
+                    .map(|info| texture(info.texture(), ImageTexture::from_image));
+                surface = surface.with_emission(scaled(tex, emissive));
+            }
+            Arc::new(surface)
+        }
+    };
+
+    if let Some(normal) = material.normal_texture() {
+        let tex = texture(normal.texture(), ImageTexture::data_from_image);
+        result = Arc::new(NormalMapped::normal_map(result, tex));
+    }
+    if material.alpha_mode() == AlphaMode::Mask {
+        let tex = base_texture.map(|t| texture(t, ImageTexture::alpha_from_image));
+        let alpha = scaled(tex, Color::new(alpha, alpha, alpha));
+        let cutoff = material.alpha_cutoff().unwrap_or(0.5) as f64;
+        result = Arc::new(AlphaMasked::new(result, alpha).with_cutoff(cutoff));
+    }
+    result
+}
+
+// Contents of a buffer or image URI: an embedded base64 data URI or a file next to the
+// glTF file.
+fn read_uri(base: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (header, payload) = data
+            .split_once(',')
+            .ok_or_else(|| "malformed data URI".to_string())?;
+        if !header.ends_with(";base64") {
+            return Err("only base64 data URIs are supported".to_string());
+        }
+        return decode_base64(payload).ok_or_else(|| "invalid base64 in data URI".to_string());
+    }
+
+    let path = base.join(percent_decode(uri));
+    fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        bits = (bits << 6) | value(c)? as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base64(bytes: &[u8]) -> String {
+        const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(DIGITS[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    fn load(name: &str, json: &str) -> io::Result<GltfScene> {
+        let path = std::env::temp_dir().join(format!("gltf_{}.gltf", name));
+        fs::write(&path, json).unwrap();
+        let result = load_gltf(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    // A file whose buffer holds one triangle and `padding` more bytes, with the given
+    // extra views, nodes and other top-level entries.
+    fn file(views: &str, nodes: &str, extra: &str, padding: usize) -> String {
+        let mut data = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(vec![0; padding]);
+        format!(
+            r#"{{
+  "asset": {{ "version": "2.0" }},
+  "buffers": [{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}],
+  "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}{}],
+  "accessors": [{{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                   "min": [0, 0, 0], "max": [1, 1, 0] }}],
+  "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+  "nodes": [{{ "mesh": 0, "translation": [0, 0, -2] }}{}],
+  "scenes": [{{ "nodes": [0, 1] }}],
+  "scene": 0{}
+}}"#,
+            data.len(),
+            base64(&data),
+            views,
+            nodes,
+            extra
+        )
+    }
+
+    #[test]
+    fn loads_meshes_and_lights() {
+        let light = r#",
+  "extensionsUsed": ["KHR_lights_punctual"],
+  "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "point", "intensity": 10 }] } }"#;
+        let light_node = r#", { "translation": [0, 3, 0],
+    "extensions": { "KHR_lights_punctual": { "light": 0 } } }"#;
+        let scene = load("light", &file("", light_node, light, 0)).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.lights.objects.len(), 1);
+
+        // The node moves the triangle from z = 0 to z = -2.
+        let ray = Ray::new(Point3::new(0.2, 0.2, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(scene
+            .world
+            .hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!((rec.t - 3.0).abs() < 1e-9, "t = {}", rec.t);
+    }
+
+    #[test]
+    fn rejects_image_outside_its_buffer() {
+        let view = r#", { "buffer": 0, "byteOffset": 36, "byteLength": 64 }"#;
+        let image = r#",
+  "images": [{ "bufferView": 1, "mimeType": "image/png" }]"#;
+        let message = load("image_view", &file(view, ", {}", image, 4))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            message.ends_with("buffer view 1 of image 0 runs past the end of its buffer"),
+            "{}",
+            message
+        );
+    }
+}
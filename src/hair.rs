@@ -0,0 +1,418 @@
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::util::{degrees_to_radians, random_double, PI};
+use crate::vec3::Vec3;
+
+// Scattering lobes tracked separately: R, TT, TRT, and the rest lumped together.
+const P_MAX: usize = 3;
+
+/// Hair fiber scattering after d'Eon et al. and Chiang et al., as in pbrt: a rough
+/// dielectric cylinder with absorbing interior. Light bounces off the surface (R),
+/// passes through (TT) or reflects once inside (TRT), each lobe with its own
+/// longitudinal and azimuthal spread. It expects the hit's tangent to run along the
+/// fiber and v to run across it, which `Curve` provides.
+pub struct Hair {
+    sigma_a: Color, // Absorption coefficient of the fiber interior, per unit diameter
+    eta: f64,
+    beta_m: f64, // Longitudinal roughness in [0, 1]
+    beta_n: f64, // Azimuthal roughness in [0, 1]
+    alpha: f64,  // Cuticle scale tilt in degrees
+}
+
+impl Hair {
+    pub fn new(sigma_a: Color) -> Self {
+        Hair {
+            sigma_a,
+            eta: 1.55,
+            beta_m: 0.3,
+            beta_n: 0.3,
+            alpha: 2.0,
+        }
+    }
+
+    /// Hair colored by its melanin pigments. Eumelanin concentration runs from about
+    /// 0 (blond) through 1.3 (brown) to 8 (black); pheomelanin makes it red.
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64) -> Self {
+        let eumelanin_sigma_a = Color::new(0.419, 0.697, 1.37);
+        let pheomelanin_sigma_a = Color::new(0.187, 0.4, 1.05);
+        Hair::new(
+            eumelanin.max(0.0) * eumelanin_sigma_a + pheomelanin.max(0.0) * pheomelanin_sigma_a,
+        )
+    }
+
+    /// Hair whose multiply scattered color approximates `color`, for the default
+    /// azimuthal roughness. Call this after `with_roughness` to match another roughness.
+    pub fn from_color(color: Color) -> Self {
+        let mut hair = Hair::new(Color::zero());
+        hair.sigma_a = sigma_a_from_color(color, hair.beta_n);
+        hair
+    }
+
+    pub fn with_roughness(mut self, beta_m: f64, beta_n: f64) -> Self {
+        self.beta_m = beta_m.clamp(1e-3, 1.0);
+        self.beta_n = beta_n.clamp(1e-3, 1.0);
+        self
+    }
+
+    pub fn with_scale_tilt(mut self, degrees: f64) -> Self {
+        self.alpha = degrees;
+        self
+    }
+
+    pub fn with_ior(mut self, eta: f64) -> Self {
+        self.eta = eta;
+        self
+    }
+
+    pub fn sigma_a(&self) -> Color {
+        self.sigma_a
+    }
+}
+
+/// Absorption giving roughly `color` after multiple scattering in a head of hair with
+/// azimuthal roughness `beta_n`.
+pub fn sigma_a_from_color(color: Color, beta_n: f64) -> Color {
+    let b = beta_n;
+    let denom = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3)
+        + 5.574 * b.powi(4)
+        + 0.245 * b.powi(5);
+    let channel = |c: f64| (c.clamp(1e-4, 1.0).ln() / denom).powi(2);
+    Color::new(channel(color.x()), channel(color.y()), channel(color.z()))
+}
+
+// Per-hit state: lobe variances, azimuthal scale and the cuticle tilt terms.
+struct Fiber {
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Color,
+    v: [f64; P_MAX + 1],
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl Fiber {
+    fn new(hair: &Hair, h: f64) -> Self {
+        let beta_m = hair.beta_m;
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let mut v = [4.0 * v0; P_MAX + 1];
+        v[0] = v0;
+        v[1] = 0.25 * v0;
+
+        let beta_n = hair.beta_n;
+        let sqrt_pi_over_8 = 0.626657069;
+        let s =
+            sqrt_pi_over_8 * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = degrees_to_radians(hair.alpha).sin();
+        cos_2k_alpha[0] = safe_sqrt(1.0 - sin_2k_alpha[0] * sin_2k_alpha[0]);
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        Fiber {
+            h,
+            gamma_o: safe_asin(h),
+            eta: hair.eta,
+            sigma_a: hair.sigma_a,
+            v,
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // Outgoing elevation tilted by the cuticle scales for lobe `p`.
+    fn tilted(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin, cos) = match p {
+            0 => (-self.sin_2k_alpha[1], self.cos_2k_alpha[1]),
+            1 => (self.sin_2k_alpha[0], self.cos_2k_alpha[0]),
+            2 => (self.sin_2k_alpha[2], self.cos_2k_alpha[2]),
+            _ => return (sin_theta_o, cos_theta_o),
+        };
+        (
+            sin_theta_o * cos + cos_theta_o * sin,
+            (cos_theta_o * cos - sin_theta_o * sin).abs(),
+        )
+    }
+
+    // Refracted azimuth and the lobe attenuations for the outgoing direction.
+    fn attenuation(&self, sin_theta_o: f64, cos_theta_o: f64) -> (f64, [Color; P_MAX + 1]) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let etap =
+            safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o.max(1e-8);
+        let sin_gamma_t = (self.h / etap).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let gamma_t = sin_gamma_t.asin();
+
+        let path = 2.0 * cos_gamma_t / cos_theta_t.max(1e-8);
+        let transmittance = Color::new(
+            (-self.sigma_a.x() * path).exp(),
+            (-self.sigma_a.y() * path).exp(),
+            (-self.sigma_a.z() * path).exp(),
+        );
+
+        let cos_gamma_o = safe_sqrt(1.0 - self.h * self.h);
+        let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, self.eta);
+        let mut ap = [Color::zero(); P_MAX + 1];
+        ap[0] = Color::new(f, f, f);
+        ap[1] = (1.0 - f).powi(2) * transmittance;
+        for p in 2..P_MAX {
+            ap[p] = ap[p - 1] * transmittance * f;
+        }
+        let tf = transmittance * f;
+        let residual = Color::new(
+            1.0 / (1.0 - tf.x()).max(1e-8),
+            1.0 / (1.0 - tf.y()).max(1e-8),
+            1.0 / (1.0 - tf.z()).max(1e-8),
+        );
+        ap[P_MAX] = ap[P_MAX - 1] * tf * residual;
+        (gamma_t, ap)
+    }
+
+    // BSDF times |cos θi|, and the sampling density, for local directions.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        let sin_theta_o = wo.x().clamp(-1.0, 1.0);
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = wo.z().atan2(wo.y());
+        let sin_theta_i = wi.x().clamp(-1.0, 1.0);
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+        let phi_i = wi.z().atan2(wi.y());
+
+        let (gamma_t, ap) = self.attenuation(sin_theta_o, cos_theta_o);
+        let weights = lobe_weights(&ap);
+        let phi = phi_i - phi_o;
+
+        let mut f = Color::zero();
+        let mut pdf = 0.0;
+        for p in 0..P_MAX {
+            let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let lobe = mp(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                * np(phi, p, self.s, self.gamma_o, gamma_t);
+            f = f + lobe * ap[p];
+            pdf += lobe * weights[p];
+        }
+        let lobe = mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) / (2.0 * PI);
+        f = f + lobe * ap[P_MAX];
+        pdf += lobe * weights[P_MAX];
+        (f, pdf)
+    }
+
+    // Samples an incident direction by picking a lobe, then its elevation and azimuth.
+    fn sample(&self, wo: &Vec3) -> Vec3 {
+        let sin_theta_o = wo.x().clamp(-1.0, 1.0);
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = wo.z().atan2(wo.y());
+
+        let (gamma_t, ap) = self.attenuation(sin_theta_o, cos_theta_o);
+        let weights = lobe_weights(&ap);
+        let mut choice = random_double();
+        let mut p = 0;
+        while p < P_MAX && choice >= weights[p] {
+            choice -= weights[p];
+            p += 1;
+        }
+
+        let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let u = random_double().max(1e-5);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * random_double()).cos();
+        let sin_theta_i = (-cos_theta * sin_op + sin_theta * cos_phi * cos_op).clamp(-1.0, 1.0);
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let dphi = if p < P_MAX {
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(random_double(), self.s)
+        } else {
+            2.0 * PI * random_double()
+        };
+        let phi_i = phi_o + dphi;
+        Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+}
+
+impl Material for Hair {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        // Local frame: x along the fiber, z the shading normal.
+        let (x, y, z) = (rec.tangent, rec.bitangent, rec.normal);
+        let wo_world = -r_in.direction().unit_vector();
+        let wo = Vec3::new(wo_world.dot(&x), wo_world.dot(&y), wo_world.dot(&z));
+
+        let fiber = Fiber::new(self, (2.0 * rec.v - 1.0).clamp(-1.0, 1.0));
+        let wi = fiber.sample(&wo);
+        let (f, pdf) = fiber.eval(&wo, &wi);
+        if pdf <= 0.0 || !pdf.is_finite() {
+            return false;
+        }
+
+        let direction = wi.x() * x + wi.y() * y + wi.z() * z;
+        *scattered = Ray::new_with_time(rec.p, direction, r_in.time());
+        *attenuation = f / pdf;
+        true
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f64) -> f64 {
+    x.clamp(-1.0, 1.0).asin()
+}
+
+// Unpolarized Fresnel reflectance entering a dielectric of index `eta` from air.
+fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta_i.clamp(-1.0, 1.0).abs();
+    let sin_t = safe_sqrt(1.0 - cos_i * cos_i) / eta;
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = safe_sqrt(1.0 - sin_t * sin_t);
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Lobe selection probabilities, proportional to each lobe's luminance.
+fn lobe_weights(ap: &[Color; P_MAX + 1]) -> [f64; P_MAX + 1] {
+    let luminance = |c: &Color| 0.212671 * c.x() + 0.715160 * c.y() + 0.072169 * c.z();
+    let total: f64 = ap.iter().map(luminance).sum();
+    if total <= 0.0 {
+        return [1.0, 0.0, 0.0, 0.0];
+    }
+    ap.map(|c| luminance(&c) / total)
+}
+
+fn bessel_i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// Longitudinal scattering.
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_bessel_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * bessel_i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Exit azimuth of lobe `p` relative to the incoming one.
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+// Azimuthal scattering: a logistic around the lobe's exit azimuth, trimmed to [-π, π].
+fn np(phi_value: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_value - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    logistic(dphi, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.clamp(-PI, PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use crate::vec3::{random_unit_vector, Point3};
+
+    // Mean attenuation of `hair` over random directions and offsets across the fiber.
+    fn albedo(hair: &Hair) -> Color {
+        let n = 10_000;
+        let mut total = Color::zero();
+        for _ in 0..n {
+            let rec = HitRecord {
+                normal: Vec3::new(0.0, 0.0, 1.0),
+                tangent: Vec3::new(1.0, 0.0, 0.0),
+                bitangent: Vec3::new(0.0, 1.0, 0.0),
+                v: random_double(),
+                ..HitRecord::default()
+            };
+            let r_in = Ray::new(Point3::zero(), random_unit_vector());
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default();
+            if hair.scatter(&r_in, &rec, &mut attenuation, &mut scattered) {
+                total = total + attenuation;
+            }
+        }
+        total / n as f64
+    }
+
+    #[test]
+    fn fibers_without_absorption_keep_all_energy() {
+        util::seed_rng(5);
+        for beta in [0.1, 0.3, 0.8] {
+            let white = albedo(&Hair::new(Color::zero()).with_roughness(beta, beta));
+            assert!((white.y() - 1.0).abs() < 1e-6, "albedo {}", white.y());
+        }
+
+        // Melanin absorbs blue most, leaving brown hair reddish.
+        let brown = albedo(&Hair::from_melanin(1.3, 0.0));
+        assert!(brown.x() < 1.0 && brown.z() < brown.y() && brown.y() < brown.x());
+    }
+}
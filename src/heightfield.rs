@@ -0,0 +1,289 @@
+use std::sync::Arc;
+
+use image::error::{ParameterError, ParameterErrorKind};
+use image::ImageError;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle;
+use crate::vec3::{Point3, Vec3};
+
+/// Terrain from a regular grid of height samples, spread over `size.x` by `size.z`
+/// starting at `corner` and raised by `height * size.y`. Each grid cell is two triangles,
+/// found by walking the cells under the ray with a 2D DDA and skipping cells whose height
+/// range the ray passes over or under. u runs along x and v along z.
+pub struct Heightfield {
+    heights: Vec<f64>, // Row-major, `columns` samples per row along x
+    columns: usize,
+    rows: usize,
+    corner: Point3,
+    size: Vec3,
+    normals: Vec<Vec3>,
+    cell_ranges: Vec<(f64, f64)>, // World-space min and max height of each cell
+    bbox: Aabb,
+    mat: Arc<dyn Material>,
+}
+
+impl Heightfield {
+    /// Heightfield over `columns * rows` samples, row by row along z. Panics if there are
+    /// fewer than 2x2 samples or `heights` has the wrong length.
+    pub fn new(
+        heights: Vec<f64>,
+        columns: usize,
+        rows: usize,
+        corner: Point3,
+        size: Vec3,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        assert!(
+            columns >= 2 && rows >= 2,
+            "a heightfield needs at least 2x2 samples"
+        );
+        assert_eq!(
+            heights.len(),
+            columns * rows,
+            "wrong number of height samples"
+        );
+
+        let mut field = Heightfield {
+            heights,
+            columns,
+            rows,
+            corner,
+            size,
+            normals: Vec::new(),
+            cell_ranges: Vec::new(),
+            bbox: Aabb::EMPTY,
+            mat,
+        };
+
+        field.normals = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| field.vertex_normal(i, j))
+            .collect();
+
+        let mut low = f64::INFINITY;
+        let mut high = f64::NEG_INFINITY;
+        for j in 0..rows - 1 {
+            for i in 0..columns - 1 {
+                let corners = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)];
+                let ys = corners.map(|(i, j)| field.vertex(i, j).y());
+                let range = (
+                    ys.iter().copied().fold(f64::INFINITY, f64::min),
+                    ys.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                );
+                low = low.min(range.0);
+                high = high.max(range.1);
+                field.cell_ranges.push(range);
+            }
+        }
+
+        field.bbox = Aabb::from_points(
+            Point3::new(corner.x(), low, corner.z()),
+            Point3::new(corner.x() + size.x(), high, corner.z() + size.z()),
+        );
+        field
+    }
+
+    /// Heightfield from the luminance of a grayscale (or any) image, black at `corner.y`
+    /// and white `size.y` above it. Images smaller than 2x2 are an error.
+    pub fn from_image(
+        filename: &str,
+        corner: Point3,
+        size: Vec3,
+        mat: Arc<dyn Material>,
+    ) -> image::ImageResult<Self> {
+        let image = image::open(filename)?.to_luma16();
+        let (columns, rows) = (image.width() as usize, image.height() as usize);
+        if columns < 2 || rows < 2 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "a heightfield needs at least 2x2 samples, not {}x{}",
+                    columns, rows
+                )),
+            )));
+        }
+        let heights = image
+            .pixels()
+            .map(|p| p.0[0] as f64 / u16::MAX as f64)
+            .collect();
+        Ok(Heightfield::new(heights, columns, rows, corner, size, mat))
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Raw sample at column `i`, row `j`.
+    pub fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.columns + i]
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        self.corner
+            + Vec3::new(
+                self.size.x() * i as f64 / (self.columns - 1) as f64,
+                self.size.y() * self.height(i, j),
+                self.size.z() * j as f64 / (self.rows - 1) as f64,
+            )
+    }
+
+    // Normal from central differences of the neighbouring samples.
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let along_x = self.vertex(i1, j) - self.vertex(i0, j);
+        let along_z = self.vertex(i, j1) - self.vertex(i, j0);
+        along_z.cross(&along_x).unit_vector()
+    }
+
+    // Nearest hit on the two triangles of cell (i, j).
+    fn hit_cell(&self, i: usize, j: usize, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        // Both triangles wind so their geometric normal points up.
+        let triangles = [
+            [(i, j), (i, j + 1), (i + 1, j)],
+            [(i + 1, j + 1), (i + 1, j), (i, j + 1)],
+        ];
+
+        let mut closest = *ray_t;
+        let mut hit_anything = false;
+        for corners in triangles {
+            let [p0, p1, p2] = corners.map(|(i, j)| self.vertex(i, j));
+            let Some((t, b1, b2)) = triangle::intersect(&p0, &p1, &p2, r) else {
+                continue;
+            };
+            if !closest.surronds(t) {
+                continue;
+            }
+
+            let b0 = 1.0 - b1 - b2;
+            let p = r.at(t);
+            let u = (p.x() - self.corner.x()) / self.size.x();
+            let v = (p.z() - self.corner.z()) / self.size.z();
+            if self.mat.masked(u, v, &p) {
+                continue;
+            }
+
+            let [n0, n1, n2] = corners.map(|(i, j)| self.normals[j * self.columns + i]);
+            let outward_normal = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+            let shading_normal = (b0 * n0 + b1 * n1 + b2 * n2).unit_vector();
+            rec.t = t;
+            rec.p = p;
+            rec.u = u;
+            rec.v = v;
+            rec.set_normal_face(r, &outward_normal);
+            rec.set_shading_frame(r, &shading_normal, &Vec3::new(1.0, 0.0, 0.0));
+            rec.mat = self.mat.clone();
+            hit_anything = true;
+            closest.max = t;
+        }
+        hit_anything
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let Some(span) = self.bbox.clip(r, ray_t) else {
+            return false;
+        };
+
+        let (cells_x, cells_z) = (self.columns - 1, self.rows - 1);
+        let origin = r.origin();
+        let direction = r.direction();
+        // Ray in grid units, where cell (i, j) spans [i, i + 1] x [j, j + 1].
+        let to_grid_x = cells_x as f64 / self.size.x();
+        let to_grid_z = cells_z as f64 / self.size.z();
+        let entry = r.at(span.min);
+        let gx = (entry.x() - self.corner.x()) * to_grid_x;
+        let gz = (entry.z() - self.corner.z()) * to_grid_z;
+        let (dx, dz) = (direction.x() * to_grid_x, direction.z() * to_grid_z);
+
+        let mut i = (gx.floor().max(0.0) as usize).min(cells_x - 1) as isize;
+        let mut j = (gz.floor().max(0.0) as usize).min(cells_z - 1) as isize;
+
+        // Ray distance to the next cell boundary along each axis, and between boundaries.
+        let setup = |g: f64, d: f64, cell: isize| -> (isize, f64, f64) {
+            if d > 0.0 {
+                (1, span.min + ((cell + 1) as f64 - g) / d, 1.0 / d)
+            } else if d < 0.0 {
+                (-1, span.min + (cell as f64 - g) / d, -1.0 / d)
+            } else {
+                (0, f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (step_i, mut next_x, delta_x) = setup(gx, dx, i);
+        let (step_j, mut next_z, delta_z) = setup(gz, dz, j);
+
+        let mut t_enter = span.min;
+        loop {
+            let t_exit = next_x.min(next_z).min(span.max);
+
+            // Skip cells whose height range the ray segment does not reach.
+            let (low, high) = self.cell_ranges[j as usize * cells_x + i as usize];
+            let (y0, y1) = (
+                origin.y() + t_enter * direction.y(),
+                origin.y() + t_exit * direction.y(),
+            );
+            if y0.min(y1) <= high
+                && y0.max(y1) >= low
+                && self.hit_cell(i as usize, j as usize, r, ray_t, rec)
+            {
+                return true;
+            }
+
+            if t_exit >= span.max {
+                return false;
+            }
+            if next_x < next_z {
+                i += step_i;
+                t_enter = next_x;
+                next_x += delta_x;
+            } else {
+                j += step_j;
+                t_enter = next_z;
+                next_z += delta_z;
+            }
+            if i < 0 || j < 0 || i as usize >= cells_x || j as usize >= cells_z {
+                return false;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn rejects_images_too_small_for_a_cell() {
+        let path = std::env::temp_dir().join("heightfield_strip.png");
+        image::GrayImage::new(1, 3).save(&path).unwrap();
+        let result = Heightfield::from_image(
+            path.to_str().unwrap(),
+            Point3::zero(),
+            Vec3::new(1.0, 1.0, 1.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let message = result.err().unwrap().to_string();
+        assert!(
+            message.contains("at least 2x2 samples, not 1x3"),
+            "{}",
+            message
+        );
+    }
+}
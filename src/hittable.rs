@@ -1,9 +1,12 @@
-use std::rc::Rc;
-
+use crate::aabb::Aabb;
 use crate::interval::Interval;
 use crate::material::DefaultMaterial;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::scene_desc;
+use crate::scene_desc::ObjectDesc;
+use crate::scene_desc::SceneExporter;
+use crate::vec3::orthonormal_basis;
 use crate::vec3::Point3;
 use crate::vec3::Vec3;
 use std::sync::Arc;
@@ -11,13 +14,19 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
-    pub normal: Vec3,
+    pub normal: Vec3,           // Shading normal, facing against the ray
+    pub geometric_normal: Vec3, // True surface normal, facing against the ray
+    pub tangent: Vec3,          // Shading frame direction of increasing u
+    pub bitangent: Vec3,        // Shading frame direction of increasing v
     pub mat: Arc<dyn Material>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
 impl HitRecord {
+    /// Sets the geometric normal and resets the shading frame to an arbitrary frame around it.
     pub fn set_normal_face(&mut self, r: &Ray, outward_normal: &Vec3) {
         self.front_face = outward_normal.dot(&r.direction()) < 0.0;
         self.normal = if self.front_face {
@@ -25,6 +34,94 @@ impl HitRecord {
         } else {
             -(*outward_normal)
         };
+        self.geometric_normal = self.normal;
+        (self.tangent, self.bitangent) = orthonormal_basis(&self.normal);
+    }
+
+    /// Sets the shading frame from an outward shading normal and a tangent along
+    /// increasing u, with v increasing along `outward_normal × tangent`. Must be called
+    /// after `set_normal_face`.
+    pub fn set_shading_frame(&mut self, r: &Ray, outward_normal: &Vec3, tangent: &Vec3) {
+        self.set_signed_shading_frame(r, outward_normal, tangent, 1.0);
+    }
+
+    /// Like `set_shading_frame` for UV layouts that may be mirrored: with `sign` -1, v
+    /// increases along `tangent × outward_normal` instead.
+    pub fn set_signed_shading_frame(
+        &mut self,
+        r: &Ray,
+        outward_normal: &Vec3,
+        tangent: &Vec3,
+        sign: f64,
+    ) {
+        let normal = if self.front_face {
+            *outward_normal
+        } else {
+            -(*outward_normal)
+        };
+        self.set_shading_normal(r, &normal);
+
+        let tangent = *tangent - self.normal * self.normal.dot(tangent);
+        if tangent.near_zero() {
+            (self.tangent, self.bitangent) = orthonormal_basis(&self.normal);
+        } else {
+            self.tangent = tangent.unit_vector();
+            // Taken from the outward side, so back-face hits keep the same v direction.
+            let outward = if self.front_face {
+                self.normal
+            } else {
+                -self.normal
+            };
+            self.bitangent = sign * outward.cross(&self.tangent);
+        }
+    }
+
+    /// Fits the tangent frame to the current shading normal, keeping the bitangent on the
+    /// side of `bitangent` so mirrored UV layouts stay mirrored.
+    pub fn set_tangent(&mut self, tangent: &Vec3, bitangent: &Vec3) {
+        let tangent = *tangent - self.normal * self.normal.dot(tangent);
+        if tangent.near_zero() {
+            return;
+        }
+        self.tangent = tangent.unit_vector();
+        let across = self.normal.cross(&self.tangent);
+        self.bitangent = if across.dot(bitangent) < 0.0 {
+            -across
+        } else {
+            across
+        };
+    }
+
+    /// Replaces the shading normal, which must already face against the ray. Normals that
+    /// would reflect the ray below the geometric surface are bent toward the viewer so the
+    /// mirror direction just grazes the surface instead.
+    pub fn set_shading_normal(&mut self, r: &Ray, normal: &Vec3) {
+        const GRAZING: f64 = 0.01;
+
+        let wo = -r.direction().unit_vector();
+        let mut normal = normal.unit_vector();
+        if normal.dot(&self.geometric_normal) < 0.0 {
+            normal = -normal;
+        }
+
+        let reflected = 2.0 * wo.dot(&normal) * normal - wo;
+        if reflected.dot(&self.geometric_normal) < GRAZING {
+            let ng = self.geometric_normal;
+            let flattened = reflected - reflected.dot(&ng) * ng;
+            let grazing = if flattened.near_zero() {
+                ng
+            } else {
+                (flattened.unit_vector() + GRAZING * ng).unit_vector()
+            };
+            let bent = wo + grazing;
+            normal = if bent.near_zero() {
+                ng
+            } else {
+                bent.unit_vector()
+            };
+        }
+
+        self.normal = normal;
     }
 }
 
@@ -33,13 +130,36 @@ impl Default for HitRecord {
         HitRecord {
             p: Point3::default(),
             normal: Vec3::default(),
-            mat: Arc::new(DefaultMaterial::default()),
+            geometric_normal: Vec3::default(),
+            tangent: Vec3::default(),
+            bitangent: Vec3::default(),
+            mat: Arc::new(DefaultMaterial),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
         }
     }
 }
 
-pub trait Hittable : Sync + Send {
+pub trait Hittable: Sync + Send {
     fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool;
+
+    fn bounding_box(&self) -> Aabb;
+
+    /// Solid-angle density of sampling `direction` from `origin` with `random`, with the
+    /// shape where it is at `time`. Shapes that cannot be sampled as lights report zero.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+        0.0
+    }
+
+    /// Direction from `origin` toward a random point on the shape at `time`.
+    fn random(&self, _origin: &Point3, _time: f64) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    /// Description of the object for a scene file, see `scene::save_scene`.
+    fn to_scene(&self, _exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Err(scene_desc::unsupported::<Self>())
+    }
 }
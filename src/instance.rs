@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::motion::AnimatedTransform;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::transform::Transform;
+use crate::vec3::{Point3, Vec3};
+
+/// Places a shared object in the world through a transform. Many instances can wrap the
+/// same `Arc`, so a mesh is stored once however many copies are rendered.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    transform: Transform,
+    motion: Option<AnimatedTransform>, // Overrides `transform` for moving instances
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Instance {
+            object,
+            transform,
+            motion: None,
+        }
+    }
+
+    /// Instance whose transform changes over the shutter interval.
+    pub fn animated(object: Arc<dyn Hittable>, motion: AnimatedTransform) -> Self {
+        Instance {
+            object,
+            transform: motion.at(0.0),
+            motion: Some(motion),
+        }
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.object
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.motion = None;
+    }
+
+    pub fn set_motion(&mut self, motion: AnimatedTransform) {
+        self.transform = motion.at(0.0);
+        self.motion = Some(motion);
+    }
+
+    pub fn motion(&self) -> Option<&AnimatedTransform> {
+        self.motion.as_ref()
+    }
+
+    pub fn transform_at(&self, time: f64) -> Transform {
+        match &self.motion {
+            Some(motion) => motion.at(time),
+            None => self.transform,
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let transform = self.transform_at(r.time());
+
+        // The direction is not renormalized, so t is the same in both spaces.
+        let object_ray = Ray::new_with_time(
+            transform.inverse_point(&r.origin()),
+            transform.inverse_vector(&r.direction()),
+            r.time(),
+        );
+
+        if !self.object.hit(&object_ray, ray_t, rec) {
+            return false;
+        }
+
+        rec.p = transform.point(&rec.p);
+        // The inverse transpose preserves the sign of n·d, so the normals still face
+        // against the world-space ray and `front_face` stays valid.
+        rec.geometric_normal = transform.normal(&rec.geometric_normal).unit_vector();
+        rec.normal = transform.normal(&rec.normal).unit_vector();
+
+        rec.set_tangent(
+            &transform.vector(&rec.tangent),
+            &transform.vector(&rec.bitangent),
+        );
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match &self.motion {
+            Some(motion) => motion.motion_bounds(&self.object.bounding_box()),
+            None => self.object.bounding_box().transformed(&self.transform),
+        }
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let transform = self.transform_at(time);
+        let local = transform.inverse_vector(direction).unit_vector();
+        let pdf = self
+            .object
+            .pdf_value(&transform.inverse_point(origin), &local, time);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+
+        // Change of variables from object-space to world-space solid angle.
+        let stretch = transform.vector(&local).length();
+        pdf * stretch * stretch * stretch / transform.determinant().abs()
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        let transform = self.transform_at(time);
+        let local = self.object.random(&transform.inverse_point(origin), time);
+        transform.vector(&local)
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.motion.is_some() {
+            return Err("moving instances cannot be saved in a scene file".to_string());
+        }
+        exporter
+            .object(self.object.as_ref())?
+            .transformed(&self.transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::util;
+    use crate::vec3::random_unit_vector;
+
+    #[test]
+    fn light_pdf_integrates_to_one() {
+        util::seed_rng(7);
+        let sphere = Sphere::new(
+            Point3::zero(),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let transform = Transform::scaling(Vec3::new(1.0, 3.0, 0.5))
+            .rotate_y(40.0)
+            .translate(Vec3::new(0.0, 1.0, -4.0));
+        let instance = Instance::new(Arc::new(sphere), transform);
+        let origin = Point3::new(0.5, 0.0, 0.0);
+
+        // Uniform directions have density 1/(4 pi), so this estimates the pdf's integral.
+        let samples = 200_000;
+        let total: f64 = (0..samples)
+            .map(|_| instance.pdf_value(&origin, &random_unit_vector(), 0.0))
+            .sum();
+        let integral = 4.0 * util::PI * total / samples as f64;
+        assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+
+        // Every sampled direction must be one the density covers.
+        for _ in 0..1000 {
+            let direction = instance.random(&origin, 0.0);
+            assert!(instance.pdf_value(&origin, &direction, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn moving_lights_are_sampled_at_the_ray_time() {
+        let sphere = Sphere::moving(
+            Point3::new(0.0, 0.0, -4.0),
+            Point3::new(4.0, 0.0, -4.0),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let motion = AnimatedTransform::linear(
+            Transform::identity(),
+            Transform::translation(Vec3::new(0.0, 4.0, 0.0)),
+        );
+        let instance = Instance::animated(Arc::new(sphere), motion);
+        let origin = Point3::zero();
+        for (time, center) in [(0.0, [0.0, 0.0, -4.0]), (1.0, [4.0, 4.0, -4.0])] {
+            let center = Vec3::new(center[0], center[1], center[2]);
+            assert!(instance.pdf_value(&origin, &center, time) > 0.0);
+            assert_eq!(instance.pdf_value(&origin, &center, 1.0 - time), 0.0);
+            let direction = instance.random(&origin, time).unit_vector();
+            assert!(direction.dot(&center.unit_vector()) > 0.99);
+        }
+    }
+
+    #[test]
+    fn normals_follow_the_inverse_transpose() {
+        // Tangents carried by the transform stay perpendicular to the carried normal.
+        let transform = Transform::scaling(Vec3::new(1.0, 3.0, 0.5))
+            .rotate(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            .translate(Vec3::new(2.0, 0.0, 1.0));
+        let n = Vec3::new(1.0, 2.0, 3.0);
+        let normal = transform.normal(&n);
+        for tangent in [Vec3::new(2.0, -1.0, 0.0), Vec3::new(0.0, 3.0, -2.0)] {
+            assert!(tangent.dot(&n).abs() < 1e-12);
+            assert!(transform.vector(&tangent).dot(&normal).abs() < 1e-12);
+        }
+
+        // A sphere stretched into an ellipsoid x² + (y/3)² + (z/0.5)² = 1.
+        let sphere = Sphere::new(
+            Point3::zero(),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let instance = Instance::new(
+            Arc::new(sphere),
+            Transform::scaling(Vec3::new(1.0, 3.0, 0.5)),
+        );
+        let r = Ray::new(Point3::new(5.0, 1.5, 0.2), Vec3::new(-1.0, 0.0, 0.0));
+        let mut rec = HitRecord::default();
+        assert!(instance.hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec));
+        let x = 0.59f64.sqrt();
+        assert!((rec.t - (5.0 - x)).abs() < 1e-9);
+        let expected = Vec3::new(x, 1.5 / 9.0, 0.2 / 0.25).unit_vector();
+        for normal in [rec.normal, rec.geometric_normal] {
+            assert!((normal - expected).length() < 1e-9);
+        }
+    }
+}
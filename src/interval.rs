@@ -1,3 +1,4 @@
+#[derive(Copy, Clone, Debug)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -8,6 +9,22 @@ impl Interval {
         Interval { min, max }
     }
 
+    /// The tightest interval enclosing both `a` and `b`.
+    pub fn enclosing(a: &Interval, b: &Interval) -> Self {
+        Interval {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    /// The part shared by `a` and `b`, empty if they are disjoint.
+    pub fn overlap(a: &Interval, b: &Interval) -> Self {
+        Interval {
+            min: a.min.max(b.min),
+            max: a.max.min(b.max),
+        }
+    }
+
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -27,7 +44,12 @@ impl Interval {
         if x > self.max {
             return self.max;
         }
-        return x;
+        x
+    }
+
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
     }
 
     pub const EMPTY: Interval = Interval {
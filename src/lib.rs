@@ -1,13 +1,43 @@
+pub mod aabb;
+pub mod box_shape;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod csg;
+pub mod curve;
+pub mod disk;
+pub mod displacement;
+pub mod film;
+pub mod gltf;
+pub mod hair;
+pub mod heightfield;
 pub mod hittable;
 pub mod hittable_list;
+pub mod instance;
 pub mod interval;
 pub mod material;
+pub mod mesh;
+pub mod motion;
+pub mod normal_map;
 pub mod obj;
 pub mod objloader;
+pub mod output;
+pub mod pbrt;
+pub mod plane;
+pub mod ply;
+pub mod quad;
+pub mod quadric;
 pub mod ray;
+pub mod scene;
+pub mod scene_desc;
+pub mod sdf;
 pub mod sphere;
+pub mod stl;
+pub mod subdivision;
+pub mod texture;
+pub mod thin_film;
+pub mod torus;
+pub mod transform;
 pub mod triangle;
 pub mod util;
 pub mod vec3;
@@ -1,37 +1,274 @@
+use clap::{CommandFactory, Parser, ValueEnum};
+use m1_raytracer::hittable::Hittable;
+use m1_raytracer::material::{Dielectric, Metal};
 use m1_raytracer::{
-    camera::{self, Camera, CameraParams},
+    bvh::BvhAccel,
+    camera::{Camera, CameraParams},
     color::Color,
-    hittable_list::{self, HittableList},
+    csg::Csg,
+    film::{ToneMap, ToneMapOperator},
+    hittable_list::HittableList,
     material::Lambertian,
-    sphere::{self, Sphere},
+    output::{Output, OutputFormat},
+    plane::Plane,
+    sphere::Sphere,
     vec3::{Point3, Vec3},
 };
+use std::str::FromStr;
 use std::sync::Arc;
-use m1_raytracer::obj::load_obj_file;
-use m1_raytracer::material::{Dielectric, Metal};
+
+/// Renders a scene file, or the built-in scene, to an image.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Scene to render: a TOML scene file or, by extension, a .pbrt file. Renders the
+    /// built-in scene when omitted.
+    scene: Option<String>,
+
+    /// Image width in pixels
+    #[arg(short, long, value_parser = parse_count::<usize>)]
+    width: Option<usize>,
+
+    /// Width over height, as a number or W:H (e.g. 16:9)
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    aspect_ratio: Option<f64>,
+
+    /// Samples per pixel
+    #[arg(short, long, value_parser = parse_count::<u32>)]
+    samples: Option<u32>,
+
+    /// Maximum ray bounces
+    #[arg(short = 'd', long, value_parser = parse_count::<u32>)]
+    max_depth: Option<u32>,
+
+    /// Worker threads [default: one per core]
+    #[arg(short = 'j', long, value_parser = parse_count::<usize>)]
+    threads: Option<usize>,
+
+    /// Seed for a reproducible render [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Output image, or - for standard output
+    #[arg(short, long, default_value = "img/output.png")]
+    output: String,
+
+    /// Output format [default: from the output extension, ppm for standard output]
+    #[arg(short, long, value_enum)]
+    format: Option<FormatArg>,
+
+    /// Exposure in stops for 8-bit output; each stop doubles the brightness
+    #[arg(
+        short,
+        long,
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_parser = parse_exposure
+    )]
+    exposure: f64,
+
+    /// Tone curve for 8-bit output
+    #[arg(short, long, value_enum, default_value = "clamp")]
+    tone_map: ToneMapArg,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ToneMapArg {
+    /// Clip values above 1
+    Clamp,
+    /// x / (1 + x)
+    Reinhard,
+    /// ACES filmic curve
+    Aces,
+}
+
+impl From<ToneMapArg> for ToneMapOperator {
+    fn from(tone_map: ToneMapArg) -> Self {
+        match tone_map {
+            ToneMapArg::Clamp => ToneMapOperator::Clamp,
+            ToneMapArg::Reinhard => ToneMapOperator::Reinhard,
+            ToneMapArg::Aces => ToneMapOperator::Aces,
+        }
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum FormatArg {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    /// Binary PPM
+    Ppm,
+    /// Plain-text PPM
+    PlainPpm,
+    /// OpenEXR, half-float RGB plus depth
+    Exr,
+    /// OpenEXR, float RGB plus depth
+    ExrFloat,
+    /// Portable float map
+    Pfm,
+    /// Radiance RGBE
+    Hdr,
+}
+
+impl From<FormatArg> for OutputFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Png => OutputFormat::Png,
+            FormatArg::Jpeg => OutputFormat::Jpeg,
+            FormatArg::Bmp => OutputFormat::Bmp,
+            FormatArg::Tga => OutputFormat::Tga,
+            FormatArg::Ppm => OutputFormat::Ppm,
+            FormatArg::PlainPpm => OutputFormat::PlainPpm,
+            FormatArg::Exr => OutputFormat::Exr,
+            FormatArg::ExrFloat => OutputFormat::ExrFloat,
+            FormatArg::Pfm => OutputFormat::Pfm,
+            FormatArg::Hdr => OutputFormat::Hdr,
+        }
+    }
+}
+
+fn parse_count<T: FromStr + From<u8> + PartialOrd>(text: &str) -> Result<T, String> {
+    match text.parse::<T>() {
+        Ok(count) if count >= T::from(1) => Ok(count),
+        Ok(_) => Err("must be at least 1".to_string()),
+        Err(_) => Err("expected a whole number".to_string()),
+    }
+}
+
+fn parse_exposure(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(stops) if stops.is_finite() => Ok(stops),
+        _ => Err("expected a number of stops".to_string()),
+    }
+}
+
+fn parse_aspect_ratio(text: &str) -> Result<f64, String> {
+    let ratio = match text.split_once(':') {
+        Some((w, h)) => {
+            let w: f64 = w
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid width '{}'", w))?;
+            let h: f64 = h
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid height '{}'", h))?;
+            w / h
+        }
+        None => text
+            .parse()
+            .map_err(|_| "expected a number or W:H".to_string())?,
+    };
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err("must be a positive number".to_string())
+    }
+}
+
+fn start_threads(threads: usize) -> Result<(), String> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|e| format!("cannot start {} worker threads: {}", threads, e))
+}
 
 fn main() {
+    let args = Args::parse();
+    let format = args.format.map(OutputFormat::from);
+    let output = if args.output == "-" {
+        Output::Stdout(format.unwrap_or(OutputFormat::Ppm))
+    } else {
+        match format {
+            Some(format) => Output::File {
+                path: args.output.clone().into(),
+                format,
+            },
+            None => Output::file(&args.output).unwrap_or_else(|| {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ValueValidation,
+                        format!(
+                            "cannot tell the image format of '{}' from its extension; use --format",
+                            args.output
+                        ),
+                    )
+                    .exit()
+            }),
+        }
+    };
+    if let Some(threads) = args.threads {
+        if let Err(message) = start_threads(threads) {
+            Args::command()
+                .error(clap::error::ErrorKind::ValueValidation, message)
+                .exit()
+        }
+    }
+
+    let (world, mut camera): (Box<dyn Hittable>, Camera) = match &args.scene {
+        Some(path) => {
+            let scene = if path.ends_with(".pbrt") {
+                m1_raytracer::pbrt::load_pbrt(path)
+            } else {
+                m1_raytracer::scene::load_scene(path)
+            };
+            match scene {
+                Ok(scene) => (Box::new(BvhAccel::from_list(scene.world)), scene.camera),
+                Err(e) => {
+                    // Parse errors already name the file and line.
+                    if e.kind() == std::io::ErrorKind::InvalidData {
+                        eprintln!("{}", e);
+                    } else {
+                        eprintln!("{}: {}", path, e);
+                    }
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let (world, camera) = builtin_scene();
+            (Box::new(world), camera)
+        }
+    };
+
+    if let Some(width) = args.width {
+        camera.img_width = width;
+    }
+    if let Some(aspect_ratio) = args.aspect_ratio {
+        camera.aspect_ratio = aspect_ratio;
+    }
+    if let Some(samples) = args.samples {
+        camera.samples_per_pixel = samples;
+    }
+    if let Some(max_depth) = args.max_depth {
+        camera.max_depth = max_depth;
+    }
+    camera.seed = args.seed;
+
+    let tone_map = ToneMap::new(args.exposure, args.tone_map.into());
+    if output.format().is_hdr()
+        && (args.exposure != 0.0 || tone_map.operator != ToneMapOperator::Clamp)
+    {
+        eprintln!("warning: --exposure and --tone-map only affect 8-bit output formats");
+    }
+    if let Err(e) = camera.render_to(world.as_ref(), &output, &tone_map) {
+        eprintln!("cannot write {}: {}", output, e);
+        std::process::exit(1);
+    }
+}
+
+// The scene rendered when no scene file is given.
+fn builtin_scene() -> (BvhAccel, Camera) {
     let mut world = HittableList::new();
     let ground_material = Arc::new(Lambertian::new(Color::new(0.4, 0.4, 0.4)));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
+    world.add(Arc::new(Plane::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
         ground_material,
     )));
 
-    // let armadillo_material =  Arc::new(Metal::new(
-    //     Color::new(0.2, 1.0, 0.2), 
-    //     0.0, 
-    // ));
-
-    // println!("Loading armadillo model...");
-    // let armadillo_triangles = load_obj_file("armadillo_lowres.obj", armadillo_material);
-    // println!("Loaded {} triangles for armadillo", armadillo_triangles.len());
-    
-    // for triangle in armadillo_triangles {
-    //     world.add(Arc::new(triangle));
-    // }
-
     let light_material = Arc::new(Lambertian::new(Color::new(1.0, 0.95, 0.9)));
     world.add(Arc::new(Sphere::new(
         Point3::new(-4.0, 6.0, -4.0),
@@ -46,35 +283,25 @@ fn main() {
         glass1.clone(),
     )));
 
-    let glass2 = Arc::new(Dielectric::new(1.8)); 
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-1.0, 1.0, 2.0),
-        0.9,
-        glass2.clone(),
+    let glass2 = Arc::new(Dielectric::new(1.8));
+    world.add(Arc::new(Csg::difference(
+        Arc::new(Sphere::new(
+            Point3::new(-1.0, 1.0, 2.0),
+            0.9,
+            glass2.clone(),
+        )),
+        Arc::new(Sphere::new(Point3::new(-1.0, 1.0, 2.0), 0.8, glass2)),
     )));
-
+    let blue_metal = Arc::new(Metal::new(Color::new(0.2, 0.5, 0.95), 0.0));
     world.add(Arc::new(Sphere::new(
-        Point3::new(-1.0, 1.0, 2.0),
-        -0.8,
-        glass2,
-    )));
-    let blue_metal = Arc::new(Metal::new(
-        Color::new(0.2, 0.5, 0.95), 
-        0.0, 
-    ));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-1.5, 0.7, 0.6), 
+        Point3::new(-1.5, 0.7, 0.6),
         0.7,
         blue_metal,
     )));
 
-   
-    let gold_metal = Arc::new(Metal::new(
-        Color::new(1.0, 0.8, 0.3),
-        0.05,
-    ));
+    let gold_metal = Arc::new(Metal::new(Color::new(1.0, 0.8, 0.3), 0.05));
     world.add(Arc::new(Sphere::new(
-        Point3::new(1.0, 0.6, 1.0), 
+        Point3::new(1.0, 0.6, 1.0),
         0.6,
         gold_metal,
     )));
@@ -93,15 +320,89 @@ fn main() {
         img_width: 1200,
         samples_per_pixel: 100,
         max_depth: 30,
-        vfov: 45.0, 
+        vfov: 45.0,
         lookfrom: Point3::new(0.0, 5.5, -1.0),
-        lookat: Point3::new(0.5, 0.0, 0.5), 
+        lookat: Point3::new(0.5, 0.0, 0.5),
         vup: Vec3::new(0.0, 1.0, 0.0),
         defocus_angle: 0.3,
         focus_dist: 7.0,
     });
 
-    println!("Rendering scene with {} objects...", world.objects.len());
-    
-    cam.render(&world);
-}
\ No newline at end of file
+    (BvhAccel::from_list(world), cam)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aspect_ratios() {
+        assert_eq!(parse_aspect_ratio("16:9"), Ok(16.0 / 9.0));
+        assert_eq!(parse_aspect_ratio("4 : 3"), Ok(4.0 / 3.0));
+        assert_eq!(parse_aspect_ratio("1.5"), Ok(1.5));
+        assert_eq!(
+            parse_aspect_ratio("wide"),
+            Err("expected a number or W:H".to_string())
+        );
+        assert_eq!(
+            parse_aspect_ratio("x:9"),
+            Err("invalid width 'x'".to_string())
+        );
+        assert_eq!(
+            parse_aspect_ratio("16:"),
+            Err("invalid height ''".to_string())
+        );
+        for bad in ["0", "-1", "16:0", "0:0", "inf", "NaN"] {
+            assert_eq!(
+                parse_aspect_ratio(bad),
+                Err("must be a positive number".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn parses_counts() {
+        assert_eq!(parse_count::<u32>("64"), Ok(64));
+        assert_eq!(parse_count::<usize>("1"), Ok(1));
+        assert_eq!(
+            parse_count::<u32>("0"),
+            Err("must be at least 1".to_string())
+        );
+        for bad in ["-1", "1.5", "", "many"] {
+            assert_eq!(
+                parse_count::<u32>(bad),
+                Err("expected a whole number".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let parse = |args: &[&str]| Args::try_parse_from(["m1_raytracer"].iter().chain(args));
+        let args = parse(&["-w", "320", "-a", "4:3", "-s", "8"]).unwrap();
+        assert_eq!(args.width, Some(320));
+        assert_eq!(args.aspect_ratio, Some(4.0 / 3.0));
+        assert_eq!(args.samples, Some(8));
+        for bad in [
+            ["-w", "0"],
+            ["-s", "-4"],
+            ["-d", "x"],
+            ["-a", "0:1"],
+            ["-j", "0"],
+        ] {
+            assert!(parse(&bad).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn reports_thread_pool_errors() {
+        // The global pool can only be built once.
+        let _ = start_threads(1);
+        let message = start_threads(1).unwrap_err();
+        assert!(
+            message.starts_with("cannot start 1 worker threads: "),
+            "{}",
+            message
+        );
+    }
+}
@@ -1,8 +1,14 @@
+use std::sync::Arc;
 
 use crate::color::Color;
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
+use crate::scene_desc::{self, MaterialDesc, SceneExporter, TextureDesc};
+use crate::texture::{SolidColor, Texture};
+use crate::thin_film::ThinFilm;
+use crate::util;
 use crate::vec3::random_unit_vector;
+use crate::vec3::Point3;
 use crate::vec3::Vec3;
 
 pub trait Material: Sync + Send {
@@ -13,6 +19,34 @@ pub trait Material: Sync + Send {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool;
+
+    /// Whether the surface is cut away at this point. Hittables test this before
+    /// accepting an intersection, so masked-out hits are skipped like misses.
+    fn masked(&self, _u: f64, _v: f64, _p: &Point3) -> bool {
+        false
+    }
+
+    /// Radiance emitted from the surface, black for everything but lights.
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::zero()
+    }
+
+    /// Whether the surface is a light, so loaders add it to `Camera::lights`.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Solid-angle density of `scatter` choosing `scattered`. Materials that report it
+    /// get light sampling mixed into their bounces; zero, the default, keeps a material
+    /// on its own sampling (mirrors, glass, layered coats).
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    /// Description of the material for a scene file, see `scene::save_scene`.
+    fn to_scene(&self, _exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        Err(scene_desc::unsupported::<Self>())
+    }
 }
 pub struct DefaultMaterial;
 
@@ -31,7 +65,7 @@ impl Material for DefaultMaterial {
             scatter_direction
         };
 
-        *scattered = Ray::new(rec.p, scatter_direction);
+        *scattered = Ray::new_with_time(rec.p, scatter_direction, r_in.time());
         *attenuation = Color::new(0.5, 0.5, 0.5);
         true
     }
@@ -44,12 +78,18 @@ impl Default for DefaultMaterial {
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Lambertian { albedo }
+        Lambertian {
+            tex: Arc::new(SolidColor::new(albedo)),
+        }
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Lambertian { tex }
     }
 }
 
@@ -69,15 +109,34 @@ impl Material for Lambertian {
             scatter_direction
         };
 
-        *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
+        *scattered = Ray::new_with_time(rec.p, scatter_direction, r_in.time());
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
         true
     }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(&scattered.direction().unit_vector());
+        cosine.max(0.0) / util::PI
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        Ok(match exporter.texture(&self.tex)? {
+            TextureDesc::Solid { color } => MaterialDesc::Lambertian {
+                albedo: Some(color),
+                texture: None,
+            },
+            texture => MaterialDesc::Lambertian {
+                albedo: None,
+                texture: Some(texture),
+            },
+        })
+    }
 }
 
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    film: Option<ThinFilm>,
 }
 
 impl Metal {
@@ -85,8 +144,15 @@ impl Metal {
         Metal {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+            film: None,
         }
     }
+
+    /// Coats the metal with a thin film, e.g. an anodized oxide layer.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
 }
 
 impl Material for Metal {
@@ -99,22 +165,47 @@ impl Material for Metal {
     ) -> bool {
         let reflected = reflect(&r_in.direction().unit_vector(), &rec.normal);
         let reflected = reflected.unit_vector() + (self.fuzz * random_unit_vector());
-        
-        *scattered = Ray::new(rec.p, reflected);
-        *attenuation = self.albedo;
-        
-        
+
+        *scattered = Ray::new_with_time(rec.p, reflected, r_in.time());
+        *attenuation = match &self.film {
+            Some(film) => {
+                let cos_theta = (-r_in.direction().unit_vector()).dot(&rec.normal);
+                film.metal_reflectance(cos_theta, 1.0, self.albedo, film.thickness_at(rec))
+            }
+            None => self.albedo,
+        };
+
         scattered.direction().dot(&rec.normal) > 0.0
     }
+
+    fn to_scene(&self, _exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        if self.film.is_some() {
+            return Err("thin-film metals cannot be saved in a scene file".to_string());
+        }
+        Ok(MaterialDesc::Metal {
+            albedo: self.albedo.into(),
+            fuzz: self.fuzz,
+        })
+    }
 }
 
 pub struct Dielectric {
     refraction_index: f64,
+    film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Dielectric { refraction_index }
+        Dielectric {
+            refraction_index,
+            film: None,
+        }
+    }
+
+    /// Coats the outer surface with a thin film, e.g. an anti-reflective layer.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
     }
 
     fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
@@ -131,8 +222,8 @@ impl Material for Dielectric {
         attenuation: &mut Color,
         scattered: &mut Ray,
     ) -> bool {
-        *attenuation = Color::new(1.0, 1.0, 1.0); 
-        
+        *attenuation = Color::new(1.0, 1.0, 1.0);
+
         let ri = if rec.front_face {
             1.0 / self.refraction_index
         } else {
@@ -144,16 +235,465 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = ri * sin_theta > 1.0;
-        
-        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > rand::random::<f64>() {
-            reflect(&unit_direction, &rec.normal)
+
+        if let Some(film) = &self.film {
+            if cannot_refract {
+                *scattered =
+                    Ray::new_with_time(rec.p, reflect(&unit_direction, &rec.normal), r_in.time());
+                return true;
+            }
+
+            let (n_outer, n_substrate) = if rec.front_face {
+                (1.0, self.refraction_index)
+            } else {
+                (self.refraction_index, 1.0)
+            };
+            let reflectance = film.dielectric_reflectance(
+                cos_theta,
+                n_outer,
+                n_substrate,
+                film.thickness_at(rec),
+            );
+            let direction = refract(&unit_direction, &rec.normal, ri);
+            scatter_thin_film(reflectance, r_in, direction, rec, attenuation, scattered);
+            return true;
+        }
+
+        let direction =
+            if cannot_refract || Self::reflectance(cos_theta, ri) > util::random_double() {
+                reflect(&unit_direction, &rec.normal)
+            } else {
+                refract(&unit_direction, &rec.normal, ri)
+            };
+
+        *scattered = Ray::new_with_time(rec.p, direction, r_in.time());
+        true
+    }
+
+    fn to_scene(&self, _exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        if self.film.is_some() {
+            return Err("thin-film dielectrics cannot be saved in a scene file".to_string());
+        }
+        Ok(MaterialDesc::Dielectric {
+            ior: self.refraction_index,
+        })
+    }
+}
+
+/// Thin-walled soap bubble: a film with air on both sides, so transmitted rays continue
+/// undeflected and only the reflection is colored by interference.
+pub struct Bubble {
+    film: ThinFilm,
+}
+
+impl Bubble {
+    pub fn new(film: ThinFilm) -> Self {
+        Bubble { film }
+    }
+}
+
+impl Material for Bubble {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
+        let thickness = self.film.thickness_at(rec);
+        let reflectance = self
+            .film
+            .dielectric_reflectance(cos_theta, 1.0, 1.0, thickness);
+
+        scatter_thin_film(
+            reflectance,
+            r_in,
+            unit_direction,
+            rec,
+            attenuation,
+            scattered,
+        );
+        true
+    }
+}
+
+/// Emissive surface for area lights. It does not scatter.
+pub struct DiffuseLight {
+    tex: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        DiffuseLight {
+            tex: Arc::new(SolidColor::new(emit)),
+        }
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        DiffuseLight { tex }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.tex.value(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        match exporter.texture(&self.tex)? {
+            TextureDesc::Solid { color } => Ok(MaterialDesc::Light { emit: color }),
+            _ => Err("textured lights cannot be saved in a scene file".to_string()),
+        }
+    }
+}
+
+/// A dielectric coat with its own roughness and absorption over an arbitrary base
+/// material, e.g. clearcoat car paint or lacquered wood. Light that enters the coat
+/// bounces between the base and the underside of the coat interface until it escapes,
+/// so energy the coat reflects back down is handed to the base again instead of lost.
+pub struct Layered {
+    base: Arc<dyn Material>,
+    ior: f64,
+    roughness: f64,
+    absorption: Color, // Absorption coefficient per unit of coat thickness
+    thickness: f64,
+}
+
+impl Layered {
+    const MAX_BOUNCES: u32 = 8;
+
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Layered {
+            base,
+            ior,
+            roughness: roughness.clamp(0.0, 1.0),
+            absorption: Color::zero(),
+            thickness: 0.0,
+        }
+    }
+
+    /// Tints the coat: light crossing it is attenuated by `exp(-absorption * path length)`.
+    pub fn with_absorption(mut self, absorption: Color, thickness: f64) -> Self {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    fn transmittance(&self, cosine: f64) -> Color {
+        if self.thickness <= 0.0 {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let distance = self.thickness / cosine.abs().max(1e-4);
+        Color::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
+    }
+
+    // Microfacet normal of the rough coat interface on the side of `normal`.
+    fn facet_normal(&self, normal: &Vec3, toward: &Vec3) -> Vec3 {
+        if self.roughness <= 0.0 {
+            return *normal;
+        }
+        let facet = (*normal + self.roughness * random_unit_vector()).unit_vector();
+        if facet.dot(toward) > 0.0 {
+            facet
         } else {
-            refract(&unit_direction, &rec.normal, ri)
-        };
+            *normal
+        }
+    }
+}
+
+impl Material for Layered {
+    fn masked(&self, u: f64, v: f64, p: &Point3) -> bool {
+        self.base.masked(u, v, p)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
 
-        *scattered = Ray::new(rec.p, direction);
+    // `scattering_pdf` keeps its zero default: the attenuation of a path through the coat
+    // is not the base's density times a fixed albedo, so light samples cannot stand in
+    // for it.
+
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let unit_direction = r_in.direction().unit_vector();
+        let facet = self.facet_normal(&rec.normal, &-unit_direction);
+        let cos_in = (-unit_direction).dot(&facet).min(1.0);
+
+        if Dielectric::reflectance(cos_in, 1.0 / self.ior) > util::random_double() {
+            *scattered = Ray::new_with_time(rec.p, reflect(&unit_direction, &facet), r_in.time());
+            *attenuation = Color::new(1.0, 1.0, 1.0);
+            return scattered.direction().dot(&rec.normal) > 0.0;
+        }
+
+        let mut direction = refract(&unit_direction, &facet, 1.0 / self.ior);
+        let mut throughput = self.transmittance(direction.unit_vector().dot(&rec.normal));
+
+        for _ in 0..Self::MAX_BOUNCES {
+            let mut base_attenuation = Color::default();
+            let mut base_scattered = Ray::default();
+            if !self.base.scatter(
+                &Ray::new_with_time(rec.p, direction, r_in.time()),
+                rec,
+                &mut base_attenuation,
+                &mut base_scattered,
+            ) {
+                return false;
+            }
+
+            throughput = throughput * base_attenuation;
+            let up = base_scattered.direction().unit_vector();
+            let cos_up = up.dot(&rec.normal);
+            if cos_up <= 0.0 {
+                // A transmissive base, e.g. a dielectric, sends the light on below the
+                // surface instead of back up through the coat.
+                *scattered = base_scattered;
+                *attenuation = throughput;
+                return true;
+            }
+            throughput = throughput * self.transmittance(cos_up);
+
+            // Leaving the coat from inside: either escape or reflect back onto the base.
+            let facet = self.facet_normal(&rec.normal, &up);
+            let cos_inside = up.dot(&facet).min(1.0);
+            let sin_inside = (1.0 - cos_inside * cos_inside).sqrt();
+            let cannot_refract = self.ior * sin_inside > 1.0;
+
+            if cannot_refract
+                || Dielectric::reflectance(cos_inside, self.ior) > util::random_double()
+            {
+                direction = reflect(&up, &facet);
+                throughput =
+                    throughput * self.transmittance(direction.unit_vector().dot(&rec.normal));
+                continue;
+            }
+
+            *scattered = Ray::new_with_time(rec.p, refract(&up, &-facet, self.ior), r_in.time());
+            *attenuation = throughput;
+            return true;
+        }
+
+        // Paths still inside the coat after `MAX_BOUNCES` are dropped, which darkens
+        // strongly reflective coats over bright bases slightly.
+        false
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        Ok(MaterialDesc::Layered {
+            base: Box::new(self.base.to_scene(exporter)?),
+            ior: self.ior,
+            roughness: self.roughness,
+            absorption: self.absorption.into(),
+            thickness: self.thickness,
+        })
+    }
+}
+
+/// Cutout transparency for foliage, fences and decals. Where the red channel of `alpha`
+/// falls below `cutoff` the surface does not exist; elsewhere `base` shades it.
+pub struct AlphaMasked {
+    base: Arc<dyn Material>,
+    alpha: Arc<dyn Texture>,
+    cutoff: f64,
+}
+
+impl AlphaMasked {
+    pub fn new(base: Arc<dyn Material>, alpha: Arc<dyn Texture>) -> Self {
+        AlphaMasked {
+            base,
+            alpha,
+            cutoff: 0.5,
+        }
+    }
+
+    pub fn with_cutoff(mut self, cutoff: f64) -> Self {
+        self.cutoff = cutoff;
+        self
+    }
+}
+
+impl Material for AlphaMasked {
+    fn masked(&self, u: f64, v: f64, p: &Point3) -> bool {
+        self.alpha.value(u, v, p).x() < self.cutoff || self.base.masked(u, v, p)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.base.scatter(r_in, rec, attenuation, scattered)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.base.scattering_pdf(r_in, rec, scattered)
+    }
+}
+
+/// Metallic-roughness surface as used by glTF. `metallic` blends from a dielectric, a
+/// glossy reflection over a diffuse base color, to a metal tinted by the base color, and
+/// `roughness` blurs the reflections. A metallic-roughness texture scales the metallic
+/// factor by its blue channel and the roughness by its green channel.
+pub struct MetallicRoughness {
+    base_color: Arc<dyn Texture>,
+    metallic: f64,
+    roughness: f64,
+    metallic_roughness: Option<Arc<dyn Texture>>,
+    emission: Option<Arc<dyn Texture>>,
+}
+
+impl MetallicRoughness {
+    const IOR: f64 = 1.5;
+
+    pub fn new(base_color: Arc<dyn Texture>, metallic: f64, roughness: f64) -> Self {
+        MetallicRoughness {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            metallic_roughness: None,
+            emission: None,
+        }
+    }
+
+    pub fn with_metallic_roughness_texture(mut self, tex: Arc<dyn Texture>) -> Self {
+        self.metallic_roughness = Some(tex);
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Arc<dyn Texture>) -> Self {
+        self.emission = Some(emission);
+        self
+    }
+}
+
+impl Material for MetallicRoughness {
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        match &self.emission {
+            Some(emission) => emission.value(u, v, p),
+            None => Color::zero(),
+        }
+    }
+
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let (mut metallic, mut roughness) = (self.metallic, self.roughness);
+        if let Some(tex) = &self.metallic_roughness {
+            let texel = tex.value(rec.u, rec.v, &rec.p);
+            metallic *= texel.z();
+            roughness *= texel.y();
+        }
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let unit_direction = r_in.direction().unit_vector();
+        // Squared roughness spreads reflections more evenly across the range.
+        let fuzz = roughness * roughness;
+
+        if util::random_double() < metallic {
+            let reflected = reflect(&unit_direction, &rec.normal) + fuzz * random_unit_vector();
+            *scattered = Ray::new_with_time(rec.p, reflected, r_in.time());
+            *attenuation = base_color;
+            return scattered.direction().dot(&rec.normal) > 0.0;
+        }
+
+        let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
+        if Dielectric::reflectance(cos_theta, 1.0 / Self::IOR) > util::random_double() {
+            let reflected = reflect(&unit_direction, &rec.normal) + fuzz * random_unit_vector();
+            *scattered = Ray::new_with_time(rec.p, reflected, r_in.time());
+            *attenuation = Color::new(1.0, 1.0, 1.0);
+            return scattered.direction().dot(&rec.normal) > 0.0;
+        }
+
+        let scatter_direction = rec.normal + random_unit_vector();
+        let scatter_direction = if scatter_direction.near_zero() {
+            rec.normal
+        } else {
+            scatter_direction
+        };
+        *scattered = Ray::new_with_time(rec.p, scatter_direction, r_in.time());
+        *attenuation = base_color;
         true
     }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<MaterialDesc, String> {
+        let base_color = match exporter.texture(&self.base_color)? {
+            TextureDesc::Solid { color } if self.metallic_roughness.is_none() => color,
+            _ => return Err("textured metallic-roughness materials cannot be saved".to_string()),
+        };
+        if self.emission.is_some() {
+            return Err("emissive metallic-roughness materials cannot be saved".to_string());
+        }
+        Ok(MaterialDesc::MetallicRoughness {
+            base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+        })
+    }
+}
+
+// Chooses between reflection and transmission with the film's mean reflectance as the
+// probability, weighting the attenuation so the spectral reflectance is preserved.
+fn scatter_thin_film(
+    reflectance: Color,
+    r_in: &Ray,
+    transmitted: Vec3,
+    rec: &HitRecord,
+    attenuation: &mut Color,
+    scattered: &mut Ray,
+) {
+    let unit_direction = &r_in.direction().unit_vector();
+    let p_reflect =
+        ((reflectance.x() + reflectance.y() + reflectance.z()) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+
+    if util::random_double() < p_reflect {
+        *scattered = Ray::new_with_time(rec.p, reflect(unit_direction, &rec.normal), r_in.time());
+        *attenuation = reflectance / p_reflect;
+    } else {
+        *scattered = Ray::new_with_time(rec.p, transmitted, r_in.time());
+        *attenuation = (Color::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - p_reflect);
+    }
 }
 
 fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
@@ -165,4 +705,123 @@ fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
     let r_out_perp = etai_over_etat * (*uv + cos_theta * *n);
     let r_out_parallel = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * *n;
     r_out_perp + r_out_parallel
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::hittable_list::HittableList;
+    use crate::interval::Interval;
+    use crate::normal_map::NormalMapped;
+    use crate::quad::Quad;
+
+    fn white() -> Arc<dyn Texture> {
+        Arc::new(SolidColor::new(Color::new(1.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn wrappers_keep_light_sampling_and_emission() {
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 1.0, 0.0),
+            ..HitRecord::default()
+        };
+        let r_in = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let scattered = Ray::new(Point3::zero(), Vec3::new(0.0, 1.0, 1.0));
+        let diffuse: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let expected = diffuse.scattering_pdf(&r_in, &rec, &scattered);
+        assert!(expected > 0.0);
+
+        // A flat normal map leaves the shading normal, and so the density, unchanged.
+        let flat = Arc::new(SolidColor::new(Color::new(0.5, 0.5, 1.0)));
+        let wrapped: [Arc<dyn Material>; 2] = [
+            Arc::new(AlphaMasked::new(diffuse.clone(), white())),
+            Arc::new(NormalMapped::normal_map(diffuse, flat)),
+        ];
+        for material in wrapped {
+            let pdf = material.scattering_pdf(&r_in, &rec, &scattered);
+            assert!((pdf - expected).abs() < 1e-12);
+        }
+
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let wrapped: [Arc<dyn Material>; 3] = [
+            Arc::new(AlphaMasked::new(light.clone(), white())),
+            Arc::new(NormalMapped::bump_map(light.clone(), white(), 1.0)),
+            Arc::new(Layered::new(light, 1.5, 0.0)),
+        ];
+        for material in wrapped {
+            assert!(material.is_emissive());
+            assert_eq!(material.emitted(0.0, 0.0, &Point3::zero()).x(), 4.0);
+        }
+    }
+
+    #[test]
+    fn alpha_masks_let_rays_through_to_what_is_behind() {
+        let diffuse: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let grey: Arc<dyn Texture> = Arc::new(SolidColor::from_rgb(0.4, 0.4, 0.4));
+        let hit_t = |front: Arc<dyn Material>| {
+            let mut world = HittableList::new();
+            for (z, mat) in [(0.0, front), (-1.0, diffuse.clone())] {
+                let (u, v) = (Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+                world.add(Arc::new(Quad::new(Point3::new(-1.0, -1.0, z), u, v, mat)));
+            }
+            let r = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let mut rec = HitRecord::default();
+            assert!(world.hit(&r, &Interval::new(0.001, f64::INFINITY), &mut rec));
+            rec.t
+        };
+
+        assert_eq!(
+            hit_t(Arc::new(AlphaMasked::new(diffuse.clone(), white()))),
+            1.0
+        );
+        assert_eq!(
+            hit_t(Arc::new(AlphaMasked::new(diffuse.clone(), grey.clone()))),
+            2.0
+        );
+        let lowered = AlphaMasked::new(diffuse.clone(), grey).with_cutoff(0.3);
+        assert_eq!(hit_t(Arc::new(lowered)), 1.0);
+    }
+
+    // Mean red attenuation of `material` over many scatters, counting absorbed paths as 0.
+    fn albedo(material: &dyn Material, r_in: &Ray, rec: &HitRecord) -> f64 {
+        let n = 10_000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            let mut attenuation = Color::default();
+            let mut scattered = Ray::default();
+            if material.scatter(r_in, rec, &mut attenuation, &mut scattered) {
+                total += attenuation.x();
+            }
+        }
+        total / n as f64
+    }
+
+    #[test]
+    fn layered_coats_conserve_energy() {
+        util::seed_rng(3);
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            front_face: true,
+            ..HitRecord::default()
+        };
+        let bases: [Arc<dyn Material>; 3] = [
+            Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))),
+            Arc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0)),
+            Arc::new(Dielectric::new(1.5)),
+        ];
+        for base in bases {
+            for direction in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -0.3)] {
+                let r_in = Ray::new(Point3::new(0.0, 0.0, 1.0), direction);
+                let rough = albedo(&Layered::new(base.clone(), 1.5, 0.3), &r_in, &rec);
+                assert!(rough <= 1.0, "albedo {}", rough);
+                // A smooth lossless coat only drops paths still bouncing after
+                // `MAX_BOUNCES`; those sent below the surface go on through the base.
+                let smooth = albedo(&Layered::new(base.clone(), 1.5, 0.0), &r_in, &rec);
+                assert!(smooth > 0.95 && smooth <= 1.0, "albedo {}", smooth);
+            }
+        }
+    }
+}
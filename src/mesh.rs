@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::triangle::{self, Triangle};
+use crate::vec3::{Point3, Vec3};
+
+/// Indexed triangle mesh with its own bottom-level BVH. Vertex attributes are stored
+/// once and shared by the faces, so a mesh is much lighter than separate `Triangle`s
+/// and can be instanced many times through `Instance`.
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,   // Per-vertex normals, or empty for flat shading
+    uvs: Vec<(f64, f64)>, // Per-vertex texture coordinates, or empty
+    indices: Vec<[usize; 3]>,
+    mat: Arc<dyn Material>,
+    bvh: Bvh,
+    source: Option<String>, // File the mesh was loaded from as-is, if any
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        let normals = if normals.len() == positions.len() {
+            normals.into_iter().map(|n| n.unit_vector()).collect()
+        } else {
+            Vec::new()
+        };
+        let uvs = if uvs.len() == positions.len() {
+            uvs
+        } else {
+            Vec::new()
+        };
+
+        let mut mesh = TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            mat,
+            bvh: Bvh::default(),
+            source: None,
+        };
+        let boxes: Vec<_> = (0..mesh.indices.len()).map(|i| mesh.face_box(i)).collect();
+        mesh.bvh = Bvh::build(&boxes);
+        mesh
+    }
+
+    /// Records the file this mesh was loaded from unchanged, so saved scenes can refer
+    /// to it instead of writing the mesh out.
+    pub fn with_source(mut self, file_path: &str) -> Self {
+        self.source = Some(file_path.to_string());
+        self
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[(f64, f64)] {
+        &self.uvs
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Expands the mesh into standalone triangles.
+    pub fn triangles(&self) -> Vec<Triangle> {
+        (0..self.indices.len())
+            .map(|i| {
+                let [a, b, c] = self.indices[i];
+                let mut tri = Triangle::new(
+                    self.positions[a],
+                    self.positions[b],
+                    self.positions[c],
+                    self.mat.clone(),
+                );
+                if !self.normals.is_empty() {
+                    tri = tri.with_normals([self.normals[a], self.normals[b], self.normals[c]]);
+                }
+                if !self.uvs.is_empty() {
+                    tri = tri.with_uvs([self.uvs[a], self.uvs[b], self.uvs[c]]);
+                }
+                tri
+            })
+            .collect()
+    }
+
+    fn face_box(&self, face: usize) -> Aabb {
+        let [a, b, c] = self.indices[face];
+        let bbox = Aabb::from_points(self.positions[a], self.positions[b]);
+        Aabb::surrounding(
+            &bbox,
+            &Aabb::from_points(self.positions[c], self.positions[c]),
+        )
+    }
+
+    fn hit_face(&self, face: usize, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let [a, b, c] = self.indices[face];
+        let (p0, p1, p2) = (&self.positions[a], &self.positions[b], &self.positions[c]);
+
+        let Some((t, u, v)) = triangle::intersect(p0, p1, p2, r) else {
+            return false;
+        };
+        if !ray_t.surronds(t) {
+            return false;
+        }
+
+        let w = 1.0 - u - v;
+        let face_uvs = if self.uvs.is_empty() {
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]
+        } else {
+            [self.uvs[a], self.uvs[b], self.uvs[c]]
+        };
+        let tex_u = w * face_uvs[0].0 + u * face_uvs[1].0 + v * face_uvs[2].0;
+        let tex_v = w * face_uvs[0].1 + u * face_uvs[1].1 + v * face_uvs[2].1;
+
+        let p = r.at(t);
+        if self.mat.masked(tex_u, tex_v, &p) {
+            return false;
+        }
+
+        let outward_normal = (*p1 - *p0).cross(&(*p2 - *p0)).unit_vector();
+        rec.t = t;
+        rec.p = p;
+        rec.set_normal_face(r, &outward_normal);
+        rec.u = tex_u;
+        rec.v = tex_v;
+
+        let shading_normal = if self.normals.is_empty() {
+            outward_normal
+        } else {
+            (w * self.normals[a] + u * self.normals[b] + v * self.normals[c]).unit_vector()
+        };
+        let (tangent, sign) = triangle::tangent_from_uvs([p0, p1, p2], face_uvs);
+        rec.set_signed_shading_frame(r, &shading_normal, &tangent, sign);
+        rec.mat = self.mat.clone();
+
+        true
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        self.bvh.hit(r, ray_t, rec, |face, interval, rec| {
+            self.hit_face(face, r, interval, rec)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Mesh {
+            path: exporter.mesh(self)?,
+            material: exporter.material(&self.mat)?,
+            smooth_angle: None,
+            transform: None,
+        })
+    }
+}
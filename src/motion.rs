@@ -0,0 +1,360 @@
+use crate::aabb::Aabb;
+use crate::transform::{Mat4, Transform};
+use crate::vec3::{Point3, Vec3};
+
+/// Piecewise-linear path through timed keyframes. Times before the first key or after
+/// the last one hold the end positions.
+#[derive(Clone, Debug)]
+pub struct MotionPath {
+    keys: Vec<(f64, Point3)>,
+}
+
+impl MotionPath {
+    pub fn fixed(p: Point3) -> Self {
+        MotionPath {
+            keys: vec![(0.0, p)],
+        }
+    }
+
+    /// Straight-line motion from `start` at time 0 to `end` at time 1.
+    pub fn linear(start: Point3, end: Point3) -> Self {
+        MotionPath {
+            keys: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    /// Path through `(time, position)` keys, in any order. Panics if `keys` is empty.
+    pub fn keyframes(mut keys: Vec<(f64, Point3)>) -> Self {
+        assert!(!keys.is_empty(), "a motion path needs at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        MotionPath { keys }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    pub fn keys(&self) -> &[(f64, Point3)] {
+        &self.keys
+    }
+
+    pub fn at(&self, time: f64) -> Point3 {
+        let (k0, k1, f) = segment(&self.keys, time);
+        let (p0, p1) = (self.keys[k0].1, self.keys[k1].1);
+        p0 + f * (p1 - p0)
+    }
+}
+
+// Keys bracketing `time` and the interpolation fraction between them.
+fn segment<T>(keys: &[(f64, T)], time: f64) -> (usize, usize, f64) {
+    let last = keys.len() - 1;
+    if time <= keys[0].0 {
+        return (0, 0, 0.0);
+    }
+    if time >= keys[last].0 {
+        return (last, last, 0.0);
+    }
+
+    let k1 = keys.partition_point(|k| k.0 <= time);
+    let k0 = k1 - 1;
+    let span = keys[k1].0 - keys[k0].0;
+    let f = if span > 0.0 {
+        (time - keys[k0].0) / span
+    } else {
+        0.0
+    };
+    (k0, k1, f)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Quaternion {
+    fn from_rotation(m: &Mat4) -> Self {
+        let m = &m.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion {
+                w: 0.25 * s,
+                x: (m[2][1] - m[1][2]) / s,
+                y: (m[0][2] - m[2][0]) / s,
+                z: (m[1][0] - m[0][1]) / s,
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quaternion {
+                w: (m[2][1] - m[1][2]) / s,
+                x: 0.25 * s,
+                y: (m[0][1] + m[1][0]) / s,
+                z: (m[0][2] + m[2][0]) / s,
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quaternion {
+                w: (m[0][2] - m[2][0]) / s,
+                x: (m[0][1] + m[1][0]) / s,
+                y: 0.25 * s,
+                z: (m[1][2] + m[2][1]) / s,
+            }
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quaternion {
+                w: (m[1][0] - m[0][1]) / s,
+                x: (m[0][2] + m[2][0]) / s,
+                y: (m[1][2] + m[2][1]) / s,
+                z: 0.25 * s,
+            }
+        };
+        q.normalized()
+    }
+
+    fn dot(&self, o: &Quaternion) -> f64 {
+        self.w * o.w + self.x * o.x + self.y * o.y + self.z * o.z
+    }
+
+    fn normalized(&self) -> Quaternion {
+        let len = self.dot(self).sqrt();
+        Quaternion {
+            w: self.w / len,
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+        }
+    }
+
+    fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion {
+        let mut other = *other;
+        let mut cos = self.dot(&other);
+        if cos < 0.0 {
+            other = Quaternion {
+                w: -other.w,
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+            };
+            cos = -cos;
+        }
+
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.clamp(-1.0, 1.0).acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Quaternion {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }
+        .normalized()
+    }
+
+    fn to_matrix(self) -> Mat4 {
+        let Quaternion { w, x, y, z } = self;
+        Mat4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+// A transform split into translation, rotation and scale/shear, which interpolate
+// without the shrinking that blending matrices directly causes mid-rotation.
+#[derive(Copy, Clone, Debug)]
+struct Decomposed {
+    translation: Vec3,
+    rotation: Quaternion,
+    scale: Mat4,
+}
+
+impl Decomposed {
+    fn new(transform: &Transform) -> Self {
+        let m = transform.matrix();
+        let translation = Vec3::new(m.m[0][3], m.m[1][3], m.m[2][3]);
+
+        let mut linear = *m;
+        for row in 0..3 {
+            linear.m[row][3] = 0.0;
+        }
+
+        // Polar decomposition: average the matrix with its inverse transpose until it
+        // converges to the closest rotation.
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let Some(inverse) = rotation.inverse() else {
+                break;
+            };
+            let inverse_transpose = inverse.transpose();
+            let mut next = rotation;
+            let mut change: f64 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    next.m[i][j] = 0.5 * (rotation.m[i][j] + inverse_transpose.m[i][j]);
+                    change = change.max((next.m[i][j] - rotation.m[i][j]).abs());
+                }
+            }
+            rotation = next;
+            if change < 1e-10 {
+                break;
+            }
+        }
+
+        // A mirroring transform converges to a rotation with determinant -1, which no
+        // quaternion represents; negate it so the scale carries the mirror instead.
+        if transform.swaps_handedness() {
+            for row in rotation.m.iter_mut().take(3) {
+                for value in row.iter_mut().take(3) {
+                    *value = -*value;
+                }
+            }
+        }
+
+        let scale = rotation.transpose() * linear;
+        Decomposed {
+            translation,
+            rotation: Quaternion::from_rotation(&rotation),
+            scale,
+        }
+    }
+
+    fn interpolate(&self, other: &Decomposed, t: f64) -> Mat4 {
+        let translation = self.translation + t * (other.translation - self.translation);
+        let rotation = self.rotation.slerp(&other.rotation, t).to_matrix();
+        let mut scale = self.scale;
+        for i in 0..3 {
+            for j in 0..3 {
+                scale.m[i][j] += t * (other.scale.m[i][j] - self.scale.m[i][j]);
+            }
+        }
+
+        let mut m = rotation * scale;
+        m.m[0][3] = translation.x();
+        m.m[1][3] = translation.y();
+        m.m[2][3] = translation.z();
+        m
+    }
+}
+
+/// Transform keyframed over time. Between keys translation and scale are blended
+/// linearly and rotation is interpolated along the shortest arc.
+#[derive(Clone, Debug)]
+pub struct AnimatedTransform {
+    keys: Vec<(f64, Transform)>,
+    decomposed: Vec<Decomposed>,
+}
+
+impl AnimatedTransform {
+    // Samples per key segment when bounding the swept volume.
+    const BOUND_STEPS: usize = 32;
+
+    pub fn fixed(transform: Transform) -> Self {
+        AnimatedTransform::keyframes(vec![(0.0, transform)])
+    }
+
+    /// Motion from `start` at time 0 to `end` at time 1.
+    pub fn linear(start: Transform, end: Transform) -> Self {
+        AnimatedTransform::keyframes(vec![(0.0, start), (1.0, end)])
+    }
+
+    /// Transform through `(time, transform)` keys, in any order. Panics if `keys` is empty.
+    pub fn keyframes(mut keys: Vec<(f64, Transform)>) -> Self {
+        assert!(
+            !keys.is_empty(),
+            "an animated transform needs at least one key"
+        );
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let decomposed = keys.iter().map(|(_, t)| Decomposed::new(t)).collect();
+        AnimatedTransform { keys, decomposed }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    pub fn keys(&self) -> &[(f64, Transform)] {
+        &self.keys
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let (k0, k1, f) = segment(&self.keys, time);
+        if k0 == k1 || f == 0.0 {
+            return self.keys[k0].1;
+        }
+
+        let m = self.decomposed[k0].interpolate(&self.decomposed[k1], f);
+        Transform::from_matrix(m).unwrap_or(self.keys[k0].1)
+    }
+
+    /// Box enclosing `bbox` over the whole motion. Rotating segments are sampled and the
+    /// result padded to cover the arcs between samples.
+    pub fn motion_bounds(&self, bbox: &Aabb) -> Aabb {
+        let mut bounds = bbox.transformed(&self.keys[0].1);
+        for k in 1..self.keys.len() {
+            let (t0, t1) = (self.keys[k - 1].0, self.keys[k].0);
+            for step in 1..=Self::BOUND_STEPS {
+                let time = t0 + (t1 - t0) * step as f64 / Self::BOUND_STEPS as f64;
+                bounds = Aabb::surrounding(&bounds, &bbox.transformed(&self.at(time)));
+            }
+        }
+
+        if self.is_moving() {
+            // Chord sag of an arc split into BOUND_STEPS pieces is under 0.5% of its radius.
+            let pad = 0.005 * (bounds.max() - bounds.min()).length();
+            bounds = Aabb::new(
+                bounds.x.expand(2.0 * pad),
+                bounds.y.expand(2.0 * pad),
+                bounds.z.expand(2.0 * pad),
+            );
+        }
+        bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &Mat4, b: &Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.m[i][j] - b.m[i][j]).abs() < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolates_mirrored_keys() {
+        let start = Transform::scaling(Vec3::new(-1.0, 1.0, 2.0)).rotate_y(30.0);
+        let end = start.translate(Vec3::new(2.0, 0.0, 0.0));
+        let motion = AnimatedTransform::linear(start, end);
+
+        let expected = start.translate(Vec3::new(1.0, 0.0, 0.0));
+        assert_near(motion.at(0.5).matrix(), expected.matrix());
+    }
+}
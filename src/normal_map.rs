@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::Point3;
+
+/// How a map perturbs the shading normal inside the hit's tangent frame.
+pub enum NormalMap {
+    /// Tangent-space normal map: red, green and blue encode the normal along the tangent,
+    /// bitangent and surface normal, remapped from [0, 1] to [-1, 1].
+    Tangent(Arc<dyn Texture>),
+    /// Scalar height map (red channel); the normal tilts against the height gradient.
+    Bump {
+        height: Arc<dyn Texture>,
+        scale: f64,
+    },
+}
+
+impl NormalMap {
+    // UV step for the finite-difference height gradient.
+    const BUMP_DELTA: f64 = 1e-3;
+
+    pub fn apply(&self, r: &Ray, rec: &mut HitRecord) {
+        let normal = match self {
+            NormalMap::Tangent(tex) => {
+                let c = tex.value(rec.u, rec.v, &rec.p);
+                let (x, y, z) = (2.0 * c.x() - 1.0, 2.0 * c.y() - 1.0, 2.0 * c.z() - 1.0);
+                x * rec.tangent + y * rec.bitangent + z * rec.normal
+            }
+            NormalMap::Bump { height, scale } => {
+                let h = |u: f64, v: f64| height.value(u, v, &rec.p).x();
+                let h0 = h(rec.u, rec.v);
+                let dhdu = (h(rec.u + Self::BUMP_DELTA, rec.v) - h0) / Self::BUMP_DELTA;
+                let dhdv = (h(rec.u, rec.v + Self::BUMP_DELTA) - h0) / Self::BUMP_DELTA;
+                rec.normal - *scale * (dhdu * rec.tangent + dhdv * rec.bitangent)
+            }
+        };
+
+        if normal.near_zero() {
+            return;
+        }
+
+        let (tangent, bitangent) = (rec.tangent, rec.bitangent);
+        rec.set_shading_normal(r, &normal);
+        rec.set_tangent(&tangent, &bitangent);
+    }
+}
+
+/// Wraps any material so it shades with a normal- or bump-mapped normal.
+pub struct NormalMapped {
+    base: Arc<dyn Material>,
+    map: NormalMap,
+}
+
+impl NormalMapped {
+    pub fn new(base: Arc<dyn Material>, map: NormalMap) -> Self {
+        NormalMapped { base, map }
+    }
+
+    pub fn normal_map(base: Arc<dyn Material>, tex: Arc<dyn Texture>) -> Self {
+        NormalMapped::new(base, NormalMap::Tangent(tex))
+    }
+
+    pub fn bump_map(base: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        NormalMapped::new(base, NormalMap::Bump { height, scale })
+    }
+}
+
+impl Material for NormalMapped {
+    fn masked(&self, u: f64, v: f64, p: &Point3) -> bool {
+        self.base.masked(u, v, p)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let mut mapped = rec.clone();
+        self.map.apply(r_in, &mut mapped);
+        self.base.scatter(r_in, &mapped, attenuation, scattered)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let mut mapped = rec.clone();
+        self.map.apply(r_in, &mut mapped);
+        self.base.scattering_pdf(r_in, &mapped, scattered)
+    }
+}
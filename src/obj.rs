@@ -1,53 +1,129 @@
-use crate::objloader::OBJLoader;
-use std::rc::Rc;
+use crate::displacement::Displacement;
+use crate::mesh::TriangleMesh;
+use crate::objloader::{FaceVertex, OBJLoader};
+use std::collections::HashMap;
+use std::io;
 use std::sync::Arc;
 
-use crate::triangle;
+use crate::material::Material;
+use crate::triangle::Triangle;
 use crate::vec3::Vec3;
-use crate::{material::Material, triangle::Triangle, vec3::Point3};
 
-pub fn load_obj_file(file_path: &str, material: Arc<dyn Material>) -> Vec<Triangle> {
-    let mut triangles: Vec<Triangle> = Vec::new();
+/// Loads an OBJ file as separate triangles, see `load_obj_mesh` for the checks it makes.
+pub fn load_obj_file(file_path: &str, material: Arc<dyn Material>) -> io::Result<Vec<Triangle>> {
+    Ok(load_obj_mesh(file_path, material)?.triangles())
+}
 
+/// Loads an OBJ file as an indexed mesh with its own BVH. Face corners that share the
+/// same position, texcoord and normal indices become a single mesh vertex.
+pub fn load_obj_mesh(file_path: &str, material: Arc<dyn Material>) -> io::Result<TriangleMesh> {
     let mut loader = OBJLoader::new();
-    loader.load_obj(file_path).unwrap();
-    println!("gaces {:?}", loader.faces[0][0].0);
-    println!("gaces {:?}", loader.faces[0][1].0);
-    println!("gaces {:?}", loader.faces[0][2].0);
-    println!("gaces {:?}", loader.vertices[loader.faces[0][0].0]);
-    println!("gaces {:?}", loader.vertices[loader.faces[0][1].0]);
-    println!("gaces {:?}", loader.vertices[loader.faces[0][2].0]);
-    println!("len verts {:?}", loader.vertices.len());
-    println!("len faces {:?}", loader.faces.len());
-
-    for face in loader.faces {
-        let f0 = face[0].0;
-        let f1 = face[1].0;
-        let f2 = face[2].0;
-        println!("{}", f0);
-        println!("{}", f1);
-        println!("{}", f2);
-        let triangle = Triangle::new(
-            Vec3::new(
-                loader.vertices[f0 - 1].0,
-                loader.vertices[f0 - 1].1,
-                loader.vertices[f0 - 1].2,
-            ),
-            Vec3::new(
-                loader.vertices[f1 - 1].0,
-                loader.vertices[f1 - 1].1,
-                loader.vertices[f1 - 1].2,
-            ),
-            Vec3::new(
-                loader.vertices[f2 - 1].0,
-                loader.vertices[f2 - 1].1,
-                loader.vertices[f2 - 1].2,
-            ),
-            Arc::clone(&material),
-        );
-
-        triangles.push(triangle);
+    loader.load_obj(file_path)?;
+
+    let has_normals = loader
+        .faces
+        .iter()
+        .flatten()
+        .all(|corner| corner.2.is_some());
+    let has_uvs = loader
+        .faces
+        .iter()
+        .flatten()
+        .all(|corner| corner.1.is_some());
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    let mut vertex_ids: HashMap<FaceVertex, usize> = HashMap::new();
+
+    let invalid = |what: &str, index: usize| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {} index {} out of range", file_path, what, index),
+        )
+    };
+
+    for face in &loader.faces {
+        let mut corner_ids = Vec::with_capacity(face.len());
+        for &corner in face {
+            if let Some(&id) = vertex_ids.get(&corner) {
+                corner_ids.push(id);
+                continue;
+            }
+
+            let (v, vt, vn) = corner;
+            let &(x, y, z) = v
+                .checked_sub(1)
+                .and_then(|i| loader.vertices.get(i))
+                .ok_or_else(|| invalid("vertex", v))?;
+            positions.push(Vec3::new(x, y, z));
+            if has_normals {
+                let n = vn.unwrap();
+                let &(x, y, z) = n
+                    .checked_sub(1)
+                    .and_then(|i| loader.normals.get(i))
+                    .ok_or_else(|| invalid("normal", n))?;
+                normals.push(Vec3::new(x, y, z));
+            }
+            if has_uvs {
+                let t = vt.unwrap();
+                let &uv = t
+                    .checked_sub(1)
+                    .and_then(|i| loader.texcoords.get(i))
+                    .ok_or_else(|| invalid("texcoord", t))?;
+                uvs.push(uv);
+            }
+
+            let id = positions.len() - 1;
+            vertex_ids.insert(corner, id);
+            corner_ids.push(id);
+        }
+
+        // Polygons are split into a fan around their first corner.
+        for k in 1..corner_ids.len().saturating_sub(1) {
+            indices.push([corner_ids[0], corner_ids[k], corner_ids[k + 1]]);
+        }
+    }
+
+    Ok(TriangleMesh::new(positions, normals, uvs, indices, material).with_source(file_path))
+}
+
+/// Loads an OBJ file as a mesh and displaces it, see `Displacement::apply`.
+pub fn load_obj_displaced(
+    file_path: &str,
+    material: Arc<dyn Material>,
+    displacement: &Displacement,
+) -> io::Result<TriangleMesh> {
+    Ok(displacement.apply(&load_obj_mesh(file_path, material)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn load(name: &str, text: &str) -> io::Result<usize> {
+        let path = std::env::temp_dir().join(format!("obj_{}.obj", name));
+        std::fs::write(&path, text).unwrap();
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let result = load_obj_file(path.to_str().unwrap(), material);
+        std::fs::remove_file(&path).unwrap();
+        result.map(|triangles| triangles.len())
+    }
+
+    #[test]
+    fn splits_polygons_into_triangles() {
+        let count = load("quad", "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        assert_eq!(count, 2);
     }
 
-    triangles
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let error = load("range", "v 0 0 0\nv 1 0 0\nf 1 2 3\n").unwrap_err();
+        assert!(error.to_string().ends_with(": vertex index 3 out of range"));
+        let error = load("normals", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//1 2//1 3//1\n").unwrap_err();
+        assert!(error.to_string().ends_with(": normal index 1 out of range"));
+    }
 }
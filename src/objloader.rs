@@ -2,11 +2,15 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+// A face corner as (vertex, texcoord, normal), 1-based as in the file.
+pub type FaceVertex = (usize, Option<usize>, Option<usize>);
+
 #[derive(Debug)]
 pub struct OBJLoader {
     pub vertices: Vec<(f64, f64, f64)>,
     pub normals: Vec<(f64, f64, f64)>,
-    pub faces: Vec<Vec<(usize, usize)>>,
+    pub texcoords: Vec<(f64, f64)>,
+    pub faces: Vec<Vec<FaceVertex>>,
 }
 
 impl OBJLoader {
@@ -14,62 +18,90 @@ impl OBJLoader {
         OBJLoader {
             vertices: Vec::new(),
             normals: Vec::new(),
+            texcoords: Vec::new(),
             faces: Vec::new(),
         }
     }
 
+    /// Reads an OBJ file. Malformed lines are an error naming the file and line;
+    /// negative (relative) face indices are resolved to absolute ones.
     pub fn load_obj(&mut self, filename: &str) -> io::Result<()> {
         let path = Path::new(filename);
-        let file = File::open(&path)?;
+        let file = File::open(path)?;
         let reader = io::BufReader::new(file);
 
-        for line in reader.lines() {
+        for (number, line) in reader.lines().enumerate() {
             let line = line?;
-            if line.starts_with("v ") {
-                self.parse_vertex(&line);
-            } else if line.starts_with("vn ") {
-                self.parse_normal(&line);
-            } else if line.starts_with("f ") {
-                self.parse_face(&line);
-            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match parts.first() {
+                Some(&"v") => self.parse_vertex(&parts),
+                Some(&"vn") => self.parse_normal(&parts),
+                Some(&"vt") => self.parse_texcoord(&parts),
+                Some(&"f") => self.parse_face(&parts),
+                _ => Ok(()),
+            };
+            parsed.map_err(|message| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", filename, number + 1, message),
+                )
+            })?;
         }
 
         Ok(())
     }
 
-    fn parse_vertex(&mut self, line: &str) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() == 4 {
-            let x = parts[1].parse::<f64>().unwrap();
-            let y = parts[2].parse::<f64>().unwrap();
-            let z = parts[3].parse::<f64>().unwrap();
-            self.vertices.push((x, y, z));
-        }
+    // Optional trailing components (vertex w, texcoord w) are ignored.
+    fn parse_vertex(&mut self, parts: &[&str]) -> Result<(), String> {
+        let [x, y, z] = parse_floats::<3>(parts)?;
+        self.vertices.push((x, y, z));
+        Ok(())
     }
 
-    fn parse_normal(&mut self, line: &str) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() == 4 {
-            let nx = parts[1].parse::<f64>().unwrap();
-            let ny = parts[2].parse::<f64>().unwrap();
-            let nz = parts[3].parse::<f64>().unwrap();
-            self.normals.push((nx, ny, nz));
-        }
+    fn parse_normal(&mut self, parts: &[&str]) -> Result<(), String> {
+        let [nx, ny, nz] = parse_floats::<3>(parts)?;
+        self.normals.push((nx, ny, nz));
+        Ok(())
+    }
+
+    // A texcoord may give only u, in which case v is 0.
+    fn parse_texcoord(&mut self, parts: &[&str]) -> Result<(), String> {
+        let (u, v) = if parts.len() == 2 {
+            (parse_floats::<1>(parts)?[0], 0.0)
+        } else {
+            let [u, v] = parse_floats::<2>(parts)?;
+            (u, v)
+        };
+        self.texcoords.push((u, v));
+        Ok(())
     }
 
-    fn parse_face(&mut self, line: &str) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        let mut face = Vec::new();
+    // Accepts the `v`, `v/vt`, `v//vn` and `v/vt/vn` corner forms.
+    fn parse_face(&mut self, parts: &[&str]) -> Result<(), String> {
+        let mut face = Vec::with_capacity(parts.len() - 1);
         for part in &parts[1..] {
-            let indices: Vec<&str> = part.split("//").collect();
-            let vertex_index = indices[0].parse::<usize>().unwrap();
-            let normal_index = indices[1].parse::<usize>().unwrap();
-            face.push((vertex_index, normal_index));
+            let mut indices = part.split('/');
+            let vertex_index = parse_index(indices.next().unwrap_or(""), self.vertices.len())?;
+            let texcoord_index = indices
+                .next()
+                .filter(|s| !s.is_empty())
+                .map(|s| parse_index(s, self.texcoords.len()))
+                .transpose()?;
+            let normal_index = indices
+                .next()
+                .filter(|s| !s.is_empty())
+                .map(|s| parse_index(s, self.normals.len()))
+                .transpose()?;
+            face.push((vertex_index, texcoord_index, normal_index));
+        }
+        if face.len() < 3 {
+            return Err(format!("face with {} corners", face.len()));
         }
         self.faces.push(face);
+        Ok(())
     }
 
-    fn display_data(&self) {
+    pub fn display_data(&self) {
         println!("Vertices:");
         for v in &self.vertices {
             println!("{:?}", v);
@@ -80,9 +112,90 @@ impl OBJLoader {
             println!("{:?}", n);
         }
 
+        println!("\nTexcoords:");
+        for t in &self.texcoords {
+            println!("{:?}", t);
+        }
+
         println!("\nFaces:");
         for f in &self.faces {
             println!("{:?}", f);
         }
     }
 }
+
+impl Default for OBJLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The first `N` numbers after the keyword of a line.
+fn parse_floats<const N: usize>(parts: &[&str]) -> Result<[f64; N], String> {
+    if parts.len() < N + 1 {
+        return Err(format!("'{}' needs {} numbers", parts[0], N));
+    }
+    let mut values = [0.0; N];
+    for (value, text) in values.iter_mut().zip(&parts[1..]) {
+        *value = text
+            .parse()
+            .map_err(|_| format!("invalid number '{}'", text))?;
+    }
+    Ok(values)
+}
+
+// A 1-based index; negative ones count back from the last of the `count` elements
+// read so far.
+fn parse_index(text: &str, count: usize) -> Result<usize, String> {
+    let index: i64 = text
+        .parse()
+        .map_err(|_| format!("invalid index '{}'", text))?;
+    let absolute = if index < 0 {
+        count as i64 + 1 + index
+    } else {
+        index
+    };
+    if absolute < 1 {
+        return Err(format!("index {} out of range", index));
+    }
+    Ok(absolute as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, text: &str) -> io::Result<OBJLoader> {
+        let path = std::env::temp_dir().join(format!("objloader_{}.obj", name));
+        std::fs::write(&path, text).unwrap();
+        let mut loader = OBJLoader::new();
+        let result = loader.load_obj(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        result.map(|_| loader)
+    }
+
+    #[test]
+    fn resolves_relative_indices() {
+        let loader = load(
+            "relative",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1 2 -1\nv 1 1 0\nf -3/-1 -2/1 -1/-1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            loader.faces,
+            vec![
+                vec![(1, None, None), (2, None, None), (3, None, None)],
+                vec![(2, Some(1), None), (3, Some(1), None), (4, Some(1), None)],
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let error = |text| load("malformed", text).unwrap_err().to_string();
+        assert!(error("v 0 0 0\nv 1 x 0\n").ends_with(":2: invalid number 'x'"));
+        assert!(error("v 0 0 0\nf 1 1 -2\n").ends_with(":2: index -2 out of range"));
+        assert!(error("v 0 0 0\nf 1 0 1\n").ends_with(":2: index 0 out of range"));
+        assert!(error("vn 0 1\n").ends_with(":1: 'vn' needs 3 numbers"));
+    }
+}
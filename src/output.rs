@@ -0,0 +1,306 @@
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use exr::prelude::{
+    f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec,
+    WritableImage,
+};
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::pnm::{PnmSubtype, SampleEncoding};
+use image::{ImageOutputFormat, Rgb};
+
+use crate::film::{Film, ToneMap};
+
+/// Image formats a render can be written in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    /// Binary PPM (P6).
+    Ppm,
+    /// Plain-text PPM (P3).
+    PlainPpm,
+    /// OpenEXR with half-float RGB and a float depth channel Z.
+    Exr,
+    /// OpenEXR with float RGB and Z.
+    ExrFloat,
+    /// Portable float map: float RGB, no tone mapping.
+    Pfm,
+    /// Radiance RGBE.
+    Hdr,
+}
+
+impl OutputFormat {
+    /// Format named by a file's extension; `.ppm` means binary PPM and `.exr` half
+    /// floats.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "bmp" => Some(OutputFormat::Bmp),
+            "tga" => Some(OutputFormat::Tga),
+            "ppm" => Some(OutputFormat::Ppm),
+            "exr" => Some(OutputFormat::Exr),
+            "pfm" => Some(OutputFormat::Pfm),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format keeps the film's full range rather than an 8-bit view.
+    pub fn is_hdr(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Exr | OutputFormat::ExrFloat | OutputFormat::Pfm | OutputFormat::Hdr
+        )
+    }
+
+    /// Encodes `film` in this format; 8-bit formats get the view `tone_map` makes.
+    pub fn encode(self, film: &Film, tone_map: &ToneMap) -> io::Result<Vec<u8>> {
+        let format = match self {
+            OutputFormat::Png => ImageOutputFormat::Png,
+            OutputFormat::Jpeg => ImageOutputFormat::Jpeg(90),
+            OutputFormat::Bmp => ImageOutputFormat::Bmp,
+            OutputFormat::Tga => ImageOutputFormat::Tga,
+            OutputFormat::Ppm => ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Binary)),
+            OutputFormat::PlainPpm => {
+                ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Ascii))
+            }
+            OutputFormat::Exr => return encode_exr(film, true),
+            OutputFormat::ExrFloat => return encode_exr(film, false),
+            OutputFormat::Pfm => return Ok(encode_pfm(film)),
+            OutputFormat::Hdr => return encode_hdr(film),
+        };
+        let mut bytes = Cursor::new(Vec::new());
+        film.to_rgb8(tone_map)
+            .write_to(&mut bytes, format)
+            .map_err(io::Error::other)?;
+        Ok(bytes.into_inner())
+    }
+}
+
+fn encode_exr(film: &Film, half: bool) -> io::Result<Vec<u8>> {
+    let channel = |i: usize| {
+        let values = film.pixels().iter().map(|pixel| pixel[i]);
+        if half {
+            FlatSamples::F16(values.map(f16::from_f32).collect())
+        } else {
+            FlatSamples::F32(values.collect())
+        }
+    };
+    let mut channels = SmallVec::new();
+    for (i, name) in ["R", "G", "B"].into_iter().enumerate() {
+        channels.push(AnyChannel::new(name, channel(i)));
+    }
+    // Depth stays full precision, as compositing tools expect.
+    channels.push(AnyChannel::new(
+        "Z",
+        FlatSamples::F32(film.depths().to_vec()),
+    ));
+    let layer = Layer::new(
+        (film.width(), film.height()),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+
+    let mut bytes = Cursor::new(Vec::new());
+    Image::from_layer(layer)
+        .write()
+        .to_buffered(&mut bytes)
+        .map_err(io::Error::other)?;
+    Ok(bytes.into_inner())
+}
+
+fn encode_pfm(film: &Film) -> Vec<u8> {
+    // A negative scale marks little-endian data; rows run bottom to top.
+    let mut bytes = format!("PF\n{} {}\n-1.0\n", film.width(), film.height()).into_bytes();
+    for row in film.pixels().chunks(film.width().max(1)).rev() {
+        for value in row.iter().flatten() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+fn encode_hdr(film: &Film) -> io::Result<Vec<u8>> {
+    let pixels: Vec<Rgb<f32>> = film.pixels().iter().map(|&pixel| Rgb(pixel)).collect();
+    let mut bytes = Vec::new();
+    HdrEncoder::new(&mut bytes)
+        .encode(&pixels, film.width(), film.height())
+        .map_err(io::Error::other)?;
+    Ok(bytes)
+}
+
+/// Where a rendered image goes.
+#[derive(Clone, Debug)]
+pub enum Output {
+    /// A file, created along with any missing parent directories.
+    File {
+        path: PathBuf,
+        format: OutputFormat,
+    },
+    Stdout(OutputFormat),
+}
+
+impl Output {
+    /// A file in the format its extension names, if it names one.
+    pub fn file(path: impl Into<PathBuf>) -> Option<Self> {
+        let path = path.into();
+        let format = OutputFormat::from_path(&path)?;
+        Some(Output::File { path, format })
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        match self {
+            Output::File { format, .. } | Output::Stdout(format) => *format,
+        }
+    }
+
+    /// Writes `film`, through `tone_map` if the format is 8-bit.
+    pub fn write(&self, film: &Film, tone_map: &ToneMap) -> io::Result<()> {
+        let bytes = self.format().encode(film, tone_map)?;
+        match self {
+            Output::File { path, .. } => {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, bytes)
+            }
+            Output::Stdout(_) => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&bytes)?;
+                stdout.flush()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Output::File { path, .. } => write!(f, "{}", path.display()),
+            Output::Stdout(_) => write!(f, "standard output"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use exr::prelude::{ReadChannels, ReadLayers};
+
+    // A 3x2 film whose pixels all differ, with a depth per pixel.
+    fn film() -> Film {
+        let mut film = Film::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                let i = (3 * y + x) as f64;
+                film.set_color(x, y, Color::new(i, 0.5 * i, 0.25));
+                film.set_depth(x, y, 10.0 + i as f32);
+            }
+        }
+        film
+    }
+
+    #[test]
+    fn picks_formats_from_extensions() {
+        let format = |name: &str| OutputFormat::from_path(Path::new(name));
+        assert_eq!(format("out/render.PNG"), Some(OutputFormat::Png));
+        assert_eq!(format("render.jpeg"), Some(OutputFormat::Jpeg));
+        assert_eq!(format("render.ppm"), Some(OutputFormat::Ppm));
+        assert_eq!(format("render.exr"), Some(OutputFormat::Exr));
+        assert_eq!(format("render.txt"), None);
+        assert_eq!(format("render"), None);
+        assert!(OutputFormat::Pfm.is_hdr() && !OutputFormat::Png.is_hdr());
+    }
+
+    #[test]
+    fn writes_files_into_new_directories() {
+        let dir = std::env::temp_dir().join("output_test_dir");
+        let path = dir.join("nested").join("render.png");
+        let output = Output::file(&path).unwrap();
+        output.write(&film(), &ToneMap::default()).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(image.dimensions(), (3, 2));
+        // Black stays black and values past 1 clip to white.
+        assert_eq!(image.get_pixel(0, 0).0[0], 0);
+        assert_eq!(
+            image.get_pixel(2, 1).0,
+            [255, 255, image.get_pixel(0, 0).0[2]]
+        );
+    }
+
+    #[test]
+    fn pfm_is_little_endian_from_the_bottom_row() {
+        let bytes = OutputFormat::Pfm
+            .encode(&film(), &ToneMap::default())
+            .unwrap();
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(values.len(), 3 * 2 * 3);
+        // The first pixel written is the bottom-left one, (0, 1).
+        assert_eq!(values[..6], [3.0, 1.5, 0.25, 4.0, 2.0, 0.25]);
+        assert_eq!(values[9..12], [0.0, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn exr_keeps_colors_and_depth() {
+        let film = film();
+        for format in [OutputFormat::Exr, OutputFormat::ExrFloat] {
+            let bytes = format.encode(&film, &ToneMap::default()).unwrap();
+            let image = exr::prelude::read()
+                .no_deep_data()
+                .largest_resolution_level()
+                .all_channels()
+                .first_valid_layer()
+                .all_attributes()
+                .from_buffered(Cursor::new(bytes))
+                .unwrap();
+            let layer = &image.layer_data;
+            assert_eq!((layer.size.x(), layer.size.y()), (3, 2));
+            for channel in &layer.channel_data.list {
+                let name = channel.name.to_string();
+                let half = matches!(channel.sample_data, FlatSamples::F16(_));
+                assert_eq!(half, format == OutputFormat::Exr && name != "Z", "{}", name);
+                let values: Vec<f32> = channel.sample_data.values_as_f32().collect();
+                let expected: Vec<f32> = match name.as_str() {
+                    "Z" => film.depths().to_vec(),
+                    "R" => film.pixels().iter().map(|p| p[0]).collect(),
+                    "G" => film.pixels().iter().map(|p| p[1]).collect(),
+                    "B" => film.pixels().iter().map(|p| p[2]).collect(),
+                    _ => panic!("unexpected channel {}", name),
+                };
+                assert_eq!(values, expected, "{}", name);
+            }
+            assert_eq!(layer.channel_data.list.len(), 4);
+        }
+    }
+
+    #[test]
+    fn hdr_round_trips_through_rgbe() {
+        let film = film();
+        let bytes = OutputFormat::Hdr
+            .encode(&film, &ToneMap::default())
+            .unwrap();
+        assert!(bytes.starts_with(b"#?RADIANCE"));
+        let decoder = image::codecs::hdr::HdrDecoder::new(Cursor::new(bytes)).unwrap();
+        let metadata = decoder.metadata();
+        assert_eq!((metadata.width, metadata.height), (3, 2));
+        let pixels = decoder.read_image_hdr().unwrap();
+        for (decoded, pixel) in pixels.iter().zip(film.pixels()) {
+            assert_eq!(decoded.0, *pixel);
+        }
+    }
+}
@@ -0,0 +1,1439 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bvh::BvhAccel;
+use crate::camera::{Background, Camera, CameraParams};
+use crate::color::Color;
+use crate::disk::Disk;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Layered, Material, Metal};
+use crate::mesh::TriangleMesh;
+use crate::ply::load_ply;
+use crate::quadric::Cylinder;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::subdivision::{ControlMesh, SubdivisionScheme};
+use crate::texture::{ImageTexture, ScaledTexture, SolidColor, Texture, UvCheckerTexture};
+use crate::transform::{Mat4, Transform};
+use crate::util::PI;
+use crate::vec3::{Point3, Vec3};
+
+// Point lights become small spheres of this fraction of the scene's radius, and distant
+// lights become disks of the scene's radius this many radii away, as for glTF.
+const LIGHT_RADIUS_FRACTION: f64 = 0.01;
+const SUN_DISTANCE: f64 = 100.0;
+// Include chains longer than this are assumed to be runaway recursion.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Loads a scene in the subset of the PBRT-v3/v4 format that maps onto this renderer:
+///
+/// - `Camera "perspective"` with `fov`, `lensradius` and `focaldistance`, `Film` resolution,
+///   `Sampler` pixel samples and `Integrator` max depth;
+/// - transforms, `AttributeBegin`/`End`, named coordinate systems, `ObjectBegin`/`End` and
+///   `ObjectInstance`, `Include` and `Import`;
+/// - `sphere`, `disk`, `cylinder`, `trianglemesh`, `bilinearmesh`, `loopsubdiv` and `plymesh`
+///   shapes;
+/// - diffuse, plastic and coated, conductor, mirror and dielectric materials with
+///   `imagemap`, `checkerboard`, `scale` and `constant` color textures;
+/// - diffuse area lights, point and spot lights as small emissive spheres (spot cones
+///   are ignored), distant lights as far emissive disks, and infinite lights as a solid
+///   background of their (average) radiance.
+///
+/// PBRT is left-handed, so the scene is mirrored in x to render the same image. Anything
+/// else is skipped with a warning on stderr. Errors point at the file and line.
+pub fn load_pbrt(file_path: &str) -> io::Result<Scene> {
+    let base = Path::new(file_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let mut loader = Loader::new(base);
+    loader.include(file_path, None)?;
+    loader.run()?;
+    loader.finish()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Str(String),
+    Num(f64),
+    Open,
+    Close,
+}
+
+// Where a token came from: an index into the loader's file names and a line number.
+type Location = (usize, usize);
+
+struct Token {
+    kind: TokenKind,
+    location: Location,
+}
+
+fn tokenize(source: &str, file: usize) -> Result<Vec<Token>, (usize, String)> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    let mut line = 1;
+    while let Some(&(start, c)) = chars.peek() {
+        let kind = match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '#' => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                continue;
+            }
+            '[' => {
+                chars.next();
+                TokenKind::Open
+            }
+            ']' => {
+                chars.next();
+                TokenKind::Close
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, c)) if c != '\n' => text.push(c),
+                            _ => return Err((line, "unterminated string".to_string())),
+                        },
+                        Some((_, '\n')) | None => {
+                            return Err((line, "unterminated string".to_string()))
+                        }
+                        Some((_, c)) => text.push(c),
+                    }
+                }
+                TokenKind::Str(text)
+            }
+            _ => {
+                let mut end = start;
+                while let Some((i, c)) = chars
+                    .next_if(|&(_, c)| !c.is_whitespace() && !matches!(c, '"' | '[' | ']' | '#'))
+                {
+                    end = i + c.len_utf8();
+                }
+                let word = &source[start..end];
+                match word.parse::<f64>() {
+                    Ok(value) => TokenKind::Num(value),
+                    Err(_) => TokenKind::Word(word.to_string()),
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            location: (file, line),
+        });
+    }
+    Ok(tokens)
+}
+
+// A parameter such as `"float radius" 2` or `"rgb Kd" [0.5 0.5 0.5]`. Numbers and
+// strings (including bools) are kept apart.
+struct Param {
+    ty: String,
+    name: String,
+    nums: Vec<f64>,
+    strs: Vec<String>,
+}
+
+#[derive(Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|p| p.name == name)
+    }
+
+    fn float(&self, name: &str, default: f64) -> f64 {
+        self.get(name)
+            .and_then(|p| p.nums.first().copied())
+            .unwrap_or(default)
+    }
+
+    fn floats(&self, name: &str) -> &[f64] {
+        self.get(name).map_or(&[], |p| &p.nums)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.get(name)
+            .filter(|p| p.ty == "string")
+            .and_then(|p| p.strs.first())
+            .map(String::as_str)
+    }
+
+    fn strings(&self, name: &str) -> &[String] {
+        self.get(name).map_or(&[], |p| &p.strs)
+    }
+
+    fn texture(&self, name: &str) -> Option<&str> {
+        self.get(name)
+            .filter(|p| p.ty == "texture")
+            .and_then(|p| p.strs.first())
+            .map(String::as_str)
+    }
+
+    // A spectrum as RGB. Sampled spectra are reduced to their average, blackbodies to
+    // their normalized color, and named spectra to a representative color.
+    fn color(&self, name: &str) -> Option<Color> {
+        let p = self.get(name)?;
+        match p.ty.as_str() {
+            "rgb" | "color" if p.nums.len() >= 3 => {
+                Some(Color::new(p.nums[0], p.nums[1], p.nums[2]))
+            }
+            "blackbody" => p.nums.first().map(|&kelvin| {
+                // pbrt-v3 takes a scale after the temperature.
+                blackbody(kelvin) * p.nums.get(1).copied().unwrap_or(1.0)
+            }),
+            "float" => p.nums.first().map(|&v| Color::new(v, v, v)),
+            "spectrum" => match p.strs.first() {
+                Some(named) => Some(named_spectrum_color(named)),
+                None if p.nums.len() >= 2 => {
+                    let values: Vec<f64> = p.nums.iter().skip(1).step_by(2).copied().collect();
+                    let average = values.iter().sum::<f64>() / values.len() as f64;
+                    Some(Color::new(average, average, average))
+                }
+                None => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Normalized color of a blackbody at `kelvin`, from Planck's law at representative red,
+// green and blue wavelengths.
+fn blackbody(kelvin: f64) -> Color {
+    if kelvin <= 0.0 {
+        return Color::zero();
+    }
+    let planck = |nm: f64| {
+        let (c, h, kb) = (299792458.0, 6.62606957e-34, 1.3806488e-23);
+        let l = nm * 1e-9;
+        (2.0 * h * c * c) / (l.powi(5) * ((h * c / (l * kb * kelvin)).exp() - 1.0))
+    };
+    let (r, g, b) = (planck(610.0), planck(550.0), planck(465.0));
+    let max = r.max(g).max(b);
+    Color::new(r / max, g / max, b / max)
+}
+
+// Normal-incidence reflectance of the metals pbrt names in its spectra (`metal-Cu-eta`,
+// ...), defaulting to copper as pbrt does.
+fn metal_color(name: &str) -> Color {
+    let metal = name
+        .strip_prefix("metal-")
+        .and_then(|rest| rest.split('-').next())
+        .unwrap_or("Cu");
+    match metal {
+        "Au" => Color::new(1.0, 0.766, 0.336),
+        "Ag" => Color::new(0.972, 0.960, 0.915),
+        "Al" => Color::new(0.913, 0.922, 0.924),
+        "CuZn" => Color::new(0.910, 0.778, 0.423),
+        _ => Color::new(0.955, 0.638, 0.538),
+    }
+}
+
+// Index of refraction of pbrt's named glasses, 1.5 for anything else.
+fn glass_ior(name: &str) -> f64 {
+    match name {
+        "glass-BK7" => 1.5168,
+        "glass-BAF10" => 1.6700,
+        "glass-FK51A" => 1.4866,
+        "glass-LASF9" => 1.8503,
+        "glass-F5" => 1.6034,
+        "glass-F10" => 1.7283,
+        "glass-F11" => 1.7847,
+        _ => 1.5,
+    }
+}
+
+fn named_spectrum_color(name: &str) -> Color {
+    if name.starts_with("metal-") {
+        metal_color(name)
+    } else {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+// Attributes saved and restored by `AttributeBegin`/`AttributeEnd`. A `None` material
+// is pbrt's "interface", which only bounds media and is not rendered.
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Mat4,
+    material: Option<Arc<dyn Material>>,
+    area_light: Option<Color>,
+    reverse_orientation: bool,
+}
+
+enum Light {
+    Point { position: Point3, intensity: Color },
+    Distant { direction: Vec3, radiance: Color },
+}
+
+struct Loader {
+    files: Vec<String>,
+    included_from: Vec<Option<usize>>, // File whose Include read each file
+    base: PathBuf,
+    tokens: Vec<Token>, // Remaining tokens, next one last
+    warned: HashSet<String>,
+
+    state: GraphicsState,
+    attribute_stack: Vec<GraphicsState>,
+    transform_stack: Vec<Mat4>,
+    transform_start: bool, // Whether transform directives affect the start of the shutter
+    coordinate_systems: HashMap<String, Mat4>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Option<Arc<dyn Material>>>,
+    objects: HashMap<String, Arc<dyn Hittable>>,
+    current_object: Option<(String, HittableList)>,
+
+    shapes: HittableList,
+    lights: Vec<Light>,
+    emitters: HittableList, // Area-lit spheres and disks, for the camera to sample
+    background: Color,
+
+    camera_from_world: Mat4,
+    camera_params: Params,
+    resolution: (usize, usize),
+    samples_per_pixel: u32,
+    max_depth: u32,
+}
+
+impl Loader {
+    fn new(base: PathBuf) -> Self {
+        Loader {
+            files: Vec::new(),
+            included_from: Vec::new(),
+            base,
+            tokens: Vec::new(),
+            warned: HashSet::new(),
+            state: GraphicsState {
+                ctm: Mat4::IDENTITY,
+                material: Some(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))),
+                area_light: None,
+                reverse_orientation: false,
+            },
+            attribute_stack: Vec::new(),
+            transform_stack: Vec::new(),
+            transform_start: true,
+            coordinate_systems: HashMap::new(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            objects: HashMap::new(),
+            current_object: None,
+            shapes: HittableList::new(),
+            lights: Vec::new(),
+            emitters: HittableList::new(),
+            background: Color::zero(),
+            camera_from_world: Mat4::IDENTITY,
+            camera_params: Params::default(),
+            resolution: (1280, 720),
+            samples_per_pixel: 16,
+            max_depth: 5,
+        }
+    }
+
+    fn error(&self, location: Location, message: impl std::fmt::Display) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", self.files[location.0], location.1, message),
+        )
+    }
+
+    // Prints each distinct warning once.
+    fn warn(&mut self, location: Location, message: String) {
+        if self.warned.insert(message.clone()) {
+            eprintln!(
+                "{}:{}: warning: {}",
+                self.files[location.0], location.1, message
+            );
+        }
+    }
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.base.join(path)
+    }
+
+    // Queues the tokens of a file so they are read next.
+    fn include(&mut self, path: &str, from: Option<Location>) -> io::Result<()> {
+        if let Some(location) = from {
+            let canonical = fs::canonicalize(path).ok();
+            let mut depth = 0;
+            let mut including = Some(location.0);
+            while let Some(file) = including {
+                if canonical.is_some() && fs::canonicalize(&self.files[file]).ok() == canonical {
+                    return Err(self.error(location, format!("{} includes itself", path)));
+                }
+                depth += 1;
+                including = self.included_from[file];
+            }
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(self.error(location, "includes nested too deeply"));
+            }
+        }
+
+        let source = fs::read_to_string(path).map_err(|e| match from {
+            Some(location) => self.error(location, format!("{}: {}", path, e)),
+            None => e,
+        })?;
+        let file = self.files.len();
+        self.files.push(path.to_string());
+        self.included_from.push(from.map(|location| location.0));
+        let tokens =
+            tokenize(&source, file).map_err(|(line, message)| self.error((file, line), message))?;
+        self.tokens.extend(tokens.into_iter().rev());
+        Ok(())
+    }
+
+    fn next_token(&mut self, location: Location) -> io::Result<Token> {
+        self.tokens
+            .pop()
+            .ok_or_else(|| self.error(location, "unexpected end of file"))
+    }
+
+    fn string(&mut self, location: Location) -> io::Result<String> {
+        let token = self.next_token(location)?;
+        match token.kind {
+            TokenKind::Str(s) => Ok(s),
+            _ => Err(self.error(token.location, "expected a quoted string")),
+        }
+    }
+
+    // Reads `count` numbers, bare or in brackets.
+    fn numbers(&mut self, count: usize, location: Location) -> io::Result<Vec<f64>> {
+        let bracketed = matches!(self.tokens.last(), Some(t) if t.kind == TokenKind::Open);
+        if bracketed {
+            self.tokens.pop();
+        }
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            let token = self.next_token(location)?;
+            match token.kind {
+                TokenKind::Num(value) => values.push(value),
+                _ => return Err(self.error(token.location, format!("expected {} numbers", count))),
+            }
+        }
+        if bracketed {
+            let token = self.next_token(location)?;
+            if token.kind != TokenKind::Close {
+                return Err(self.error(token.location, "expected ']'"));
+            }
+        }
+        Ok(values)
+    }
+
+    fn params(&mut self) -> io::Result<Params> {
+        let mut params = Vec::new();
+        while let Some(Token {
+            kind: TokenKind::Str(_),
+            ..
+        }) = self.tokens.last()
+        {
+            let token = self.tokens.pop().unwrap();
+            let TokenKind::Str(declaration) = token.kind else {
+                unreachable!()
+            };
+            let words: Vec<&str> = declaration.split_whitespace().collect();
+            let [ty, name] = words[..] else {
+                return Err(self.error(
+                    token.location,
+                    format!("malformed parameter declaration \"{}\"", declaration),
+                ));
+            };
+            let mut param = Param {
+                ty: ty.to_string(),
+                name: name.to_string(),
+                nums: Vec::new(),
+                strs: Vec::new(),
+            };
+
+            let value = self.next_token(token.location)?;
+            let values = if value.kind == TokenKind::Open {
+                let mut values = Vec::new();
+                loop {
+                    let value = self.next_token(token.location)?;
+                    if value.kind == TokenKind::Close {
+                        break values;
+                    }
+                    values.push(value);
+                }
+            } else {
+                vec![value]
+            };
+            for value in values {
+                match value.kind {
+                    TokenKind::Num(n) => param.nums.push(n),
+                    TokenKind::Str(s) => param.strs.push(s),
+                    TokenKind::Word(w) if w == "true" || w == "false" => param.strs.push(w),
+                    _ => {
+                        return Err(self.error(
+                            value.location,
+                            format!("bad value for parameter \"{}\"", name),
+                        ))
+                    }
+                }
+            }
+            params.push(param);
+        }
+        Ok(Params(params))
+    }
+
+    // Skips the arguments of a directive that is not supported.
+    fn skip_arguments(&mut self) {
+        while let Some(token) = self.tokens.last() {
+            match &token.kind {
+                TokenKind::Word(w) if w != "true" && w != "false" => break,
+                _ => {
+                    self.tokens.pop();
+                }
+            }
+        }
+    }
+
+    fn apply_transform(&mut self, m: Mat4) {
+        if self.transform_start {
+            self.state.ctm = self.state.ctm * m;
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(token) = self.tokens.pop() {
+            let location = token.location;
+            let TokenKind::Word(directive) = token.kind else {
+                return Err(self.error(location, "expected a directive"));
+            };
+            self.directive(&directive, location)?;
+        }
+        if let Some((name, _)) = &self.current_object {
+            let message = format!("object \"{}\" is missing ObjectEnd", name);
+            return Err(self.error((0, 1), message));
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, location: Location) -> io::Result<()> {
+        match directive {
+            "Identity" => {
+                if self.transform_start {
+                    self.state.ctm = Mat4::IDENTITY;
+                }
+            }
+            "Translate" => {
+                let v = self.numbers(3, location)?;
+                let t = Transform::translation(Vec3::new(v[0], v[1], v[2]));
+                self.apply_transform(*t.matrix());
+            }
+            "Scale" => {
+                let v = self.numbers(3, location)?;
+                self.apply_transform(scale_matrix(v[0], v[1], v[2]));
+            }
+            "Rotate" => {
+                let v = self.numbers(4, location)?;
+                let t = Transform::rotation(Vec3::new(v[1], v[2], v[3]), v[0]);
+                self.apply_transform(*t.matrix());
+            }
+            "LookAt" => {
+                let v = self.numbers(9, location)?;
+                let m = look_at(
+                    Point3::new(v[0], v[1], v[2]),
+                    Point3::new(v[3], v[4], v[5]),
+                    Vec3::new(v[6], v[7], v[8]),
+                )
+                .ok_or_else(|| self.error(location, "degenerate LookAt"))?;
+                self.apply_transform(m);
+            }
+            "Transform" | "ConcatTransform" => {
+                // The 16 values are column-major.
+                let v = self.numbers(16, location)?;
+                let mut m = [[0.0; 4]; 4];
+                for (i, row) in m.iter_mut().enumerate() {
+                    for (j, value) in row.iter_mut().enumerate() {
+                        *value = v[4 * j + i];
+                    }
+                }
+                if directive == "Transform" {
+                    if self.transform_start {
+                        self.state.ctm = Mat4::new(m);
+                    }
+                } else {
+                    self.apply_transform(Mat4::new(m));
+                }
+            }
+            "CoordinateSystem" => {
+                let name = self.string(location)?;
+                self.coordinate_systems.insert(name, self.state.ctm);
+            }
+            "CoordSysTransform" => {
+                let name = self.string(location)?;
+                match self.coordinate_systems.get(&name) {
+                    Some(&m) => self.state.ctm = m,
+                    None => self.warn(location, format!("unknown coordinate system \"{}\"", name)),
+                }
+            }
+            "ActiveTransform" => {
+                let token = self.next_token(location)?;
+                // Only the start of the shutter is rendered; motion is ignored.
+                self.transform_start = match token.kind {
+                    TokenKind::Word(w) => w != "EndTime",
+                    _ => {
+                        return Err(self.error(token.location, "expected StartTime, EndTime or All"))
+                    }
+                };
+            }
+            "ReverseOrientation" => {
+                self.state.reverse_orientation = !self.state.reverse_orientation;
+            }
+
+            "Camera" => {
+                let ty = self.string(location)?;
+                let params = self.params()?;
+                if ty != "perspective" {
+                    self.warn(location, format!("{} camera rendered as perspective", ty));
+                }
+                self.camera_from_world = self.state.ctm;
+                if let Some(world_from_camera) = self.state.ctm.inverse() {
+                    self.coordinate_systems
+                        .insert("camera".to_string(), world_from_camera);
+                }
+                self.camera_params = params;
+            }
+            "Film" => {
+                self.string(location)?;
+                let params = self.params()?;
+                self.resolution = (
+                    params.float("xresolution", 1280.0).max(1.0) as usize,
+                    params.float("yresolution", 720.0).max(1.0) as usize,
+                );
+            }
+            "Sampler" => {
+                self.string(location)?;
+                let params = self.params()?;
+                self.samples_per_pixel = params.float("pixelsamples", 16.0).max(1.0) as u32;
+            }
+            "Integrator" => {
+                self.string(location)?;
+                let params = self.params()?;
+                // pbrt counts bounces; a depth of 1 here is camera rays only.
+                self.max_depth = params.float("maxdepth", 5.0).max(0.0) as u32 + 1;
+            }
+            "WorldBegin" => {
+                self.state.ctm = Mat4::IDENTITY;
+                self.coordinate_systems
+                    .insert("world".to_string(), Mat4::IDENTITY);
+            }
+            "WorldEnd" => {}
+
+            "AttributeBegin" => self.attribute_stack.push(self.state.clone()),
+            "AttributeEnd" => {
+                self.state = self
+                    .attribute_stack
+                    .pop()
+                    .ok_or_else(|| self.error(location, "unmatched AttributeEnd"))?;
+            }
+            "TransformBegin" => self.transform_stack.push(self.state.ctm),
+            "TransformEnd" => {
+                self.state.ctm = self
+                    .transform_stack
+                    .pop()
+                    .ok_or_else(|| self.error(location, "unmatched TransformEnd"))?;
+            }
+
+            "Texture" => {
+                let name = self.string(location)?;
+                let ty = self.string(location)?;
+                let class = self.string(location)?;
+                let params = self.params()?;
+                // Float textures only feed parameters that are read as constants.
+                if ty == "spectrum" || ty == "color" {
+                    let texture = self.make_texture(&class, &params, location)?;
+                    self.textures.insert(name, texture);
+                }
+            }
+            "Material" => {
+                let ty = self.string(location)?;
+                let params = self.params()?;
+                self.state.material = self.make_material(&ty, &params, location);
+            }
+            "MakeNamedMaterial" => {
+                let name = self.string(location)?;
+                let params = self.params()?;
+                let ty = params.string("type").unwrap_or("diffuse").to_string();
+                let material = self.make_material(&ty, &params, location);
+                self.materials.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let name = self.string(location)?;
+                self.state.material = self.materials.get(&name).cloned().ok_or_else(|| {
+                    self.error(location, format!("unknown material \"{}\"", name))
+                })?;
+            }
+
+            "LightSource" => {
+                let ty = self.string(location)?;
+                let params = self.params()?;
+                self.add_light(&ty, &params, location);
+            }
+            "AreaLightSource" => {
+                let ty = self.string(location)?;
+                let params = self.params()?;
+                if ty != "diffuse" {
+                    self.warn(location, format!("unsupported area light \"{}\"", ty));
+                }
+                let radiance = params.color("L").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                self.state.area_light = Some(params.float("scale", 1.0) * radiance);
+            }
+            "Shape" => {
+                let ty = self.string(location)?;
+                let params = self.params()?;
+                if let Some(shape) = self.make_shape(&ty, &params, location)? {
+                    match &mut self.current_object {
+                        Some((_, list)) => list.add(shape),
+                        None => {
+                            if self.state.area_light.is_some()
+                                && matches!(ty.as_str(), "sphere" | "disk")
+                            {
+                                self.emitters.add(shape.clone());
+                            }
+                            self.shapes.add(shape)
+                        }
+                    }
+                }
+            }
+
+            "ObjectBegin" => {
+                let name = self.string(location)?;
+                if self.current_object.is_some() {
+                    return Err(self.error(location, "ObjectBegin inside another object"));
+                }
+                self.attribute_stack.push(self.state.clone());
+                self.current_object = Some((name, HittableList::new()));
+            }
+            "ObjectEnd" => {
+                let (name, list) = self
+                    .current_object
+                    .take()
+                    .ok_or_else(|| self.error(location, "ObjectEnd outside an object"))?;
+                self.objects
+                    .insert(name, Arc::new(BvhAccel::from_list(list)));
+                if let Some(state) = self.attribute_stack.pop() {
+                    self.state = state;
+                }
+            }
+            "ObjectInstance" => {
+                let name = self.string(location)?;
+                let object =
+                    self.objects.get(&name).cloned().ok_or_else(|| {
+                        self.error(location, format!("unknown object \"{}\"", name))
+                    })?;
+                // The object's shapes are already in render space.
+                let m = mirror() * self.state.ctm * mirror();
+                match Transform::from_matrix(m) {
+                    Some(t) => self.shapes.add(Arc::new(Instance::new(object, t))),
+                    None => self.warn(
+                        location,
+                        "skipping instance with a singular transform".to_string(),
+                    ),
+                }
+            }
+
+            "Include" | "Import" => {
+                let path = self.string(location)?;
+                let path = self.resolve(&path);
+                self.include(&path.to_string_lossy(), Some(location))?;
+            }
+
+            "Option" | "ColorSpace" | "PixelFilter" | "Accelerator" | "Attribute"
+            | "TransformTimes" | "MakeNamedMedium" | "MediumInterface" => self.skip_arguments(),
+            _ => {
+                self.warn(
+                    location,
+                    format!("ignoring unsupported directive {}", directive),
+                );
+                self.skip_arguments();
+            }
+        }
+        Ok(())
+    }
+
+    // Texture for a spectrum parameter that may name a texture or give a color.
+    fn color_texture(
+        &mut self,
+        params: &Params,
+        name: &str,
+        default: Color,
+        location: Location,
+    ) -> Arc<dyn Texture> {
+        if let Some(texture) = params.texture(name) {
+            if let Some(texture) = self.textures.get(texture) {
+                return texture.clone();
+            }
+            self.warn(location, format!("unknown texture \"{}\"", texture));
+        }
+        Arc::new(SolidColor::new(params.color(name).unwrap_or(default)))
+    }
+
+    fn make_texture(
+        &mut self,
+        class: &str,
+        params: &Params,
+        location: Location,
+    ) -> io::Result<Arc<dyn Texture>> {
+        let texture: Arc<dyn Texture> = match class {
+            "constant" => self.color_texture(params, "value", Color::new(1.0, 1.0, 1.0), location),
+            "imagemap" => {
+                let filename = params
+                    .string("filename")
+                    .ok_or_else(|| self.error(location, "imagemap without a filename"))?;
+                let path = self.resolve(filename);
+                let image = ImageTexture::load(&path.to_string_lossy())
+                    .map_err(|e| self.error(location, format!("{}: {}", path.display(), e)))?;
+                let scale = params.float("scale", 1.0);
+                if scale != 1.0 {
+                    Arc::new(ScaledTexture::new(
+                        Arc::new(image.with_repeat()),
+                        Color::new(scale, scale, scale),
+                    ))
+                } else {
+                    Arc::new(image.with_repeat())
+                }
+            }
+            "checkerboard" => {
+                let even = self.color_texture(params, "tex1", Color::new(1.0, 1.0, 1.0), location);
+                let odd = self.color_texture(params, "tex2", Color::zero(), location);
+                Arc::new(UvCheckerTexture::new(
+                    params.float("uscale", 1.0),
+                    params.float("vscale", 1.0),
+                    even,
+                    odd,
+                ))
+            }
+            "scale" => {
+                let tex = self.color_texture(params, "tex", Color::new(1.0, 1.0, 1.0), location);
+                let scale = params.color("scale").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                Arc::new(ScaledTexture::new(tex, scale))
+            }
+            _ => {
+                self.warn(location, format!("unsupported texture \"{}\"", class));
+                Arc::new(SolidColor::new(Color::new(0.5, 0.5, 0.5)))
+            }
+        };
+        Ok(texture)
+    }
+
+    fn make_material(
+        &mut self,
+        ty: &str,
+        params: &Params,
+        location: Location,
+    ) -> Option<Arc<dyn Material>> {
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let roughness = |default: f64| {
+            let u = params.float("uroughness", params.float("roughness", default));
+            let v = params.float("vroughness", params.float("roughness", default));
+            0.5 * (u + v)
+        };
+        let material: Arc<dyn Material> = match ty {
+            "" | "none" | "interface" => return None,
+            "matte" | "diffuse" | "diffusetransmission" => {
+                let name = if ty == "matte" { "Kd" } else { "reflectance" };
+                let albedo = self.color_texture(params, name, gray, location);
+                Arc::new(Lambertian::from_texture(albedo))
+            }
+            "plastic" | "substrate" | "uber" | "coateddiffuse" => {
+                let name = if ty == "coateddiffuse" {
+                    "reflectance"
+                } else {
+                    "Kd"
+                };
+                let albedo = self.color_texture(params, name, gray, location);
+                let default_roughness = if ty == "coateddiffuse" { 0.0 } else { 0.1 };
+                Arc::new(Layered::new(
+                    Arc::new(Lambertian::from_texture(albedo)),
+                    params.float("eta", params.float("index", 1.5)),
+                    roughness(default_roughness),
+                ))
+            }
+            "metal" | "conductor" | "coatedconductor" => {
+                let prefix = if ty == "coatedconductor" {
+                    "conductor."
+                } else {
+                    ""
+                };
+                let color = conductor_color(params, prefix);
+                let metal = Arc::new(Metal::new(color, roughness(0.0)));
+                if ty == "coatedconductor" {
+                    let coat = params.float("interface.roughness", 0.0);
+                    Arc::new(Layered::new(
+                        metal,
+                        params.float("interface.eta", 1.5),
+                        coat,
+                    ))
+                } else {
+                    metal
+                }
+            }
+            "mirror" => Arc::new(Metal::new(
+                params.color("Kr").unwrap_or(Color::new(0.9, 0.9, 0.9)),
+                0.0,
+            )),
+            "glass" | "dielectric" | "thindielectric" => {
+                let ior = match params.get("eta").or(params.get("index")) {
+                    Some(p) if !p.nums.is_empty() => match p.ty.as_str() {
+                        // Sampled spectra are (wavelength, value) pairs.
+                        "spectrum" => p.nums.get(1).copied().unwrap_or(1.5),
+                        _ => p.nums[0],
+                    },
+                    Some(p) => p.strs.first().map_or(1.5, |name| glass_ior(name)),
+                    None => 1.5,
+                };
+                Arc::new(Dielectric::new(ior))
+            }
+            "mix" => {
+                // Use whichever material has the larger weight.
+                let names: Vec<String> = match params.strings("materials") {
+                    [a, b] => vec![a.clone(), b.clone()],
+                    _ => ["namedmaterial1", "namedmaterial2"]
+                        .iter()
+                        .filter_map(|n| params.string(n).map(String::from))
+                        .collect(),
+                };
+                let pick = if params.float("amount", 0.5) < 0.5 {
+                    0
+                } else {
+                    1
+                };
+                return match names.get(pick).and_then(|n| self.materials.get(n)) {
+                    Some(material) => material.clone(),
+                    None => {
+                        self.warn(location, "mix of unknown materials".to_string());
+                        Some(Arc::new(Lambertian::new(gray)))
+                    }
+                };
+            }
+            _ => {
+                self.warn(
+                    location,
+                    format!("unsupported material \"{}\" rendered as diffuse", ty),
+                );
+                Arc::new(Lambertian::new(gray))
+            }
+        };
+        Some(material)
+    }
+
+    fn add_light(&mut self, ty: &str, params: &Params, location: Location) {
+        let scale = params.float("scale", 1.0);
+        let render = mirror() * self.state.ctm;
+        match ty {
+            "point" | "spot" => {
+                let from = params.floats("from");
+                let from = match from {
+                    [x, y, z, ..] => Point3::new(*x, *y, *z),
+                    _ => Point3::zero(),
+                };
+                let intensity = params.color("I").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                self.lights.push(Light::Point {
+                    position: render.transform_point(&from),
+                    intensity: scale * intensity,
+                });
+            }
+            "distant" => {
+                let point = |name: &str, default: Point3| match params.floats(name) {
+                    [x, y, z, ..] => Point3::new(*x, *y, *z),
+                    _ => default,
+                };
+                let from = point("from", Point3::zero());
+                let to = point("to", Point3::new(0.0, 0.0, 1.0));
+                let radiance = params.color("L").unwrap_or(Color::new(1.0, 1.0, 1.0));
+                self.lights.push(Light::Distant {
+                    direction: render.transform_vector(&(to - from)).unit_vector(),
+                    radiance: scale * radiance,
+                });
+            }
+            "infinite" => {
+                let radiance = match params.string("filename") {
+                    Some(filename) => {
+                        let path = self.resolve(filename);
+                        match image::open(&path) {
+                            Ok(image) => average_color(&image),
+                            Err(e) => {
+                                self.warn(location, format!("{}: {}", path.display(), e));
+                                Color::new(1.0, 1.0, 1.0)
+                            }
+                        }
+                    }
+                    None => params.color("L").unwrap_or(Color::new(1.0, 1.0, 1.0)),
+                };
+                self.background = scale * radiance;
+            }
+            _ => self.warn(location, format!("unsupported light \"{}\"", ty)),
+        }
+    }
+
+    fn make_shape(
+        &mut self,
+        ty: &str,
+        params: &Params,
+        location: Location,
+    ) -> io::Result<Option<Arc<dyn Hittable>>> {
+        let material: Arc<dyn Material> = match (self.state.area_light, &self.state.material) {
+            (Some(radiance), _) => Arc::new(DiffuseLight::new(radiance)),
+            (None, Some(material)) => material.clone(),
+            (None, None) => return Ok(None),
+        };
+        let render = mirror() * self.state.ctm;
+        let Some(transform) = Transform::from_matrix(render) else {
+            self.warn(
+                location,
+                "skipping shape with a singular transform".to_string(),
+            );
+            return Ok(None);
+        };
+
+        let shape: Arc<dyn Hittable> = match ty {
+            "sphere" => {
+                let sphere = Sphere::new(Point3::zero(), params.float("radius", 1.0), material);
+                Arc::new(Instance::new(Arc::new(sphere), transform))
+            }
+            "disk" => {
+                let disk = Disk::new(
+                    Point3::new(0.0, 0.0, params.float("height", 0.0)),
+                    Vec3::new(0.0, 0.0, 1.0),
+                    params.float("radius", 1.0),
+                    material,
+                );
+                Arc::new(Instance::new(Arc::new(disk), transform))
+            }
+            "cylinder" => {
+                let (zmin, zmax) = (params.float("zmin", -1.0), params.float("zmax", 1.0));
+                let mut cylinder = Cylinder::new(
+                    Point3::new(0.0, zmin.min(zmax), 0.0),
+                    params.float("radius", 1.0),
+                    (zmax - zmin).abs(),
+                    material,
+                );
+                let phi_max = params.float("phimax", 360.0);
+                if phi_max < 360.0 {
+                    cylinder = cylinder.with_sweep(phi_max);
+                }
+                // Our cylinders stand along +y and sweep toward +z; pbrt's stand along +z
+                // and sweep toward +y.
+                let swap_yz = Mat4::new([
+                    [1.0, 0.0, 0.0, 0.0],
+                    [0.0, 0.0, 1.0, 0.0],
+                    [0.0, 1.0, 0.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0],
+                ]);
+                let transform = Transform::from_matrix(render * swap_yz).unwrap();
+                Arc::new(Instance::new(Arc::new(cylinder), transform))
+            }
+            "trianglemesh" | "bilinearmesh" | "loopsubdiv" => {
+                let positions = points(params.floats("P"));
+                let normals = points(params.floats("N"));
+                let uvs = match params.get("uv").or(params.get("st")) {
+                    Some(p) => p.nums.chunks_exact(2).map(|c| (c[0], c[1])).collect(),
+                    None => Vec::new(),
+                };
+                let corners = if ty == "bilinearmesh" { 4 } else { 3 };
+                let indices: Vec<usize> = match params.get("indices") {
+                    Some(p) => p.nums.iter().map(|&i| i as usize).collect(),
+                    None if positions.len() == corners => (0..corners).collect(),
+                    None => return Err(self.error(location, format!("{} without indices", ty))),
+                };
+                if !indices.len().is_multiple_of(corners)
+                    || indices.iter().any(|&i| i >= positions.len())
+                {
+                    return Err(self.error(location, format!("bad indices for {}", ty)));
+                }
+                if (!normals.is_empty() && normals.len() != positions.len())
+                    || (!uvs.is_empty() && uvs.len() != positions.len())
+                {
+                    return Err(self.error(location, "N or uv does not match P in length"));
+                }
+
+                let faces: Vec<[usize; 3]> = if ty == "bilinearmesh" {
+                    // Patch corners are p00, p10, p01, p11.
+                    indices
+                        .chunks_exact(4)
+                        .flat_map(|q| [[q[0], q[1], q[3]], [q[0], q[3], q[2]]])
+                        .collect()
+                } else {
+                    indices
+                        .chunks_exact(3)
+                        .map(|t| [t[0], t[1], t[2]])
+                        .collect()
+                };
+                if ty == "loopsubdiv" {
+                    let positions: Vec<Point3> =
+                        positions.iter().map(|p| transform.point(p)).collect();
+                    let faces = self
+                        .orient(&positions, &[], faces, &transform)
+                        .into_iter()
+                        .map(|f| f.to_vec())
+                        .collect();
+                    let levels = params.float("levels", 3.0).max(0.0) as usize;
+                    let control = ControlMesh::new(positions, faces);
+                    Arc::new(control.to_mesh(SubdivisionScheme::Loop, levels, material))
+                } else {
+                    Arc::new(self.bake_mesh(positions, normals, uvs, faces, &transform, material))
+                }
+            }
+            "plymesh" => {
+                let filename = params
+                    .string("filename")
+                    .ok_or_else(|| self.error(location, "plymesh without a filename"))?;
+                let path = self.resolve(filename);
+                let ply = load_ply(&path.to_string_lossy()).map_err(|e| match e.kind() {
+                    // Parse errors already name the file.
+                    io::ErrorKind::InvalidData => self.error(location, e),
+                    _ => self.error(location, format!("{}: {}", path.display(), e)),
+                })?;
+                let faces = ply
+                    .faces
+                    .iter()
+                    .flat_map(|f| (1..f.len().saturating_sub(1)).map(|i| [f[0], f[i], f[i + 1]]))
+                    .collect();
+                Arc::new(self.bake_mesh(
+                    ply.positions,
+                    ply.normals,
+                    ply.uvs,
+                    faces,
+                    &transform,
+                    material,
+                ))
+            }
+            _ => {
+                self.warn(location, format!("unsupported shape \"{}\"", ty));
+                return Ok(None);
+            }
+        };
+        Ok(Some(shape))
+    }
+
+    // Moves a mesh into render space. pbrt orients faces toward their shading normals
+    // when there are any, and otherwise by winding in object space.
+    fn bake_mesh(
+        &self,
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        faces: Vec<[usize; 3]>,
+        transform: &Transform,
+        material: Arc<dyn Material>,
+    ) -> TriangleMesh {
+        let positions: Vec<Point3> = positions.iter().map(|p| transform.point(p)).collect();
+        let normals: Vec<Vec3> = normals.iter().map(|n| transform.normal(n)).collect();
+        let faces = self.orient(&positions, &normals, faces, transform);
+        TriangleMesh::new(positions, normals, uvs, faces, material)
+    }
+
+    // Winding that gives render-space faces the normals pbrt would use.
+    fn orient(
+        &self,
+        positions: &[Point3],
+        normals: &[Vec3],
+        faces: Vec<[usize; 3]>,
+        transform: &Transform,
+    ) -> Vec<[usize; 3]> {
+        let flip = transform.swaps_handedness() ^ self.state.reverse_orientation;
+        faces
+            .into_iter()
+            .map(|[a, b, c]| {
+                let normal = (positions[b] - positions[a]).cross(&(positions[c] - positions[a]));
+                let reversed = if normals.is_empty() {
+                    flip
+                } else {
+                    normal.dot(&(normals[a] + normals[b] + normals[c])) < 0.0
+                };
+                if reversed {
+                    [a, c, b]
+                } else {
+                    [a, b, c]
+                }
+            })
+            .collect()
+    }
+
+    fn finish(self) -> io::Result<Scene> {
+        let mut list = self.shapes;
+        let mut emitters = self.emitters;
+        let bbox = list.bounding_box();
+        let (center, radius) = if bbox.is_bounded() && !bbox.is_empty() {
+            (bbox.centroid(), 0.5 * (bbox.max() - bbox.min()).length())
+        } else {
+            (Point3::zero(), 1.0)
+        };
+        for light in self.lights {
+            let light: Arc<dyn Hittable> = match light {
+                Light::Distant {
+                    direction,
+                    radiance,
+                } => {
+                    // Radiance that gives the light's irradiance over the disk's solid angle.
+                    let distance = SUN_DISTANCE * radius;
+                    let solid_angle = PI * radius * radius / (distance * distance);
+                    Arc::new(Disk::new(
+                        center - distance * direction,
+                        direction,
+                        radius,
+                        Arc::new(DiffuseLight::new(radiance / solid_angle)),
+                    ))
+                }
+                Light::Point {
+                    position,
+                    intensity,
+                } => {
+                    let light_radius = LIGHT_RADIUS_FRACTION * radius;
+                    let emit = intensity / (PI * light_radius * light_radius);
+                    Arc::new(Sphere::new(
+                        position,
+                        light_radius,
+                        Arc::new(DiffuseLight::new(emit)),
+                    ))
+                }
+            };
+            list.add(light.clone());
+            emitters.add(light);
+        }
+        let mut world = HittableList::new();
+        if !list.objects.is_empty() {
+            world.add(Arc::new(BvhAccel::from_list(list)));
+        }
+
+        let world_from_camera = self.camera_from_world.inverse().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: camera transform is singular", self.files[0]),
+            )
+        })?;
+        let render_from_camera = mirror() * world_from_camera;
+        let params = &self.camera_params;
+        let (width, height) = self.resolution;
+        let aspect_ratio = width as f64 / height as f64;
+        // pbrt's fov spans the shorter image axis.
+        let fov = params.float("fov", 90.0);
+        let vfov = if aspect_ratio >= 1.0 {
+            fov
+        } else {
+            2.0 * ((0.5 * fov).to_radians().tan() / aspect_ratio)
+                .atan()
+                .to_degrees()
+        };
+        // Camera rays are as long as the focus distance, so pinholes keep it at 1 rather
+        // than pbrt's far default.
+        let lens_radius = params.float("lensradius", 0.0);
+        let focus_dist = if lens_radius > 0.0 {
+            params.float("focaldistance", 1e6)
+        } else {
+            1.0
+        };
+
+        let mut camera = Camera::new_default();
+        camera.set_basic_params(CameraParams {
+            aspect_ratio,
+            img_width: width,
+            samples_per_pixel: self.samples_per_pixel,
+            max_depth: self.max_depth,
+            vfov,
+            lookfrom: render_from_camera.transform_point(&Point3::zero()),
+            lookat: render_from_camera.transform_point(&Point3::new(0.0, 0.0, 1.0)),
+            vup: render_from_camera.transform_vector(&Vec3::new(0.0, 1.0, 0.0)),
+            defocus_angle: 2.0 * (lens_radius / focus_dist).atan().to_degrees(),
+            focus_dist,
+        });
+        camera.background = Background::Solid(self.background);
+        camera.lights = emitters;
+
+        Ok(Scene { world, camera })
+    }
+}
+
+// Converts pbrt's left-handed space to ours.
+fn mirror() -> Mat4 {
+    scale_matrix(-1.0, 1.0, 1.0)
+}
+
+fn scale_matrix(x: f64, y: f64, z: f64) -> Mat4 {
+    Mat4::new([
+        [x, 0.0, 0.0, 0.0],
+        [0.0, y, 0.0, 0.0],
+        [0.0, 0.0, z, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+// pbrt's LookAt: the camera-from-world matrix of a camera at `eye` looking down +z at
+// `look`, with +x to the right of `up` in its left-handed space.
+fn look_at(eye: Point3, look: Point3, up: Vec3) -> Option<Mat4> {
+    let dir = (look - eye).unit_vector();
+    let right = up.unit_vector().cross(&dir);
+    if right.near_zero() {
+        return None;
+    }
+    let right = right.unit_vector();
+    let new_up = dir.cross(&right);
+    Mat4::new([
+        [right.x(), new_up.x(), dir.x(), eye.x()],
+        [right.y(), new_up.y(), dir.y(), eye.y()],
+        [right.z(), new_up.z(), dir.z(), eye.z()],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+    .inverse()
+}
+
+fn points(values: &[f64]) -> Vec<Point3> {
+    values
+        .chunks_exact(3)
+        .map(|c| Point3::new(c[0], c[1], c[2]))
+        .collect()
+}
+
+// Reflectance of a conductor at normal incidence, from `reflectance` (pbrt-v4), named
+// metal spectra, or RGB `eta` and `k`.
+fn conductor_color(params: &Params, prefix: &str) -> Color {
+    let name = |n: &str| format!("{}{}", prefix, n);
+    if let Some(color) = params.color(&name("reflectance")) {
+        return color;
+    }
+    let (eta, k) = (params.get(&name("eta")), params.get(&name("k")));
+    if let Some(named) = eta.and_then(|p| p.strs.first()) {
+        return metal_color(named);
+    }
+    match (params.color(&name("eta")), params.color(&name("k"))) {
+        (Some(eta), Some(k)) if eta.x() > 0.0 || k.x() > 0.0 => {
+            let f0 = |n: f64, k: f64| ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+            Color::new(f0(eta.x(), k.x()), f0(eta.y(), k.y()), f0(eta.z(), k.z()))
+        }
+        _ if k.is_some() || eta.is_some() => Color::new(0.9, 0.9, 0.9),
+        _ => metal_color("metal-Cu"),
+    }
+}
+
+fn average_color(image: &image::DynamicImage) -> Color {
+    let pixels = image.to_rgb32f();
+    let count = (pixels.width() as f64 * pixels.height() as f64).max(1.0);
+    let sum = pixels.pixels().fold([0.0; 3], |sum, p| {
+        [
+            sum[0] + p[0] as f64,
+            sum[1] + p[1] as f64,
+            sum[2] + p[2] as f64,
+        ]
+    });
+    Color::new(sum[0] / count, sum[1] / count, sum[2] / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_recursive_includes() {
+        let dir = std::env::temp_dir();
+        let scene = dir.join("pbrt_recursive.pbrt");
+        let part = dir.join("pbrt_recursive_part.pbrt");
+        fs::write(&scene, "WorldBegin\nInclude \"pbrt_recursive_part.pbrt\"\n").unwrap();
+        fs::write(&part, "\n\nInclude \"pbrt_recursive.pbrt\"\n").unwrap();
+        let result = load_pbrt(scene.to_str().unwrap());
+        fs::remove_file(&scene).unwrap();
+        fs::remove_file(&part).unwrap();
+
+        let message = result.err().unwrap().to_string();
+        let expected = format!("{}:3: {} includes itself", part.display(), scene.display());
+        assert_eq!(message, expected);
+    }
+
+    fn kinds(source: &str) -> Vec<(TokenKind, usize)> {
+        let tokens = tokenize(source, 0).unwrap();
+        tokens.into_iter().map(|t| (t.kind, t.location.1)).collect()
+    }
+
+    // Parameters as the loader reads them after a directive.
+    fn params(source: &str) -> io::Result<Params> {
+        let mut loader = Loader::new(PathBuf::new());
+        loader.files.push("test.pbrt".to_string());
+        loader.tokens = tokenize(source, 0).unwrap().into_iter().rev().collect();
+        loader.params()
+    }
+
+    #[test]
+    fn tokenizes_words_strings_numbers_and_brackets() {
+        let word = |w: &str| TokenKind::Word(w.to_string());
+        let string = |s: &str| TokenKind::Str(s.to_string());
+        assert_eq!(
+            kinds("Shape \"sphere\" # comment \"ignored\"\n\"float radius\" [-2.5e1]\n"),
+            vec![
+                (word("Shape"), 1),
+                (string("sphere"), 1),
+                (string("float radius"), 2),
+                (TokenKind::Open, 2),
+                (TokenKind::Num(-25.0), 2),
+                (TokenKind::Close, 2),
+            ]
+        );
+        assert_eq!(
+            kinds("\"a\\\"b\\tc\"[1]"),
+            vec![
+                (string("a\"b\tc"), 1),
+                (TokenKind::Open, 1),
+                (TokenKind::Num(1.0), 1),
+                (TokenKind::Close, 1),
+            ]
+        );
+        assert_eq!(
+            tokenize("Shape\n\"sphere\n\"", 0).err(),
+            Some((2, "unterminated string".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_parameter_lists() {
+        let p = params(
+            "\"float radius\" 2 \"point3 P\" [0 0 0 1 0 0] \"string filename\" \"a.ply\" \
+             \"bool twosided\" true \"texture Kd\" \"checks\" \"rgb Ks\" [0.1 0.2 0.3] Shape",
+        )
+        .unwrap();
+        assert_eq!(p.float("radius", 1.0), 2.0);
+        assert_eq!(p.float("zmax", 1.0), 1.0);
+        assert_eq!(p.floats("P"), [0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(p.string("filename"), Some("a.ply"));
+        assert_eq!(p.strings("twosided"), ["true"]);
+        assert_eq!(p.texture("Kd"), Some("checks"));
+        assert_eq!(p.string("Kd"), None);
+        let ks = p.color("Ks").unwrap();
+        assert_eq!([ks.x(), ks.y(), ks.z()], [0.1, 0.2, 0.3]);
+        assert_eq!(p.color("Kd").map(|c| c.x()), None);
+
+        let p = params("\"spectrum a\" [400 0.2 500 0.4] \"blackbody b\" 6500").unwrap();
+        assert!((p.color("a").unwrap().y() - 0.3).abs() < 1e-12);
+        let b = p.color("b").unwrap();
+        assert_eq!(b.x().max(b.y()).max(b.z()), 1.0);
+
+        let message = |source: &str| params(source).err().unwrap().to_string();
+        assert_eq!(
+            message("\"radius\" 2"),
+            "test.pbrt:1: malformed parameter declaration \"radius\""
+        );
+        assert_eq!(
+            message("\"float radius\"\n[1 Shape]"),
+            "test.pbrt:2: bad value for parameter \"radius\""
+        );
+        assert_eq!(
+            message("\"float radius\" [1"),
+            "test.pbrt:1: unexpected end of file"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::vec3::{orthonormal_basis, Point3, Vec3};
+
+/// Infinite plane through `point`, e.g. a ground that replaces the huge-sphere trick.
+/// Texture coordinates are distances along two in-plane axes divided by `uv_scale`,
+/// so they repeat every `uv_scale` units with wrapping textures.
+pub struct Plane {
+    point: Point3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    uv_scale: f64,
+    mat: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, mat: Arc<dyn Material>) -> Self {
+        let normal = normal.unit_vector();
+        let (tangent, bitangent) = orthonormal_basis(&normal);
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            uv_scale: 1.0,
+            mat,
+        }
+    }
+
+    pub fn with_uv_scale(mut self, uv_scale: f64) -> Self {
+        self.uv_scale = uv_scale;
+        self
+    }
+
+    pub fn point(&self) -> Point3 {
+        self.point
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = self.normal.dot(&(self.point - r.origin())) / denom;
+        if !ray_t.surronds(t) {
+            return false;
+        }
+
+        let p = r.at(t);
+        let offset = p - self.point;
+        let u = offset.dot(&self.tangent) / self.uv_scale;
+        let v = offset.dot(&self.bitangent) / self.uv_scale;
+        if self.mat.masked(u, v, &p) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        rec.mat = self.mat.clone();
+        rec.set_normal_face(r, &self.normal);
+        rec.set_shading_frame(r, &self.normal, &self.tangent);
+
+        true
+    }
+
+    // Unbounded except along an axis-aligned normal, where the box is a thin slab.
+    fn bounding_box(&self) -> Aabb {
+        let n = self.normal;
+        let axis = |normal_component: f64, coordinate: f64| {
+            if (normal_component.abs() - 1.0).abs() < 1e-12 {
+                Interval::new(coordinate, coordinate)
+            } else {
+                Interval::UNIVERSE
+            }
+        };
+        Aabb::new(
+            axis(n.x(), self.point.x()),
+            axis(n.y(), self.point.y()),
+            axis(n.z(), self.point.z()),
+        )
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.uv_scale != 1.0 {
+            return Err("planes with a uv scale cannot be saved in a scene file".to_string());
+        }
+        Ok(ObjectDesc::Plane {
+            point: self.point.into(),
+            normal: self.normal.into(),
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
@@ -0,0 +1,565 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::vec3::{Point3, Vec3};
+
+/// Vertex and face data read from a PLY file. Normals, uvs and colors are empty unless
+/// the file has them for every vertex. Faces are polygons of vertex indices.
+#[derive(Clone, Debug, Default)]
+pub struct PlyData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub faces: Vec<Vec<usize>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // Largest value of an integer type, used to normalize colors.
+    fn max(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Property {
+    Scalar(Scalar, String),
+    List(Scalar, Scalar, String), // Count type, item type, name
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(_, name) | Property::List(_, _, name) => name,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Reads scalars from the body of the file, as text or in either byte order.
+struct Body<R: BufRead> {
+    reader: R,
+    format: Format,
+    tokens: Vec<String>, // Remaining tokens of the current ASCII line, reversed
+}
+
+impl<R: BufRead> Body<R> {
+    fn scalar(&mut self, ty: Scalar) -> io::Result<f64> {
+        match self.format {
+            Format::Ascii => {
+                while self.tokens.is_empty() {
+                    let mut line = String::new();
+                    if self.reader.read_line(&mut line)? == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    self.tokens = line.split_whitespace().rev().map(String::from).collect();
+                }
+                let token = self.tokens.pop().unwrap();
+                token.parse::<f64>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid number '{}'", token),
+                    )
+                })
+            }
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+                let mut bytes = [0u8; 8];
+                let n = ty.size();
+                self.reader.read_exact(&mut bytes[..n])?;
+                if self.format == Format::BinaryBigEndian {
+                    bytes[..n].reverse();
+                }
+                let half = [bytes[0], bytes[1]];
+                let word = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                Ok(match ty {
+                    Scalar::I8 => bytes[0] as i8 as f64,
+                    Scalar::U8 => bytes[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes(half) as f64,
+                    Scalar::U16 => u16::from_le_bytes(half) as f64,
+                    Scalar::I32 => i32::from_le_bytes(word) as f64,
+                    Scalar::U32 => u32::from_le_bytes(word) as f64,
+                    Scalar::F32 => f32::from_le_bytes(word) as f64,
+                    Scalar::F64 => f64::from_le_bytes(bytes),
+                })
+            }
+        }
+    }
+}
+
+/// Reads a PLY file in ASCII or binary (either endianness). Vertex positions, normals,
+/// texture coordinates and colors are read from the `vertex` element and polygons from
+/// `face`; other elements and properties are skipped.
+pub fn load_ply(file_path: &str) -> io::Result<PlyData> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", file_path, message),
+        )
+    };
+
+    let mut reader = BufReader::new(File::open(file_path)?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid("not a PLY file".to_string()));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line_number = 1;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("header has no end_header".to_string()));
+        }
+        line_number += 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let bad_line = || invalid(format!("line {}: malformed header line", line_number));
+
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid(format!("unknown format '{}'", name))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| bad_line())?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let element = elements.last_mut().ok_or_else(bad_line)?;
+                let count_type = Scalar::parse(count_type).ok_or_else(bad_line)?;
+                let item_type = Scalar::parse(item_type).ok_or_else(bad_line)?;
+                element
+                    .properties
+                    .push(Property::List(count_type, item_type, name.to_string()));
+            }
+            ["property", ty, name] => {
+                let element = elements.last_mut().ok_or_else(bad_line)?;
+                let ty = Scalar::parse(ty).ok_or_else(bad_line)?;
+                element
+                    .properties
+                    .push(Property::Scalar(ty, name.to_string()));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(bad_line()),
+        }
+    }
+
+    let mut body = Body {
+        reader,
+        format: format.ok_or_else(|| invalid("header has no format".to_string()))?,
+        tokens: Vec::new(),
+    };
+    let mut data = PlyData::default();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut body, element, &mut data),
+            "face" => read_faces(&mut body, element, &mut data),
+            _ => skip_element(&mut body, element),
+        }
+        .map_err(|e| invalid(format!("{} element: {}", element.name, e)))?;
+    }
+
+    let vertex_count = data.positions.len();
+    for (f, face) in data.faces.iter().enumerate() {
+        if let Some(&i) = face.iter().find(|&&i| i >= vertex_count) {
+            return Err(invalid(format!(
+                "face {} uses vertex {} out of range",
+                f, i
+            )));
+        }
+    }
+    Ok(data)
+}
+
+/// Loads a PLY file as an indexed mesh with its own BVH, like `obj::load_obj_mesh`.
+/// Polygons are split into a fan around their first corner. Vertex colors are not
+/// kept; use `load_ply` to get them.
+pub fn load_ply_mesh(file_path: &str, material: Arc<dyn Material>) -> io::Result<TriangleMesh> {
+    let data = load_ply(file_path)?;
+    let mut indices = Vec::new();
+    for face in &data.faces {
+        for k in 1..face.len().saturating_sub(1) {
+            indices.push([face[0], face[k], face[k + 1]]);
+        }
+    }
+    Ok(
+        TriangleMesh::new(data.positions, data.normals, data.uvs, indices, material)
+            .with_source(file_path),
+    )
+}
+
+/// Writes `data` as an ASCII PLY file. Values are written as doubles in their shortest
+/// exact form, so `load_ply` reads back exactly the same numbers. Normals, uvs and
+/// colors are written when there is one per vertex.
+pub fn save_ply(file_path: &str, data: &PlyData) -> io::Result<()> {
+    let count = data.positions.len();
+    let normals = data.normals.len() == count && count > 0;
+    let uvs = data.uvs.len() == count && count > 0;
+    let colors = data.colors.len() == count && count > 0;
+
+    let mut out = BufWriter::new(File::create(file_path)?);
+    writeln!(out, "ply\nformat ascii 1.0\nelement vertex {}", count)?;
+    let mut properties = vec!["x", "y", "z"];
+    if normals {
+        properties.extend(["nx", "ny", "nz"]);
+    }
+    if uvs {
+        properties.extend(["u", "v"]);
+    }
+    if colors {
+        properties.extend(["red", "green", "blue"]);
+    }
+    for name in properties {
+        writeln!(out, "property double {}", name)?;
+    }
+    writeln!(out, "element face {}", data.faces.len())?;
+    writeln!(out, "property list uchar int vertex_indices\nend_header")?;
+
+    for i in 0..count {
+        let p = data.positions[i];
+        let mut values = vec![p.x(), p.y(), p.z()];
+        if normals {
+            let n = data.normals[i];
+            values.extend([n.x(), n.y(), n.z()]);
+        }
+        if uvs {
+            values.extend([data.uvs[i].0, data.uvs[i].1]);
+        }
+        if colors {
+            let c = data.colors[i];
+            values.extend([c.x(), c.y(), c.z()]);
+        }
+        let line: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        writeln!(out, "{}", line.join(" "))?;
+    }
+    for face in &data.faces {
+        let indices: Vec<String> = face.iter().map(|i| i.to_string()).collect();
+        writeln!(out, "{} {}", face.len(), indices.join(" "))?;
+    }
+    out.flush()
+}
+
+fn read_vertices<R: BufRead>(
+    body: &mut Body<R>,
+    element: &Element,
+    data: &mut PlyData,
+) -> io::Result<()> {
+    let find = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| matches!(p, Property::Scalar(..)) && names.contains(&p.name()))
+    };
+    let all = |slots: &[Option<usize>]| slots.iter().copied().collect::<Option<Vec<usize>>>();
+
+    let position = all(&[find(&["x"]), find(&["y"]), find(&["z"])]);
+    let normal = all(&[find(&["nx"]), find(&["ny"]), find(&["nz"])]);
+    let uv = all(&[
+        find(&["u", "s", "texture_u", "texture_s"]),
+        find(&["v", "t", "texture_v", "texture_t"]),
+    ]);
+    let color = all(&[
+        find(&["red", "r", "diffuse_red"]),
+        find(&["green", "g", "diffuse_green"]),
+        find(&["blue", "b", "diffuse_blue"]),
+    ]);
+    let position = position
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing x, y or z property"))?;
+    let color_scale: Vec<f64> = color.as_ref().map_or(Vec::new(), |slots| {
+        slots
+            .iter()
+            .map(|&i| match element.properties[i] {
+                Property::Scalar(ty, _) => ty.max(),
+                Property::List(..) => 1.0,
+            })
+            .collect()
+    });
+
+    let mut values = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            match property {
+                Property::Scalar(ty, _) => values[i] = body.scalar(*ty)?,
+                Property::List(count_type, item_type, _) => {
+                    skip_list(body, *count_type, *item_type)?
+                }
+            }
+        }
+
+        data.positions.push(Point3::new(
+            values[position[0]],
+            values[position[1]],
+            values[position[2]],
+        ));
+        if let Some(n) = &normal {
+            data.normals
+                .push(Vec3::new(values[n[0]], values[n[1]], values[n[2]]));
+        }
+        if let Some(t) = &uv {
+            data.uvs.push((values[t[0]], values[t[1]]));
+        }
+        if let Some(c) = &color {
+            data.colors.push(Color::new(
+                values[c[0]] / color_scale[0],
+                values[c[1]] / color_scale[1],
+                values[c[2]] / color_scale[2],
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn read_faces<R: BufRead>(
+    body: &mut Body<R>,
+    element: &Element,
+    data: &mut PlyData,
+) -> io::Result<()> {
+    let indices = element.properties.iter().position(|p| {
+        matches!(p, Property::List(..)) && matches!(p.name(), "vertex_indices" | "vertex_index")
+    });
+
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            match property {
+                Property::Scalar(ty, _) => {
+                    body.scalar(*ty)?;
+                }
+                Property::List(count_type, item_type, _) if Some(i) == indices => {
+                    // The count is not trusted for an allocation; a bad one runs out of data.
+                    let count = body.scalar(*count_type)? as usize;
+                    let mut face = Vec::new();
+                    for _ in 0..count {
+                        let index = body.scalar(*item_type)?;
+                        if index < 0.0 {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("negative vertex index {}", index),
+                            ));
+                        }
+                        face.push(index as usize);
+                    }
+                    data.faces.push(face);
+                }
+                Property::List(count_type, item_type, _) => {
+                    skip_list(body, *count_type, *item_type)?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_element<R: BufRead>(body: &mut Body<R>, element: &Element) -> io::Result<()> {
+    for _ in 0..element.count {
+        for property in &element.properties {
+            match property {
+                Property::Scalar(ty, _) => {
+                    body.scalar(*ty)?;
+                }
+                Property::List(count_type, item_type, _) => {
+                    skip_list(body, *count_type, *item_type)?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_list<R: BufRead>(
+    body: &mut Body<R>,
+    count_type: Scalar,
+    item_type: Scalar,
+) -> io::Result<()> {
+    let count = body.scalar(count_type)? as usize;
+    for _ in 0..count {
+        body.scalar(item_type)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                          property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                          element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn load(name: &str, bytes: &[u8]) -> io::Result<PlyData> {
+        let path = std::env::temp_dir().join(format!("ply_{}.ply", name));
+        std::fs::write(&path, bytes).unwrap();
+        let result = load_ply(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    // Binary body of the triangle in `check_triangle`, with `bytes` ordering each value.
+    fn binary(format: &str, bytes: fn(f32) -> [u8; 4], index: fn(i32) -> [u8; 4]) -> Vec<u8> {
+        let mut file = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        for (p, c) in [
+            ([0.0, 0.0, 0.0], 255),
+            ([1.0, 0.0, 0.0], 0),
+            ([0.0, 2.0, 0.5], 51),
+        ] {
+            for x in p {
+                file.extend(bytes(x));
+            }
+            file.extend([c, 0, 0]);
+        }
+        file.push(3);
+        for i in [0, 1, 2] {
+            file.extend(index(i));
+        }
+        file
+    }
+
+    fn xyz(vectors: &[Vec3]) -> Vec<[f64; 3]> {
+        vectors.iter().map(|v| [v.x(), v.y(), v.z()]).collect()
+    }
+
+    fn check_triangle(data: &PlyData) {
+        assert_eq!(
+            xyz(&data.positions),
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.0, 0.5]]
+        );
+        assert_eq!(
+            xyz(&data.colors),
+            [[1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.2, 0.0, 0.0]]
+        );
+        assert!(data.normals.is_empty() && data.uvs.is_empty());
+        assert_eq!(data.faces, [vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let file = format!(
+            "ply\nformat ascii 1.0\ncomment test\n{}0 0 0 255 0 0\n1 0 0 0 0 0\n0 2 0.5 51 0 0\n3 0 1 2\n",
+            HEADER
+        );
+        check_triangle(&load("ascii", file.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn reads_little_endian() {
+        let file = binary("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        check_triangle(&load("little_endian", &file).unwrap());
+    }
+
+    #[test]
+    fn reads_big_endian() {
+        let file = binary("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+        check_triangle(&load("big_endian", &file).unwrap());
+    }
+
+    #[test]
+    fn rejects_truncated_and_out_of_range_faces() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+                      property float y\nproperty float z\nelement face 1\n\
+                      property list uint int vertex_indices\nend_header\n0 0 0\n";
+        let huge = load("huge", format!("{}4294967295 0 0 0\n", header).as_bytes());
+        assert!(huge.is_err());
+        let message = load("range", format!("{}3 0 0 1\n", header).as_bytes())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            message.ends_with("face 0 uses vertex 1 out of range"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn round_trips_through_save_ply() {
+        let data = PlyData {
+            positions: vec![
+                Point3::new(0.1, 0.2, 0.3),
+                Point3::new(-1.0 / 3.0, 1e-7, 5.0),
+                Point3::new(2.0, 0.0, -0.7),
+                Point3::new(1.0, 1.0, 1.0),
+            ],
+            normals: vec![Vec3::new(0.0, 0.0, 1.0); 4],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (0.25, 0.75), (1.0, 1.0)],
+            colors: vec![Color::new(0.5, 0.25, 1.0); 4],
+            faces: vec![vec![0, 1, 2], vec![0, 2, 3, 1]],
+        };
+        let path = std::env::temp_dir().join("ply_round_trip.ply");
+        save_ply(path.to_str().unwrap(), &data).unwrap();
+        let loaded = load_ply(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(xyz(&loaded.positions), xyz(&data.positions));
+        assert_eq!(xyz(&loaded.normals), xyz(&data.normals));
+        assert_eq!(loaded.uvs, data.uvs);
+        assert_eq!(xyz(&loaded.colors), xyz(&data.colors));
+        assert_eq!(loaded.faces, data.faces);
+    }
+}
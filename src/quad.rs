@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::util::random_double;
+use crate::vec3::{Point3, Vec3};
+
+/// Parallelogram with corner `q` and edges `u` and `v`. Texture coordinates run from
+/// 0 to 1 along each edge.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3, // n / (n·n), for the planar coordinates of a hit point
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        let d = normal.dot(&q);
+        let w = n / n.dot(&n);
+
+        Quad {
+            q,
+            u,
+            v,
+            w,
+            mat,
+            normal,
+            d,
+            area: n.length(),
+        }
+    }
+
+    pub fn corner(&self) -> Point3 {
+        self.q
+    }
+
+    pub fn edges(&self) -> (Vec3, Vec3) {
+        (self.u, self.v)
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let denom = self.normal.dot(&r.direction());
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin())) / denom;
+        if !ray_t.surronds(t) {
+            return false;
+        }
+
+        let intersection = r.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
+
+        let unit_interval = Interval::new(0.0, 1.0);
+        if !unit_interval.contains(alpha) || !unit_interval.contains(beta) {
+            return false;
+        }
+        if self.mat.masked(alpha, beta, &intersection) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.mat = self.mat.clone();
+        rec.set_normal_face(r, &self.normal);
+        rec.set_shading_frame(r, &self.normal, &self.u);
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox_diagonal1 = Aabb::from_points(self.q, self.q + self.u + self.v);
+        let bbox_diagonal2 = Aabb::from_points(self.q + self.u, self.q + self.v);
+        Aabb::surrounding(&bbox_diagonal1, &bbox_diagonal2)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(
+            &Ray::new(*origin, *direction),
+            &Interval::new(0.001, f64::INFINITY),
+            &mut rec,
+        ) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let p = self.q + (random_double() * self.u) + (random_double() * self.v);
+        p - *origin
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        Ok(ObjectDesc::Quad {
+            q: self.q.into(),
+            u: self.u.into(),
+            v: self.v.into(),
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::disk::Disk;
+    use crate::material::Lambertian;
+    use crate::plane::Plane;
+
+    #[test]
+    fn hits_at_the_interval_ends_are_excluded() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let shapes: [Arc<dyn Hittable>; 3] = [
+            Arc::new(Quad::new(
+                Point3::new(-1.0, -1.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                mat.clone(),
+            )),
+            Arc::new(Disk::new(Point3::zero(), normal, 1.0, mat.clone())),
+            Arc::new(Plane::new(Point3::zero(), normal, mat)),
+        ];
+        // The surface is at t = 1, so a search starting there (as CSG does after each
+        // crossing) must not find it again.
+        let r = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        for shape in shapes {
+            assert!(shape.hit(&r, &Interval::new(0.0, 2.0), &mut rec));
+            assert_eq!(rec.t, 1.0);
+            assert!(!shape.hit(&r, &Interval::new(1.0, 2.0), &mut rec));
+            assert!(!shape.hit(&r, &Interval::new(0.0, 1.0), &mut rec));
+        }
+    }
+}
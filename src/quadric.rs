@@ -0,0 +1,535 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::util::{degrees_to_radians, PI};
+use crate::vec3::{Point3, Vec3};
+
+// Angle of (x, z) around the +y axis, in [0, 2π).
+pub(crate) fn azimuth(x: f64, z: f64) -> f64 {
+    let phi = z.atan2(x);
+    if phi < 0.0 {
+        phi + 2.0 * PI
+    } else {
+        phi
+    }
+}
+
+// Clamped sweep angle in radians.
+pub(crate) fn sweep_radians(degrees: f64) -> f64 {
+    degrees_to_radians(degrees.clamp(0.0, 360.0))
+}
+
+// Roots of a t² + 2 half_b t + c in increasing order, with a linear fallback.
+fn solve_quadratic(a: f64, half_b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        if half_b.abs() < 1e-12 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Hit on a disk cap at local height `y`, facing along `outward_y`.
+#[allow(clippy::too_many_arguments)]
+fn hit_cap(
+    center: &Point3,
+    y: f64,
+    outward_y: f64,
+    radius: f64,
+    phi_max: f64,
+    mat: &Arc<dyn Material>,
+    r: &Ray,
+    ray_t: &Interval,
+    rec: &mut HitRecord,
+) -> bool {
+    let o = r.origin() - *center;
+    let d = r.direction();
+    if d.y().abs() < 1e-12 {
+        return false;
+    }
+
+    let t = (y - o.y()) / d.y();
+    if !ray_t.surronds(t) {
+        return false;
+    }
+
+    let local = o + t * d;
+    let dist_squared = local.x() * local.x() + local.z() * local.z();
+    if dist_squared > radius * radius {
+        return false;
+    }
+    let phi = azimuth(local.x(), local.z());
+    if phi > phi_max {
+        return false;
+    }
+
+    let p = r.at(t);
+    let (u, v) = (phi / phi_max, 1.0 - dist_squared.sqrt() / radius);
+    if mat.masked(u, v, &p) {
+        return false;
+    }
+
+    let outward_normal = Vec3::new(0.0, outward_y, 0.0);
+    rec.t = t;
+    rec.p = p;
+    rec.u = u;
+    rec.v = v;
+    rec.set_normal_face(r, &outward_normal);
+    rec.set_shading_frame(r, &outward_normal, &Vec3::new(-local.z(), 0.0, local.x()));
+    rec.mat = mat.clone();
+    true
+}
+
+/// Cylinder around the +y axis, from `center` up to `center + height`. Orient it with an
+/// `Instance`. u runs around the axis over the sweep and v from bottom to top.
+pub struct Cylinder {
+    center: Point3,
+    radius: f64,
+    height: f64,
+    phi_max: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(center: Point3, radius: f64, height: f64, mat: Arc<dyn Material>) -> Self {
+        Cylinder {
+            center,
+            radius: radius.abs(),
+            height: height.abs(),
+            phi_max: 2.0 * PI,
+            capped: false,
+            mat,
+        }
+    }
+
+    /// Closes both ends with disks.
+    pub fn with_caps(mut self) -> Self {
+        self.capped = true;
+        self
+    }
+
+    /// Keeps only the part from the +x axis up to `degrees` toward +z.
+    pub fn with_sweep(mut self, degrees: f64) -> Self {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    fn hit_side(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let o = r.origin() - self.center;
+        let d = r.direction();
+        let a = d.x() * d.x() + d.z() * d.z();
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        let Some((t0, t1)) = solve_quadratic(a, half_b, c) else {
+            return false;
+        };
+
+        for t in [t0, t1] {
+            if !ray_t.surronds(t) {
+                continue;
+            }
+            let local = o + t * d;
+            if local.y() < 0.0 || local.y() > self.height {
+                continue;
+            }
+            let phi = azimuth(local.x(), local.z());
+            if phi > self.phi_max {
+                continue;
+            }
+
+            let p = r.at(t);
+            let (u, v) = (phi / self.phi_max, local.y() / self.height);
+            if self.mat.masked(u, v, &p) {
+                continue;
+            }
+
+            let outward_normal = Vec3::new(local.x(), 0.0, local.z()) / self.radius;
+            rec.t = t;
+            rec.p = p;
+            rec.u = u;
+            rec.v = v;
+            rec.set_normal_face(r, &outward_normal);
+            rec.set_shading_frame(r, &outward_normal, &Vec3::new(-local.z(), 0.0, local.x()));
+            rec.mat = self.mat.clone();
+            return true;
+        }
+        false
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let mut closest = *ray_t;
+        let mut hit_anything = false;
+        if self.hit_side(r, &closest, rec) {
+            hit_anything = true;
+            closest.max = rec.t;
+        }
+        if self.capped {
+            for (y, outward_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                let (center, radius, phi_max) = (&self.center, self.radius, self.phi_max);
+                if hit_cap(
+                    center, y, outward_y, radius, phi_max, &self.mat, r, &closest, rec,
+                ) {
+                    hit_anything = true;
+                    closest.max = rec.t;
+                }
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(
+            self.center + Vec3::new(-self.radius, 0.0, -self.radius),
+            self.center + Vec3::new(self.radius, self.height, self.radius),
+        )
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.phi_max < 2.0 * PI {
+            return Err("partial cylinders cannot be saved in a scene file".to_string());
+        }
+        Ok(ObjectDesc::Cylinder {
+            center: self.center.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.capped,
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+/// Cone around the +y axis with its base disk at `center` and its apex `height` above.
+/// u runs around the axis over the sweep and v from the base to the apex. Panics if
+/// `height` is zero, which leaves the side without a slope.
+pub struct Cone {
+    center: Point3,
+    radius: f64,
+    height: f64,
+    phi_max: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(center: Point3, radius: f64, height: f64, mat: Arc<dyn Material>) -> Self {
+        assert!(height.abs() > 0.0, "a cone needs a nonzero height");
+        Cone {
+            center,
+            radius: radius.abs(),
+            height: height.abs(),
+            phi_max: 2.0 * PI,
+            capped: false,
+            mat,
+        }
+    }
+
+    /// Closes the base with a disk.
+    pub fn with_cap(mut self) -> Self {
+        self.capped = true;
+        self
+    }
+
+    /// Keeps only the part from the +x axis up to `degrees` toward +z.
+    pub fn with_sweep(mut self, degrees: f64) -> Self {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    fn hit_side(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        // x² + z² = k² (height - y)²
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let o = r.origin() - self.center;
+        let d = r.direction();
+        let above = self.height - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let half_b = o.x() * d.x() + o.z() * d.z() + k2 * above * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k2 * above * above;
+        let Some((t0, t1)) = solve_quadratic(a, half_b, c) else {
+            return false;
+        };
+
+        for t in [t0, t1] {
+            if !ray_t.surronds(t) {
+                continue;
+            }
+            let local = o + t * d;
+            if local.y() < 0.0 || local.y() > self.height {
+                continue;
+            }
+            let phi = azimuth(local.x(), local.z());
+            if phi > self.phi_max {
+                continue;
+            }
+
+            let p = r.at(t);
+            let (u, v) = (phi / self.phi_max, local.y() / self.height);
+            if self.mat.masked(u, v, &p) {
+                continue;
+            }
+
+            let outward_normal =
+                Vec3::new(local.x(), k2 * (self.height - local.y()), local.z()).unit_vector();
+            rec.t = t;
+            rec.p = p;
+            rec.u = u;
+            rec.v = v;
+            rec.set_normal_face(r, &outward_normal);
+            rec.set_shading_frame(r, &outward_normal, &Vec3::new(-local.z(), 0.0, local.x()));
+            rec.mat = self.mat.clone();
+            return true;
+        }
+        false
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let mut closest = *ray_t;
+        let mut hit_anything = false;
+        if self.hit_side(r, &closest, rec) {
+            hit_anything = true;
+            closest.max = rec.t;
+        }
+        if self.capped {
+            let (center, radius, phi_max) = (&self.center, self.radius, self.phi_max);
+            if hit_cap(
+                center, 0.0, -1.0, radius, phi_max, &self.mat, r, &closest, rec,
+            ) {
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(
+            self.center + Vec3::new(-self.radius, 0.0, -self.radius),
+            self.center + Vec3::new(self.radius, self.height, self.radius),
+        )
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.phi_max < 2.0 * PI {
+            return Err("partial cones cannot be saved in a scene file".to_string());
+        }
+        Ok(ObjectDesc::Cone {
+            center: self.center.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.capped,
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+/// Paraboloid around the +y axis with its vertex at `center`, opening upward to
+/// `radius` at `height`. u runs around the axis over the sweep and v from the vertex up.
+/// Panics if `height` is zero.
+pub struct Paraboloid {
+    center: Point3,
+    radius: f64,
+    height: f64,
+    phi_max: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Paraboloid {
+    pub fn new(center: Point3, radius: f64, height: f64, mat: Arc<dyn Material>) -> Self {
+        assert!(height.abs() > 0.0, "a paraboloid needs a nonzero height");
+        Paraboloid {
+            center,
+            radius: radius.abs(),
+            height: height.abs(),
+            phi_max: 2.0 * PI,
+            capped: false,
+            mat,
+        }
+    }
+
+    /// Closes the open top with a disk.
+    pub fn with_cap(mut self) -> Self {
+        self.capped = true;
+        self
+    }
+
+    /// Keeps only the part from the +x axis up to `degrees` toward +z.
+    pub fn with_sweep(mut self, degrees: f64) -> Self {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    fn hit_side(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        // x² + z² = k y
+        let k = self.radius * self.radius / self.height;
+        let o = r.origin() - self.center;
+        let d = r.direction();
+        let a = d.x() * d.x() + d.z() * d.z();
+        let half_b = o.x() * d.x() + o.z() * d.z() - 0.5 * k * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k * o.y();
+        let Some((t0, t1)) = solve_quadratic(a, half_b, c) else {
+            return false;
+        };
+
+        for t in [t0, t1] {
+            if !ray_t.surronds(t) {
+                continue;
+            }
+            let local = o + t * d;
+            if local.y() < 0.0 || local.y() > self.height {
+                continue;
+            }
+            let phi = azimuth(local.x(), local.z());
+            if phi > self.phi_max {
+                continue;
+            }
+
+            let p = r.at(t);
+            let (u, v) = (phi / self.phi_max, local.y() / self.height);
+            if self.mat.masked(u, v, &p) {
+                continue;
+            }
+
+            let outward_normal = Vec3::new(2.0 * local.x(), -k, 2.0 * local.z()).unit_vector();
+            rec.t = t;
+            rec.p = p;
+            rec.u = u;
+            rec.v = v;
+            rec.set_normal_face(r, &outward_normal);
+            rec.set_shading_frame(r, &outward_normal, &Vec3::new(-local.z(), 0.0, local.x()));
+            rec.mat = self.mat.clone();
+            return true;
+        }
+        false
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let mut closest = *ray_t;
+        let mut hit_anything = false;
+        if self.hit_side(r, &closest, rec) {
+            hit_anything = true;
+            closest.max = rec.t;
+        }
+        if self.capped {
+            let (center, radius, phi_max) = (&self.center, self.radius, self.phi_max);
+            if hit_cap(
+                center,
+                self.height,
+                1.0,
+                radius,
+                phi_max,
+                &self.mat,
+                r,
+                &closest,
+                rec,
+            ) {
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(
+            self.center + Vec3::new(-self.radius, 0.0, -self.radius),
+            self.center + Vec3::new(self.radius, self.height, self.radius),
+        )
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.phi_max < 2.0 * PI {
+            return Err("partial paraboloids cannot be saved in a scene file".to_string());
+        }
+        Ok(ObjectDesc::Paraboloid {
+            center: self.center.into(),
+            radius: self.radius,
+            height: self.height,
+            capped: self.capped,
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn hit_t(object: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<f64> {
+        let mut rec = HitRecord::default();
+        let interval = Interval::new(0.001, f64::INFINITY);
+        object
+            .hit(&Ray::new(origin, direction), &interval, &mut rec)
+            .then_some(rec.t)
+    }
+
+    fn white() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)))
+    }
+
+    #[test]
+    fn cone_hits_its_side_and_cap() {
+        let cone = Cone::new(Point3::new(0.0, 0.0, 0.0), 1.0, 2.0, white()).with_cap();
+        // Halfway up the radius is 0.5.
+        let t = hit_t(&cone, Point3::new(-3.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((t.unwrap() - 2.5).abs() < 1e-9);
+        let t = hit_t(&cone, Point3::new(0.5, -2.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!((t.unwrap() - 2.0).abs() < 1e-9);
+        assert!(hit_t(&cone, Point3::new(-3.0, 2.5, 0.0), Vec3::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn paraboloid_and_cylinder_hit_their_sides() {
+        // x² + z² = y / 4 for a radius of 1 at height 4.
+        let paraboloid = Paraboloid::new(Point3::new(0.0, 0.0, 0.0), 1.0, 4.0, white());
+        let t = hit_t(
+            &paraboloid,
+            Point3::new(-3.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert!((t.unwrap() - 2.5).abs() < 1e-9);
+        let cylinder = Cylinder::new(Point3::new(0.0, 0.0, 0.0), 1.0, 2.0, white());
+        let t = hit_t(
+            &cylinder,
+            Point3::new(0.0, 1.0, -3.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert!((t.unwrap() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "a cone needs a nonzero height")]
+    fn flat_cones_are_rejected() {
+        Cone::new(Point3::new(0.0, 0.0, 0.0), 1.0, 0.0, white());
+    }
+
+    #[test]
+    #[should_panic(expected = "a paraboloid needs a nonzero height")]
+    fn flat_paraboloids_are_rejected() {
+        Paraboloid::new(Point3::new(0.0, 0.0, 0.0), 1.0, f64::NAN, white());
+    }
+}
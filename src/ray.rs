@@ -2,15 +2,25 @@ use crate::vec3::Vec3;
 
 use crate::vec3::Point3;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Ray {
     origin: Point3,
     dir: Vec3,
+    tm: f64,
 }
 
 impl Ray {
     pub fn new(origin: Point3, dir: Vec3) -> Self {
-        Ray { origin, dir }
+        Ray {
+            origin,
+            dir,
+            tm: 0.0,
+        }
+    }
+
+    /// Ray sampled at time `tm` within the camera shutter interval.
+    pub fn new_with_time(origin: Point3, dir: Vec3, tm: f64) -> Self {
+        Ray { origin, dir, tm }
     }
 
     pub fn origin(&self) -> Point3 {
@@ -21,16 +31,11 @@ impl Ray {
         self.dir
     }
 
-    pub fn at(&self, t: f64) -> Point3 {
-        self.origin() + (t * self.direction())
+    pub fn time(&self) -> f64 {
+        self.tm
     }
-}
 
-impl Default for Ray {
-    fn default() -> Self {
-        Ray {
-            origin: Point3::default(),
-            dir: Vec3::default(),
-        }
+    pub fn at(&self, t: f64) -> Point3 {
+        self.origin() + (t * self.direction())
     }
 }
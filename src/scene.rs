@@ -0,0 +1,835 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use toml::Spanned;
+
+use crate::box_shape::BoxShape;
+use crate::bvh::BvhAccel;
+use crate::camera::{Background, Camera, CameraParams};
+use crate::csg::Csg;
+use crate::disk::Disk;
+use crate::gltf::GltfModel;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::material::{
+    Dielectric, DiffuseLight, Lambertian, Layered, Material, Metal, MetallicRoughness,
+};
+use crate::mesh::TriangleMesh;
+use crate::plane::Plane;
+use crate::quad::Quad;
+use crate::quadric::{Cone, Cylinder, Paraboloid};
+use crate::scene_desc::{CsgOpDesc, MaterialDesc, ObjectDesc, SceneExporter, TextureDesc};
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, SolidColor, Texture};
+use crate::torus::Torus;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+
+/// A scene loaded from a description file: everything to render and the camera to
+/// render it with.
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: Camera,
+}
+
+impl Scene {
+    /// Writes the scene back out; see `save_scene`.
+    pub fn save(&self, file_path: &str) -> io::Result<()> {
+        save_scene(file_path, &self.world, &self.camera)
+    }
+}
+
+// The file format, in TOML. See `scenes/default.toml` for an example of every section.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: Option<Spanned<RenderSettings>>,
+    camera: CameraSettings,
+    #[serde(default)]
+    environment: EnvironmentSettings,
+    // Materials and objects are decoded one by one so errors can point at their entry.
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<toml::Table>>,
+    #[serde(default)]
+    objects: Vec<Spanned<toml::Table>>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+struct RenderSettings {
+    aspect_ratio: f64,
+    width: usize,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    shutter: [f64; 2],
+}
+
+impl RenderSettings {
+    // The offending key and what is wrong with it.
+    fn validate(&self) -> Result<(), (&'static str, &'static str)> {
+        if self.aspect_ratio <= 0.0 || !self.aspect_ratio.is_finite() {
+            return Err(("aspect_ratio", "must be positive"));
+        }
+        if self.width == 0 {
+            return Err(("width", "must be at least 1"));
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(("samples_per_pixel", "must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            aspect_ratio: 16.0 / 9.0,
+            width: 400,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            shutter: [0.0, 0.0],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CameraSettings {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    #[serde(default = "default_vfov")]
+    vfov: f64,
+    #[serde(default)]
+    defocus_angle: f64,
+    #[serde(default = "default_focus_dist")]
+    focus_dist: f64,
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_vfov() -> f64 {
+    90.0
+}
+
+fn default_focus_dist() -> f64 {
+    10.0
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentSettings {
+    #[default]
+    Sky,
+    Solid {
+        color: [f64; 3],
+    },
+    Gradient {
+        horizon: [f64; 3],
+        zenith: [f64; 3],
+    },
+}
+
+/// Loads a scene description file (TOML). Errors name the file and line at fault.
+pub fn load_scene(file_path: &str) -> io::Result<Scene> {
+    let source = fs::read_to_string(file_path)?;
+    let base = Path::new(file_path).parent().unwrap_or(Path::new(""));
+    parse_scene(&source, file_path, base)
+}
+
+/// Builds a scene from the text of a description file. `name` labels error messages
+/// and relative paths in the file are resolved against `base`.
+pub fn parse_scene(source: &str, name: &str, base: &Path) -> io::Result<Scene> {
+    let error_at = |offset: Option<usize>, message: &str| {
+        let location = match offset {
+            Some(offset) => format!("{}:{}", name, source[..offset].matches('\n').count() + 1),
+            None => name.to_string(),
+        };
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", location, message),
+        )
+    };
+
+    let file: SceneFile = toml::from_str(source)
+        .map_err(|e| error_at(e.span().map(|span| span.start), e.message()))?;
+
+    let mut materials: BTreeMap<&str, Arc<dyn Material>> = BTreeMap::new();
+    for (key, table) in &file.materials {
+        let material = decode::<MaterialDesc>(table)
+            .and_then(|desc| build_material(&desc, base))
+            .map_err(|message| error_at(Some(table.span().start), &message))?;
+        materials.insert(key, material);
+    }
+
+    let mut world = HittableList::new();
+    let mut meshes = MeshCache::new();
+    let mut lights = Vec::new();
+    for table in &file.objects {
+        let object = decode::<ObjectDesc>(table)
+            .and_then(|desc| build_object(&desc, &materials, &mut meshes, &mut lights, base))
+            .map_err(|message| error_at(Some(table.span().start), &message))?;
+        world.add(object);
+    }
+
+    let render = match &file.render {
+        Some(render) => {
+            let settings = render.get_ref();
+            settings.validate().map_err(|(key, message)| {
+                // Point at the key's own line when it is written on one.
+                let span = render.span();
+                let offset = source[span.clone()]
+                    .match_indices(key)
+                    .map(|(i, _)| span.start + i)
+                    .find(|&i| source[..i].ends_with('\n'))
+                    .unwrap_or(span.start);
+                error_at(Some(offset), &format!("{} {}", key, message))
+            })?;
+            settings
+        }
+        None => &RenderSettings::default(),
+    };
+    let camera_settings = &file.camera;
+    let mut camera = Camera::new_default();
+    camera.set_basic_params(CameraParams {
+        aspect_ratio: render.aspect_ratio,
+        img_width: render.width,
+        samples_per_pixel: render.samples_per_pixel,
+        max_depth: render.max_depth,
+        vfov: camera_settings.vfov,
+        lookfrom: vec3(camera_settings.lookfrom),
+        lookat: vec3(camera_settings.lookat),
+        vup: vec3(camera_settings.vup),
+        defocus_angle: camera_settings.defocus_angle,
+        focus_dist: camera_settings.focus_dist,
+    });
+    camera.set_shutter(render.shutter[0], render.shutter[1]);
+    for light in lights {
+        camera.lights.add(light);
+    }
+    camera.background = match file.environment {
+        EnvironmentSettings::Sky => Background::default(),
+        EnvironmentSettings::Solid { color } => Background::Solid(vec3(color)),
+        EnvironmentSettings::Gradient { horizon, zenith } => Background::Gradient {
+            horizon: vec3(horizon),
+            zenith: vec3(zenith),
+        },
+    };
+
+    Ok(Scene { world, camera })
+}
+
+// What `save_scene` writes: the layout of `SceneFile` with materials and objects given
+// directly.
+#[derive(Serialize)]
+struct SceneOutput {
+    render: RenderSettings,
+    camera: CameraSettings,
+    environment: EnvironmentSettings,
+    materials: BTreeMap<String, MaterialDesc>,
+    objects: Vec<ObjectDesc>,
+}
+
+/// Writes a scene description file that `load_scene` turns back into the same world
+/// and camera. Each object and material describes itself through its `to_scene`
+/// method; shared materials are written once by name. Meshes refer to the file they
+/// were loaded from, and meshes built in code are written next to the scene file as
+/// `<name>_mesh<N>.ply`. Objects the format cannot express, such as moving ones, are
+/// an error naming the object.
+pub fn save_scene(file_path: &str, world: &HittableList, camera: &Camera) -> io::Result<()> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", file_path, message),
+        )
+    };
+    if camera.is_moving() {
+        return Err(invalid("a moving camera cannot be saved".to_string()));
+    }
+
+    let path = Path::new(file_path);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut exporter = SceneExporter::new(
+        std::path::absolute(dir)?,
+        path.file_stem()
+            .map_or("scene".to_string(), |s| s.to_string_lossy().into_owned()),
+    );
+    let mut objects = Vec::with_capacity(world.objects.len());
+    for (i, object) in world.objects.iter().enumerate() {
+        let desc = exporter
+            .object(object.as_ref())
+            .map_err(|message| invalid(format!("object {}: {}", i, message)))?;
+        objects.push(desc);
+    }
+
+    let environment = match camera.background {
+        Background::Solid(color) => EnvironmentSettings::Solid {
+            color: color.into(),
+        },
+        Background::Gradient { horizon, zenith } => EnvironmentSettings::Gradient {
+            horizon: horizon.into(),
+            zenith: zenith.into(),
+        },
+    };
+    let output = SceneOutput {
+        render: RenderSettings {
+            aspect_ratio: camera.aspect_ratio,
+            width: camera.img_width,
+            samples_per_pixel: camera.samples_per_pixel,
+            max_depth: camera.max_depth,
+            shutter: [camera.shutter_open, camera.shutter_close],
+        },
+        camera: CameraSettings {
+            lookfrom: camera.lookfrom.into(),
+            lookat: camera.lookat.into(),
+            vup: camera.vup.into(),
+            vfov: camera.vfov,
+            defocus_angle: camera.defocus_angle,
+            focus_dist: camera.focus_dist,
+        },
+        environment,
+        materials: exporter.into_materials(),
+        objects,
+    };
+    let text = toml::to_string(&output).map_err(|e| invalid(e.to_string()))?;
+    fs::write(file_path, text)
+}
+
+fn decode<T: serde::de::DeserializeOwned>(table: &Spanned<toml::Table>) -> Result<T, String> {
+    toml::Value::Table(table.get_ref().clone())
+        .try_into()
+        .map_err(|e: toml::de::Error| e.message().to_string())
+}
+
+// Sizes are checked so a typo cannot turn a shape inside out.
+fn non_negative(name: &str, value: f64) -> Result<f64, String> {
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{} must not be negative", name))
+    }
+}
+
+fn non_zero(name: &str, value: f64) -> Result<f64, String> {
+    if value.abs() > 0.0 {
+        Ok(value)
+    } else {
+        Err(format!("{} must not be zero", name))
+    }
+}
+
+fn vec3([x, y, z]: [f64; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+fn build_texture(desc: &TextureDesc, base: &Path) -> Result<Arc<dyn Texture>, String> {
+    Ok(match desc {
+        TextureDesc::Solid { color } => Arc::new(SolidColor::new(vec3(*color))),
+        TextureDesc::Checker { scale, even, odd } => {
+            Arc::new(CheckerTexture::from_colors(*scale, vec3(*even), vec3(*odd)))
+        }
+        TextureDesc::Image { path, repeat } => {
+            let path = base.join(path);
+            let image = ImageTexture::load(&path.to_string_lossy())
+                .map_err(|e| format!("cannot load texture {}: {}", path.display(), e))?;
+            if *repeat {
+                Arc::new(image.with_repeat())
+            } else {
+                Arc::new(image)
+            }
+        }
+    })
+}
+
+fn build_material(desc: &MaterialDesc, base: &Path) -> Result<Arc<dyn Material>, String> {
+    Ok(match desc {
+        MaterialDesc::Lambertian { albedo, texture } => match (albedo, texture) {
+            (Some(albedo), None) => Arc::new(Lambertian::new(vec3(*albedo))),
+            (None, Some(texture)) => {
+                Arc::new(Lambertian::from_texture(build_texture(texture, base)?))
+            }
+            _ => return Err("lambertian needs exactly one of albedo or texture".to_string()),
+        },
+        MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(vec3(*albedo), *fuzz)),
+        MaterialDesc::Dielectric { ior } => {
+            if *ior <= 0.0 || ior.is_nan() {
+                return Err("ior must be positive".to_string());
+            }
+            Arc::new(Dielectric::new(*ior))
+        }
+        MaterialDesc::Light { emit } => Arc::new(DiffuseLight::new(vec3(*emit))),
+        MaterialDesc::MetallicRoughness {
+            base_color,
+            metallic,
+            roughness,
+        } => Arc::new(MetallicRoughness::new(
+            Arc::new(SolidColor::new(vec3(*base_color))),
+            *metallic,
+            *roughness,
+        )),
+        MaterialDesc::Layered {
+            base: coated,
+            ior,
+            roughness,
+            absorption,
+            thickness,
+        } => {
+            if *ior <= 0.0 || ior.is_nan() {
+                return Err("ior must be positive".to_string());
+            }
+            Arc::new(
+                Layered::new(build_material(coated, base)?, *ior, *roughness)
+                    .with_absorption(vec3(*absorption), non_negative("thickness", *thickness)?),
+            )
+        }
+    })
+}
+
+// Meshes already loaded, by path, material name and smoothing angle, so objects that
+// place the same mesh file share it.
+type MeshCache = HashMap<(String, String, Option<u64>), Arc<TriangleMesh>>;
+
+// Emissive spheres, quads, disks and triangles, and glTF lights, are also pushed onto
+// `lights`, placed as they are in the world, for the camera to sample.
+fn build_object(
+    desc: &ObjectDesc,
+    materials: &BTreeMap<&str, Arc<dyn Material>>,
+    meshes: &mut MeshCache,
+    lights: &mut Vec<Arc<dyn Hittable>>,
+    base: &Path,
+) -> Result<Arc<dyn Hittable>, String> {
+    let material = |name: &str| {
+        materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("unknown material '{}'", name))
+    };
+    let path = |path: &str| base.join(path).to_string_lossy().into_owned();
+
+    let first_light = lights.len();
+    let emissive = match desc {
+        ObjectDesc::Sphere { material: m, .. }
+        | ObjectDesc::Quad { material: m, .. }
+        | ObjectDesc::Disk { material: m, .. }
+        | ObjectDesc::Triangle { material: m, .. } => material(m)?.is_emissive(),
+        _ => false,
+    };
+
+    let (object, transform): (Arc<dyn Hittable>, _) = match desc {
+        ObjectDesc::Sphere {
+            center,
+            radius,
+            material: m,
+            transform,
+        } => (
+            Arc::new(Sphere::new(
+                vec3(*center),
+                non_negative("radius", *radius)?,
+                material(m)?,
+            )),
+            transform,
+        ),
+        ObjectDesc::Plane {
+            point,
+            normal,
+            material: m,
+            transform,
+        } => (
+            Arc::new(Plane::new(vec3(*point), vec3(*normal), material(m)?)),
+            transform,
+        ),
+        ObjectDesc::Quad {
+            q,
+            u,
+            v,
+            material: m,
+            transform,
+        } => (
+            Arc::new(Quad::new(vec3(*q), vec3(*u), vec3(*v), material(m)?)),
+            transform,
+        ),
+        ObjectDesc::Disk {
+            center,
+            normal,
+            radius,
+            material: m,
+            transform,
+        } => (
+            Arc::new(Disk::new(
+                vec3(*center),
+                vec3(*normal),
+                non_negative("radius", *radius)?,
+                material(m)?,
+            )),
+            transform,
+        ),
+        ObjectDesc::Triangle {
+            vertices: [a, b, c],
+            material: m,
+            transform,
+        } => (
+            Arc::new(Triangle::new(vec3(*a), vec3(*b), vec3(*c), material(m)?)),
+            transform,
+        ),
+        ObjectDesc::Box {
+            min,
+            max,
+            material: m,
+            transform,
+        } => (
+            Arc::new(BoxShape::new(vec3(*min), vec3(*max), material(m)?)),
+            transform,
+        ),
+        ObjectDesc::Cylinder {
+            center,
+            radius,
+            height,
+            capped,
+            material: m,
+            transform,
+        } => {
+            let mut cylinder = Cylinder::new(
+                vec3(*center),
+                non_negative("radius", *radius)?,
+                *height,
+                material(m)?,
+            );
+            if *capped {
+                cylinder = cylinder.with_caps();
+            }
+            (Arc::new(cylinder), transform)
+        }
+        ObjectDesc::Cone {
+            center,
+            radius,
+            height,
+            capped,
+            material: m,
+            transform,
+        } => {
+            let mut cone = Cone::new(
+                vec3(*center),
+                non_negative("radius", *radius)?,
+                non_zero("height", *height)?,
+                material(m)?,
+            );
+            if *capped {
+                cone = cone.with_cap();
+            }
+            (Arc::new(cone), transform)
+        }
+        ObjectDesc::Paraboloid {
+            center,
+            radius,
+            height,
+            capped,
+            material: m,
+            transform,
+        } => {
+            let mut paraboloid = Paraboloid::new(
+                vec3(*center),
+                non_negative("radius", *radius)?,
+                non_zero("height", *height)?,
+                material(m)?,
+            );
+            if *capped {
+                paraboloid = paraboloid.with_cap();
+            }
+            (Arc::new(paraboloid), transform)
+        }
+        ObjectDesc::Torus {
+            center,
+            major_radius,
+            minor_radius,
+            material: m,
+            transform,
+        } => (
+            Arc::new(Torus::new(
+                vec3(*center),
+                non_negative("major_radius", *major_radius)?,
+                non_negative("minor_radius", *minor_radius)?,
+                material(m)?,
+            )),
+            transform,
+        ),
+        ObjectDesc::Mesh {
+            path: p,
+            material: m,
+            smooth_angle,
+            transform,
+        } => {
+            let full = path(p);
+            let key = (full.clone(), m.clone(), smooth_angle.map(f64::to_bits));
+            let mesh = match meshes.get(&key) {
+                Some(mesh) => mesh.clone(),
+                None => {
+                    let extension = Path::new(p)
+                        .extension()
+                        .map(|e| e.to_string_lossy().to_lowercase());
+                    let mesh = match extension.as_deref() {
+                        Some("obj") => crate::obj::load_obj_mesh(&full, material(m)?),
+                        Some("ply") => crate::ply::load_ply_mesh(&full, material(m)?),
+                        Some("stl") => {
+                            crate::stl::load_stl_mesh(&full, material(m)?, *smooth_angle)
+                        }
+                        _ => return Err(format!("unsupported mesh format '{}'", p)),
+                    }
+                    .map_err(|e| format!("cannot load mesh: {}", e))?;
+                    let mesh = Arc::new(mesh);
+                    meshes.insert(key, mesh.clone());
+                    mesh
+                }
+            };
+            (mesh, transform)
+        }
+        ObjectDesc::Gltf { path: p, transform } => {
+            let full = path(p);
+            let scene = crate::gltf::load_gltf(&full)
+                .map_err(|e| format!("cannot load glTF scene: {}", e))?;
+            lights.extend(scene.lights.objects);
+            (Arc::new(GltfModel::new(scene.world, &full)), transform)
+        }
+        ObjectDesc::Csg {
+            op,
+            left,
+            right,
+            transform,
+        } => {
+            // Only the parts of the operands that survive are lit, so neither is a light.
+            let left = build_object(left, materials, meshes, &mut Vec::new(), base)?;
+            let right = build_object(right, materials, meshes, &mut Vec::new(), base)?;
+            let csg = match op {
+                CsgOpDesc::Union => Csg::union(left, right),
+                CsgOpDesc::Intersection => Csg::intersection(left, right),
+                CsgOpDesc::Difference => Csg::difference(left, right),
+            };
+            (Arc::new(csg), transform)
+        }
+        ObjectDesc::Group { objects, transform } => {
+            let objects = objects
+                .iter()
+                .map(|desc| build_object(desc, materials, meshes, lights, base))
+                .collect::<Result<Vec<_>, _>>()?;
+            (Arc::new(BvhAccel::new(objects)), transform)
+        }
+    };
+
+    if emissive {
+        lights.push(object.clone());
+    }
+    match transform {
+        Some(transform) => {
+            let transform = transform.to_transform()?;
+            for light in &mut lights[first_light..] {
+                *light = Arc::new(Instance::new(light.clone(), transform));
+            }
+            Ok(Arc::new(Instance::new(object, transform)))
+        }
+        None => Ok(object),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HitRecord;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use crate::vec3::Point3;
+
+    const SCENE: &str = r#"[render]
+width = 64
+samples_per_pixel = 4
+
+[camera]
+lookfrom = [0.0, 1.0, 5.0]
+lookat = [0.0, 0.0, 0.0]
+
+[materials.white]
+type = "lambertian"
+albedo = [0.8, 0.8, 0.8]
+
+[materials.lamp]
+type = "light"
+emit = [4.0, 4.0, 4.0]
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = "white"
+
+[[objects]]
+type = "quad"
+q = [-1.0, 3.0, -1.0]
+u = [2.0, 0.0, 0.0]
+v = [0.0, 0.0, 2.0]
+material = "lamp"
+transform = { translate = [0.0, 1.0, 0.0] }
+"#;
+
+    fn error(source: &str) -> String {
+        match parse_scene(source, "test.toml", Path::new(".")) {
+            Ok(_) => panic!("scene should not parse"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_scene_and_collects_lights() {
+        let scene = parse_scene(SCENE, "test.toml", Path::new(".")).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.camera.img_width, 64);
+        assert_eq!(scene.camera.lights.objects.len(), 1);
+    }
+
+    #[test]
+    fn reports_error_lines() {
+        assert_eq!(
+            error(&SCENE.replace("samples_per_pixel = 4", "samples_per_pixel = 0")),
+            "test.toml:3: samples_per_pixel must be at least 1"
+        );
+        assert_eq!(
+            error(&SCENE.replace("width = 64", "width = 64\naspect_ratio = -1.0")),
+            "test.toml:3: aspect_ratio must be positive"
+        );
+        assert_eq!(
+            error(&SCENE.replace("radius = 1.0", "radius = -1.0")),
+            "test.toml:17: radius must not be negative"
+        );
+        assert_eq!(
+            error(&SCENE.replace("material = \"white\"", "material = \"black\"")),
+            "test.toml:17: unknown material 'black'"
+        );
+        assert_eq!(
+            error(&SCENE.replace("emit = [4.0, 4.0, 4.0]", "emit = 4.0")),
+            "test.toml:13: invalid type: floating point `4.0`, expected an array of length 3"
+        );
+    }
+
+    #[test]
+    fn saved_scenes_load_back_the_same() {
+        let scene = parse_scene(SCENE, "test.toml", Path::new(".")).unwrap();
+        let first = std::env::temp_dir().join("scene_saved.toml");
+        let first = first.to_str().unwrap();
+        save_scene(first, &scene.world, &scene.camera).unwrap();
+        let loaded = load_scene(first).unwrap();
+        assert_eq!(loaded.world.objects.len(), 2);
+        assert_eq!(loaded.camera.img_width, 64);
+        assert_eq!(loaded.camera.samples_per_pixel, 4);
+        assert_eq!(loaded.camera.lights.objects.len(), 1);
+
+        // Rays meet both worlds at the same places.
+        let interval = Interval::new(0.001, f64::INFINITY);
+        for target in [[0.0, 0.5, 0.0], [0.0, 4.0, 0.0], [0.9, 4.0, 0.9]] {
+            let r = Ray::new(
+                Point3::new(0.0, 1.0, 5.0),
+                Point3::new(target[0], target[1], target[2]) - Point3::new(0.0, 1.0, 5.0),
+            );
+            let (mut a, mut b) = (HitRecord::default(), HitRecord::default());
+            assert!(scene.world.hit(&r, &interval, &mut a));
+            assert!(loaded.world.hit(&r, &interval, &mut b));
+            assert!((a.t - b.t).abs() < 1e-9);
+        }
+
+        // Saving what was loaded writes the same file again.
+        let second = std::env::temp_dir().join("scene_saved_again.toml");
+        let second = second.to_str().unwrap();
+        save_scene(second, &loaded.world, &loaded.camera).unwrap();
+        assert_eq!(
+            fs::read_to_string(first).unwrap(),
+            fs::read_to_string(second).unwrap()
+        );
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn saves_gltf_scenes_by_reference() {
+        let dir = std::env::temp_dir();
+        let model = dir.join("model.gltf");
+        let world = HittableList {
+            objects: vec![Arc::new(GltfModel::new(
+                HittableList::new(),
+                model.to_str().unwrap(),
+            ))],
+        };
+        let camera = parse_scene(SCENE, "test.toml", Path::new("."))
+            .unwrap()
+            .camera;
+        let file = dir.join("scene_gltf.toml");
+        save_scene(file.to_str().unwrap(), &world, &camera).unwrap();
+        let text = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert!(text.contains("type = \"gltf\""));
+        assert!(text.contains("path = \"model.gltf\""));
+    }
+
+    #[test]
+    fn saves_paraboloids() {
+        let source = SCENE.replace(
+            "type = \"sphere\"\ncenter = [0.0, 0.0, 0.0]",
+            "type = \"paraboloid\"\ncenter = [0.0, -1.0, 0.0]\nheight = 2.0\ncapped = true",
+        );
+        assert_eq!(
+            error(&source.replace("height = 2.0", "height = 0.0")),
+            "test.toml:17: height must not be zero"
+        );
+
+        let scene = parse_scene(&source, "test.toml", Path::new(".")).unwrap();
+        let file = std::env::temp_dir().join("scene_paraboloid.toml");
+        save_scene(file.to_str().unwrap(), &scene.world, &scene.camera).unwrap();
+        let text = fs::read_to_string(&file).unwrap();
+        let loaded = load_scene(file.to_str().unwrap()).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert!(text.contains("type = \"paraboloid\""));
+
+        let interval = Interval::new(0.001, f64::INFINITY);
+        for (origin, target) in [
+            ([0.0, 0.5, 5.0], [0.0, 0.5, 0.0]),
+            ([0.3, 3.0, 0.0], [0.3, 0.0, 0.0]),
+        ] {
+            let origin = Point3::new(origin[0], origin[1], origin[2]);
+            let r = Ray::new(
+                origin,
+                Point3::new(target[0], target[1], target[2]) - origin,
+            );
+            let (mut a, mut b) = (HitRecord::default(), HitRecord::default());
+            assert!(scene.world.hit(&r, &interval, &mut a));
+            assert!(loaded.world.hit(&r, &interval, &mut b));
+            assert!((a.t - b.t).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn saves_layered_materials() {
+        let source = SCENE.replace(
+            "type = \"lambertian\"\nalbedo = [0.8, 0.8, 0.8]",
+            "type = \"layered\"\nior = 1.5\nroughness = 0.1\nbase = { type = \"metal\", albedo = [0.8, 0.8, 0.8] }",
+        );
+        assert_eq!(
+            error(&source.replace("ior = 1.5", "ior = 0.0")),
+            "test.toml:9: ior must be positive"
+        );
+
+        let scene = parse_scene(&source, "test.toml", Path::new(".")).unwrap();
+        let file = std::env::temp_dir().join("scene_layered.toml");
+        save_scene(file.to_str().unwrap(), &scene.world, &scene.camera).unwrap();
+        let text = fs::read_to_string(&file).unwrap();
+        let loaded = load_scene(file.to_str().unwrap()).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert!(text.contains("type = \"layered\""));
+        assert!(text.contains("type = \"metal\""));
+        assert_eq!(loaded.world.objects.len(), 2);
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::ply::{save_ply, PlyData};
+use crate::texture::Texture;
+use crate::transform::{Mat4, Transform};
+use crate::vec3::Vec3;
+
+/// A texture in a scene file.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: [f64; 3],
+        odd: [f64; 3],
+    },
+    Image {
+        path: String,
+        #[serde(default)]
+        repeat: bool,
+    },
+}
+
+/// A named material in a scene file.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: Option<[f64; 3]>,
+        texture: Option<TextureDesc>,
+    },
+    Metal {
+        albedo: [f64; 3],
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        ior: f64,
+    },
+    Light {
+        emit: [f64; 3],
+    },
+    MetallicRoughness {
+        base_color: [f64; 3],
+        metallic: f64,
+        roughness: f64,
+    },
+    Layered {
+        base: Box<MaterialDesc>,
+        ior: f64,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        absorption: [f64; 3],
+        #[serde(default)]
+        thickness: f64,
+    },
+}
+
+/// Placement of an object: `matrix` (row-major) first, then scale, rotations about x,
+/// y and z in degrees, and translation.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TransformDesc {
+    pub matrix: Option<[[f64; 4]; 4]>,
+    pub scale: Option<[f64; 3]>,
+    pub rotate_x: Option<f64>,
+    pub rotate_y: Option<f64>,
+    pub rotate_z: Option<f64>,
+    pub translate: Option<[f64; 3]>,
+}
+
+/// An object in a scene file. Materials are referred to by name.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Cylinder {
+        center: [f64; 3],
+        radius: f64,
+        height: f64,
+        #[serde(default)]
+        capped: bool,
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Cone {
+        center: [f64; 3],
+        radius: f64,
+        height: f64,
+        #[serde(default)]
+        capped: bool,
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Paraboloid {
+        center: [f64; 3],
+        radius: f64,
+        height: f64,
+        #[serde(default)]
+        capped: bool,
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    Torus {
+        center: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+        transform: Option<TransformDesc>,
+    },
+    /// OBJ, PLY or STL file, picked by extension.
+    Mesh {
+        path: String,
+        material: String,
+        smooth_angle: Option<f64>,
+        transform: Option<TransformDesc>,
+    },
+    /// glTF scene with its own materials; its cameras are ignored.
+    Gltf {
+        path: String,
+        transform: Option<TransformDesc>,
+    },
+    Csg {
+        op: CsgOpDesc,
+        left: Box<ObjectDesc>,
+        right: Box<ObjectDesc>,
+        transform: Option<TransformDesc>,
+    },
+    /// Objects placed together under one BVH.
+    Group {
+        objects: Vec<ObjectDesc>,
+        transform: Option<TransformDesc>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOpDesc {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl ObjectDesc {
+    /// This object moved by `transform` after its own transform, which is folded into
+    /// one matrix.
+    pub fn transformed(mut self, transform: &Transform) -> Result<ObjectDesc, String> {
+        let slot = match &mut self {
+            ObjectDesc::Sphere { transform, .. }
+            | ObjectDesc::Plane { transform, .. }
+            | ObjectDesc::Quad { transform, .. }
+            | ObjectDesc::Disk { transform, .. }
+            | ObjectDesc::Triangle { transform, .. }
+            | ObjectDesc::Box { transform, .. }
+            | ObjectDesc::Cylinder { transform, .. }
+            | ObjectDesc::Cone { transform, .. }
+            | ObjectDesc::Paraboloid { transform, .. }
+            | ObjectDesc::Torus { transform, .. }
+            | ObjectDesc::Mesh { transform, .. }
+            | ObjectDesc::Gltf { transform, .. }
+            | ObjectDesc::Csg { transform, .. }
+            | ObjectDesc::Group { transform, .. } => transform,
+        };
+        let own = match slot {
+            Some(desc) => desc.to_transform()?,
+            None => Transform::identity(),
+        };
+        *slot = Some(TransformDesc {
+            matrix: Some((*transform * own).matrix().m),
+            ..TransformDesc::default()
+        });
+        Ok(self)
+    }
+}
+
+impl TransformDesc {
+    /// The transform described, or why it is invalid.
+    pub fn to_transform(&self) -> Result<Transform, String> {
+        let mut transform = Transform::identity();
+        if let Some(matrix) = self.matrix {
+            transform = Transform::from_matrix(Mat4::new(matrix))
+                .ok_or_else(|| "transform matrix is not invertible".to_string())?;
+        }
+        if let Some(scale) = self.scale {
+            if scale.contains(&0.0) {
+                return Err("transform scale must not be zero".to_string());
+            }
+            transform = transform.scale(Vec3::new(scale[0], scale[1], scale[2]));
+        }
+        if let Some(degrees) = self.rotate_x {
+            transform = transform.rotate_x(degrees);
+        }
+        if let Some(degrees) = self.rotate_y {
+            transform = transform.rotate_y(degrees);
+        }
+        if let Some(degrees) = self.rotate_z {
+            transform = transform.rotate_z(degrees);
+        }
+        if let Some(offset) = self.translate {
+            transform = transform.translate(Vec3::new(offset[0], offset[1], offset[2]));
+        }
+        Ok(transform)
+    }
+}
+
+/// State of a scene being saved, handed to the `to_scene` methods of objects,
+/// materials and textures so they can describe their parts.
+pub struct SceneExporter {
+    dir: PathBuf, // Absolute directory of the scene file
+    stem: String,
+    materials: BTreeMap<String, MaterialDesc>,
+    material_names: HashMap<usize, String>, // By address of the shared material
+    meshes: HashMap<usize, String>,         // Mesh file paths by address of the mesh
+}
+
+impl SceneExporter {
+    /// Exporter for a scene file named `stem` in the absolute directory `dir`.
+    pub(crate) fn new(dir: PathBuf, stem: String) -> Self {
+        SceneExporter {
+            dir,
+            stem,
+            materials: BTreeMap::new(),
+            material_names: HashMap::new(),
+            meshes: HashMap::new(),
+        }
+    }
+
+    /// The materials described so far, by name.
+    pub(crate) fn into_materials(self) -> BTreeMap<String, MaterialDesc> {
+        self.materials
+    }
+
+    pub fn object(&mut self, object: &dyn Hittable) -> Result<ObjectDesc, String> {
+        object.to_scene(self)
+    }
+
+    /// Name under which `material` is written, describing it on first use.
+    pub fn material(&mut self, material: &Arc<dyn Material>) -> Result<String, String> {
+        let key = Arc::as_ptr(material) as *const () as usize;
+        if let Some(name) = self.material_names.get(&key) {
+            return Ok(name.clone());
+        }
+        let desc = material.to_scene(self)?;
+        let name = format!("material{}", self.material_names.len());
+        self.materials.insert(name.clone(), desc);
+        self.material_names.insert(key, name.clone());
+        Ok(name)
+    }
+
+    pub fn texture(&mut self, texture: &Arc<dyn Texture>) -> Result<TextureDesc, String> {
+        texture.to_scene(self)
+    }
+
+    /// Path, relative to the scene file when possible, of a file holding `mesh`: the
+    /// one it was loaded from, or a PLY file written now.
+    pub fn mesh(&mut self, mesh: &TriangleMesh) -> Result<String, String> {
+        let key = mesh as *const TriangleMesh as usize;
+        if let Some(path) = self.meshes.get(&key) {
+            return Ok(path.clone());
+        }
+        let path = match mesh.source() {
+            Some(source) => self.relative(source),
+            None => {
+                let name = format!("{}_mesh{}.ply", self.stem, self.meshes.len());
+                let data = PlyData {
+                    positions: mesh.positions().to_vec(),
+                    normals: mesh.normals().to_vec(),
+                    uvs: mesh.uvs().to_vec(),
+                    colors: Vec::new(),
+                    faces: mesh.indices().iter().map(|face| face.to_vec()).collect(),
+                };
+                let full = self.dir.join(&name);
+                save_ply(&full.to_string_lossy(), &data)
+                    .map_err(|e| format!("cannot write {}: {}", full.display(), e))?;
+                name
+            }
+        };
+        self.meshes.insert(key, path.clone());
+        Ok(path)
+    }
+
+    /// `path` as the scene file should refer to it.
+    pub fn relative(&self, path: &str) -> String {
+        let Ok(absolute) = std::path::absolute(path) else {
+            return path.to_string();
+        };
+        match absolute.strip_prefix(&self.dir) {
+            Ok(inside) => inside.to_string_lossy().into_owned(),
+            Err(_) => absolute.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// The error for objects, materials and textures the scene format has no place for.
+pub fn unsupported<T: ?Sized>() -> String {
+    let name = std::any::type_name::<T>();
+    format!(
+        "{} cannot be saved in a scene file",
+        name.rsplit("::").next().unwrap_or(name)
+    )
+}
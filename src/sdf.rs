@@ -0,0 +1,470 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::transform::Transform;
+use crate::util::PI;
+use crate::vec3::{Point3, Vec3};
+
+pub type DistanceFn = Arc<dyn Fn(&Point3) -> f64 + Send + Sync>;
+
+/// Tree of signed distance functions. Leaves are centered at the origin; place them
+/// with `translate`. Distances are negative inside.
+#[derive(Clone)]
+pub enum SdfNode {
+    Sphere {
+        radius: f64,
+    },
+    Box {
+        half_extents: Vec3,
+    },
+    /// Box of `half_extents` with its edges rounded off by `radius`.
+    RoundedBox {
+        half_extents: Vec3,
+        radius: f64,
+    },
+    /// Torus around the y axis.
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// Arbitrary distance function, which must not overestimate the true distance.
+    Custom {
+        distance: DistanceFn,
+        bounds: Aabb,
+    },
+    Translate {
+        node: Box<SdfNode>,
+        offset: Vec3,
+    },
+    Union(Box<SdfNode>, Box<SdfNode>),
+    /// Union blended over a distance of about `k`.
+    SmoothUnion {
+        a: Box<SdfNode>,
+        b: Box<SdfNode>,
+        k: f64,
+    },
+    /// `a` with `b` carved out.
+    Subtract(Box<SdfNode>, Box<SdfNode>),
+    SmoothSubtract {
+        a: Box<SdfNode>,
+        b: Box<SdfNode>,
+        k: f64,
+    },
+    /// Rotates the xz plane by `rate` radians per unit of height.
+    Twist {
+        node: Box<SdfNode>,
+        rate: f64,
+    },
+    /// Copies of `node` every `spacing` along each axis, `counts` copies to either side
+    /// of the original. Axes with zero spacing are not repeated.
+    Repeat {
+        node: Box<SdfNode>,
+        spacing: Vec3,
+        counts: [u32; 3],
+    },
+}
+
+impl SdfNode {
+    pub fn sphere(radius: f64) -> Self {
+        SdfNode::Sphere {
+            radius: radius.abs(),
+        }
+    }
+
+    pub fn cuboid(half_extents: Vec3) -> Self {
+        SdfNode::Box { half_extents }
+    }
+
+    pub fn rounded_box(half_extents: Vec3, radius: f64) -> Self {
+        SdfNode::RoundedBox {
+            half_extents,
+            radius: radius.abs(),
+        }
+    }
+
+    pub fn torus(major_radius: f64, minor_radius: f64) -> Self {
+        SdfNode::Torus {
+            major_radius: major_radius.abs(),
+            minor_radius: minor_radius.abs(),
+        }
+    }
+
+    /// `bounds` must enclose the surface.
+    pub fn custom<F>(distance: F, bounds: Aabb) -> Self
+    where
+        F: Fn(&Point3) -> f64 + Send + Sync + 'static,
+    {
+        SdfNode::Custom {
+            distance: Arc::new(distance),
+            bounds,
+        }
+    }
+
+    pub fn translate(self, offset: Vec3) -> Self {
+        SdfNode::Translate {
+            node: Box::new(self),
+            offset,
+        }
+    }
+
+    pub fn union(self, other: SdfNode) -> Self {
+        SdfNode::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other: SdfNode, k: f64) -> Self {
+        SdfNode::SmoothUnion {
+            a: Box::new(self),
+            b: Box::new(other),
+            k: k.abs(),
+        }
+    }
+
+    pub fn subtract(self, other: SdfNode) -> Self {
+        SdfNode::Subtract(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_subtract(self, other: SdfNode, k: f64) -> Self {
+        SdfNode::SmoothSubtract {
+            a: Box::new(self),
+            b: Box::new(other),
+            k: k.abs(),
+        }
+    }
+
+    pub fn twist(self, rate: f64) -> Self {
+        SdfNode::Twist {
+            node: Box::new(self),
+            rate,
+        }
+    }
+
+    pub fn repeat(self, spacing: Vec3, counts: [u32; 3]) -> Self {
+        SdfNode::Repeat {
+            node: Box::new(self),
+            spacing,
+            counts,
+        }
+    }
+
+    pub fn distance(&self, p: &Point3) -> f64 {
+        match self {
+            SdfNode::Sphere { radius } => p.length() - radius,
+            SdfNode::Box { half_extents } => box_distance(p, half_extents),
+            SdfNode::RoundedBox {
+                half_extents,
+                radius,
+            } => {
+                let inner = *half_extents - Vec3::new(*radius, *radius, *radius);
+                box_distance(p, &inner) - radius
+            }
+            SdfNode::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major_radius;
+                (ring * ring + p.y() * p.y()).sqrt() - minor_radius
+            }
+            SdfNode::Custom { distance, .. } => distance(p),
+            SdfNode::Translate { node, offset } => node.distance(&(*p - *offset)),
+            SdfNode::Union(a, b) => a.distance(p).min(b.distance(p)),
+            SdfNode::SmoothUnion { a, b, k } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return da.min(db);
+                }
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db + (da - db) * h - k * h * (1.0 - h)
+            }
+            SdfNode::Subtract(a, b) => a.distance(p).max(-b.distance(p)),
+            SdfNode::SmoothSubtract { a, b, k } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return da.max(-db);
+                }
+                let h = (0.5 - 0.5 * (da + db) / k).clamp(0.0, 1.0);
+                da + (-db - da) * h + k * h * (1.0 - h)
+            }
+            SdfNode::Twist { node, rate } => {
+                let (sin, cos) = (rate * p.y()).sin_cos();
+                let q = Point3::new(cos * p.x() - sin * p.z(), p.y(), sin * p.x() + cos * p.z());
+                node.distance(&q)
+            }
+            SdfNode::Repeat {
+                node,
+                spacing,
+                counts,
+            } => {
+                let fold = |x: f64, s: f64, c: u32| {
+                    if s > 0.0 {
+                        x - s * (x / s).round().clamp(-(c as f64), c as f64)
+                    } else {
+                        x
+                    }
+                };
+                let q = Point3::new(
+                    fold(p.x(), spacing.x(), counts[0]),
+                    fold(p.y(), spacing.y(), counts[1]),
+                    fold(p.z(), spacing.z(), counts[2]),
+                );
+                node.distance(&q)
+            }
+        }
+    }
+
+    /// Box enclosing the surface.
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            SdfNode::Sphere { radius } => {
+                let r = Vec3::new(*radius, *radius, *radius);
+                Aabb::from_points(-r, r)
+            }
+            SdfNode::Box { half_extents } | SdfNode::RoundedBox { half_extents, .. } => {
+                Aabb::from_points(-*half_extents, *half_extents)
+            }
+            SdfNode::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let outer = major_radius + minor_radius;
+                let r = Vec3::new(outer, *minor_radius, outer);
+                Aabb::from_points(-r, r)
+            }
+            SdfNode::Custom { bounds, .. } => *bounds,
+            SdfNode::Translate { node, offset } => {
+                node.bounds().transformed(&Transform::translation(*offset))
+            }
+            SdfNode::Union(a, b) => Aabb::surrounding(&a.bounds(), &b.bounds()),
+            SdfNode::SmoothUnion { a, b, k } => {
+                // The blend adds at most k/4 of material.
+                let b = Aabb::surrounding(&a.bounds(), &b.bounds());
+                Aabb::new(
+                    b.x.expand(k / 2.0),
+                    b.y.expand(k / 2.0),
+                    b.z.expand(k / 2.0),
+                )
+            }
+            SdfNode::Subtract(a, _) | SdfNode::SmoothSubtract { a, .. } => a.bounds(),
+            SdfNode::Twist { node, .. } => {
+                let b = node.bounds();
+                let reach = twist_reach(&b);
+                Aabb::new(
+                    Interval::new(-reach, reach),
+                    b.y,
+                    Interval::new(-reach, reach),
+                )
+            }
+            SdfNode::Repeat {
+                node,
+                spacing,
+                counts,
+            } => {
+                let b = node.bounds();
+                let widen = |i: Interval, s: f64, c: u32| {
+                    if s > 0.0 {
+                        Interval::new(i.min - s * c as f64, i.max + s * c as f64)
+                    } else {
+                        i
+                    }
+                };
+                Aabb::new(
+                    widen(b.x, spacing.x(), counts[0]),
+                    widen(b.y, spacing.y(), counts[1]),
+                    widen(b.z, spacing.z(), counts[2]),
+                )
+            }
+        }
+    }
+
+    /// Upper bound on how fast the distance changes, which limits the safe step size.
+    /// Twisting stretches space, so its distances overestimate.
+    pub fn lipschitz(&self) -> f64 {
+        match self {
+            SdfNode::Sphere { .. }
+            | SdfNode::Box { .. }
+            | SdfNode::RoundedBox { .. }
+            | SdfNode::Torus { .. }
+            | SdfNode::Custom { .. } => 1.0,
+            SdfNode::Translate { node, .. } | SdfNode::Repeat { node, .. } => node.lipschitz(),
+            SdfNode::Union(a, b)
+            | SdfNode::Subtract(a, b)
+            | SdfNode::SmoothUnion { a, b, .. }
+            | SdfNode::SmoothSubtract { a, b, .. } => a.lipschitz().max(b.lipschitz()),
+            SdfNode::Twist { node, rate } => {
+                let reach = twist_reach(&node.bounds());
+                node.lipschitz() * (1.0 + (rate * reach).powi(2)).sqrt()
+            }
+        }
+    }
+}
+
+fn box_distance(p: &Point3, half_extents: &Vec3) -> f64 {
+    let q = Vec3::new(
+        p.x().abs() - half_extents.x(),
+        p.y().abs() - half_extents.y(),
+        p.z().abs() - half_extents.z(),
+    );
+    let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+    outside + q.x().max(q.y()).max(q.z()).min(0.0)
+}
+
+// Largest distance from the y axis within `bbox`.
+fn twist_reach(bbox: &Aabb) -> f64 {
+    let x = bbox.x.min.abs().max(bbox.x.max.abs());
+    let z = bbox.z.min.abs().max(bbox.z.max.abs());
+    (x * x + z * z).sqrt()
+}
+
+/// Surface of a signed distance function, intersected by sphere tracing: the ray
+/// advances by the distance to the nearest surface until it is close enough to count as
+/// a hit. Texture coordinates map the normal to latitude and longitude.
+pub struct Sdf {
+    root: SdfNode,
+    bbox: Aabb,
+    step_scale: f64,
+    mat: Arc<dyn Material>,
+}
+
+impl Sdf {
+    const MAX_STEPS: usize = 512;
+    const HIT_DISTANCE: f64 = 1e-4;
+    const NORMAL_DELTA: f64 = 1e-5;
+
+    pub fn new(root: SdfNode, mat: Arc<dyn Material>) -> Self {
+        Sdf {
+            bbox: root.bounds(),
+            step_scale: 1.0 / root.lipschitz().max(1.0),
+            root,
+            mat,
+        }
+    }
+
+    /// Surface of a user distance function enclosed by `bounds`.
+    pub fn from_fn<F>(distance: F, bounds: Aabb, mat: Arc<dyn Material>) -> Self
+    where
+        F: Fn(&Point3) -> f64 + Send + Sync + 'static,
+    {
+        Sdf::new(SdfNode::custom(distance, bounds), mat)
+    }
+
+    /// Shortens every step by `scale` (in (0, 1]), for distance functions that
+    /// overestimate.
+    pub fn with_step_scale(mut self, scale: f64) -> Self {
+        self.step_scale = scale.clamp(1e-3, 1.0);
+        self
+    }
+
+    pub fn root(&self) -> &SdfNode {
+        &self.root
+    }
+
+    fn normal_at(&self, p: &Point3) -> Vec3 {
+        let e = Self::NORMAL_DELTA;
+        let d = |offset: Vec3| self.root.distance(&(*p + offset));
+        let gradient = Vec3::new(
+            d(Vec3::new(e, 0.0, 0.0)) - d(Vec3::new(-e, 0.0, 0.0)),
+            d(Vec3::new(0.0, e, 0.0)) - d(Vec3::new(0.0, -e, 0.0)),
+            d(Vec3::new(0.0, 0.0, e)) - d(Vec3::new(0.0, 0.0, -e)),
+        );
+        if gradient.near_zero() {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            gradient.unit_vector()
+        }
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let Some(span) = self.bbox.clip(r, ray_t) else {
+            return false;
+        };
+        let length = r.direction().length();
+        if length == 0.0 {
+            return false;
+        }
+
+        // Rays starting inside the solid march on |distance| to reach the far side.
+        let mut t = span.min;
+        // A ray that starts within the box may be leaving the surface it was spawned on,
+        // so it must get clear of the surface before a hit counts.
+        let mut clear = span.min > ray_t.min;
+        for _ in 0..Self::MAX_STEPS {
+            if t > span.max {
+                return false;
+            }
+
+            let p = r.at(t);
+            let distance = self.root.distance(&p).abs();
+            if distance >= Self::HIT_DISTANCE {
+                clear = true;
+            } else if clear && ray_t.surronds(t) {
+                let outward_normal = self.normal_at(&p);
+                let u = 0.5 + outward_normal.z().atan2(outward_normal.x()) / (2.0 * PI);
+                let v = outward_normal.y().clamp(-1.0, 1.0).acos() / PI;
+                if !self.mat.masked(u, v, &p) {
+                    rec.t = t;
+                    rec.p = p;
+                    rec.u = u;
+                    rec.v = v;
+                    rec.set_normal_face(r, &outward_normal);
+                    let tangent = Vec3::new(-outward_normal.z(), 0.0, outward_normal.x());
+                    rec.set_shading_frame(r, &outward_normal, &tangent);
+                    rec.mat = self.mat.clone();
+                    return true;
+                }
+            }
+
+            let step = (distance * self.step_scale).max(Self::HIT_DISTANCE);
+            t += step / length;
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn unit_sphere() -> Sdf {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Sdf::new(SdfNode::sphere(1.0), mat)
+    }
+
+    #[test]
+    fn rays_leaving_the_surface_miss_it() {
+        let sphere = unit_sphere();
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let mut rec = HitRecord::default();
+
+        // Grazing: the first step still lies within the hit distance of the surface.
+        let s = 0.5f64.sqrt();
+        let grazing = Ray::new(Point3::new(s, s, 0.0), Vec3::new(-0.95 * s, 1.05 * s, 0.0));
+        assert!(!sphere.hit(&grazing, &ray_t, &mut rec));
+
+        let inward = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(sphere.hit(&inward, &ray_t, &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-3, "t = {}", rec.t);
+    }
+
+    #[test]
+    fn rays_from_outside_hit_the_near_side() {
+        let sphere = unit_sphere();
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        let mut rec = HitRecord::default();
+
+        let head_on = Ray::new(Point3::new(3.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(sphere.hit(&head_on, &ray_t, &mut rec));
+        assert!((rec.t - 2.0).abs() < 1e-3, "t = {}", rec.t);
+    }
+}
@@ -1,30 +1,50 @@
-use std::rc::Rc;
+use crate::aabb::Aabb;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::motion::MotionPath;
 use crate::ray::Ray;
-use crate::vec3::{Point3, Vec3};
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::util::{random_double, PI};
+use crate::vec3::{random_unit_vector, Point3, Vec3};
 use std::sync::Arc;
 pub struct Sphere {
-    center: Point3,
+    center: MotionPath,
     radius: f64,
     mat: Arc<dyn Material>,
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Sphere::animated(MotionPath::fixed(center), radius, mat)
+    }
+
+    /// Sphere moving from `center1` at time 0 to `center2` at time 1.
+    pub fn moving(center1: Point3, center2: Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
+        Sphere::animated(MotionPath::linear(center1, center2), radius, mat)
+    }
+
+    pub fn animated(center: MotionPath, radius: f64, mat: Arc<dyn Material>) -> Self {
         Sphere {
             center,
             radius,
             mat,
         }
     }
+
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
-        let oc = self.center - r.origin();
+        let center = self.center.at(r.time());
+        let oc = center - r.origin();
         let a = r.direction().length_squared();
         let h = oc.dot(&r.direction());
         let c = oc.length_squared() - (self.radius * self.radius);
@@ -35,20 +55,90 @@ impl Hittable for Sphere {
 
         let sqrtd = descriminant.sqrt();
 
-        let mut root = (h - sqrtd) / a;
-        if !ray_t.surronds(root) {
-            root = (h + sqrtd) / a;
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
             if !ray_t.surronds(root) {
-                return false;
+                continue;
             }
+
+            let p = r.at(root);
+            let outward_normal: Vec3 = (p - center) / self.radius;
+            let (u, v) = Self::get_sphere_uv(&outward_normal);
+            if self.mat.masked(u, v, &p) {
+                continue;
+            }
+
+            rec.t = root;
+            rec.p = p;
+            rec.set_normal_face(r, &outward_normal);
+            (rec.u, rec.v) = (u, v);
+            let tangent = Vec3::new(outward_normal.z(), 0.0, -outward_normal.x());
+            rec.set_shading_frame(r, &outward_normal, &tangent);
+            rec.mat = self.mat.clone();
+
+            return true;
         }
 
-        rec.t = root;
-        rec.p = r.at(rec.t);
-        let outward_normal: Vec3 = (rec.p - self.center) / self.radius;
-        rec.set_normal_face(r, &outward_normal);
-        rec.mat = self.mat.clone();
+        false
+    }
 
-        true
+    fn bounding_box(&self) -> Aabb {
+        // Motion is linear between keys, so the boxes at the keys enclose the sweep.
+        let rvec = Vec3::new(self.radius, self.radius, self.radius);
+        self.center.keys().iter().fold(Aabb::EMPTY, |bbox, (_, c)| {
+            Aabb::surrounding(&bbox, &Aabb::from_points(*c - rvec, *c + rvec))
+        })
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let to_center = self.center.at(time) - *origin;
+        let distance_squared = to_center.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+
+        // Directions are drawn uniformly from the cone the sphere subtends.
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let cosine = to_center.dot(direction) / (distance_squared.sqrt() * direction.length());
+        if cosine < cos_theta_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        let to_center = self.center.at(time) - *origin;
+        let distance_squared = to_center.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return random_unit_vector();
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + random_double() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_double();
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        let w = to_center.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        (sin_theta * phi.cos()) * u + (sin_theta * phi.sin()) * v + z * w
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.center.is_moving() {
+            return Err("moving spheres cannot be saved in a scene file".to_string());
+        }
+        Ok(ObjectDesc::Sphere {
+            center: self.center.at(0.0).into(),
+            radius: self.radius,
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
     }
 }
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::util::degrees_to_radians;
+use crate::vec3::{Point3, Vec3};
+
+/// Reads the triangles of an ASCII or binary STL file as corner positions. Facet
+/// normals stored in the file are ignored; the winding order defines the facing.
+pub fn load_stl(file_path: &str) -> io::Result<Vec<[Point3; 3]>> {
+    let bytes = fs::read(file_path)?;
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", file_path, message),
+        )
+    };
+
+    // Binary files may also start with "solid", so trust the size of a binary file first.
+    // Some exporters pad the end, so extra bytes are allowed; text in the count field
+    // would claim gigabytes of facets.
+    if bytes.len() >= 84 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if bytes.len() as u64 >= 84 + 50 * count as u64 {
+            return Ok(read_binary(&bytes[84..], count));
+        }
+    }
+    if !bytes.starts_with(b"solid") {
+        return Err(invalid("not an STL file".to_string()));
+    }
+
+    let text = String::from_utf8_lossy(&bytes);
+    let mut corners = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.first() != Some(&"vertex") {
+            continue;
+        }
+        let coords = words[1..]
+            .iter()
+            .map(|w| w.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>();
+        match coords.as_deref() {
+            Ok([x, y, z]) => corners.push(Point3::new(*x, *y, *z)),
+            _ => return Err(invalid(format!("line {}: malformed vertex", number + 1))),
+        }
+    }
+    if corners.is_empty() {
+        return Err(invalid("no facets".to_string()));
+    }
+    if corners.len() % 3 != 0 {
+        return Err(invalid("facet with fewer than 3 vertices".to_string()));
+    }
+    Ok(corners.chunks(3).map(|c| [c[0], c[1], c[2]]).collect())
+}
+
+/// Loads an STL file as an indexed mesh with its own BVH. Corners at the same position
+/// are welded into one vertex. With `smooth_angle` (degrees), each corner gets the
+/// area-weighted average normal of the faces around it that bend less than the angle
+/// from its own face, so hard CAD edges stay sharp; otherwise faces are shaded flat.
+pub fn load_stl_mesh(
+    file_path: &str,
+    material: Arc<dyn Material>,
+    smooth_angle: Option<f64>,
+) -> io::Result<TriangleMesh> {
+    let triangles = load_stl(file_path)?;
+
+    let mut positions = Vec::new();
+    let mut faces = Vec::with_capacity(triangles.len());
+    let mut face_normals = Vec::with_capacity(triangles.len());
+    let mut welded: HashMap<[u64; 3], usize> = HashMap::new();
+    for corners in &triangles {
+        let normal = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+        if normal.near_zero() {
+            continue;
+        }
+        let face = corners.map(|p| {
+            // Adding zero turns -0.0 into 0.0 so both weld together.
+            let key = [p.x() + 0.0, p.y() + 0.0, p.z() + 0.0].map(f64::to_bits);
+            *welded.entry(key).or_insert_with(|| {
+                positions.push(p);
+                positions.len() - 1
+            })
+        });
+        if face[0] == face[1] || face[1] == face[2] || face[2] == face[0] {
+            continue;
+        }
+        faces.push(face);
+        face_normals.push(normal);
+    }
+
+    let Some(angle) = smooth_angle else {
+        return Ok(
+            TriangleMesh::new(positions, Vec::new(), Vec::new(), faces, material)
+                .with_source(file_path),
+        );
+    };
+
+    let mut vertex_faces = vec![Vec::new(); positions.len()];
+    for (f, face) in faces.iter().enumerate() {
+        for &v in face {
+            vertex_faces[v].push(f);
+        }
+    }
+
+    // Corners whose smoothed normals come out equal share a vertex.
+    let cos_angle = degrees_to_radians(angle).cos();
+    let mut vertices: HashMap<(usize, [u64; 3]), usize> = HashMap::new();
+    let mut out_positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = Vec::with_capacity(faces.len());
+    for (f, face) in faces.iter().enumerate() {
+        let own = face_normals[f].unit_vector();
+        let corner = face.map(|v| {
+            let normal = vertex_faces[v]
+                .iter()
+                .filter(|&&g| face_normals[g].unit_vector().dot(&own) >= cos_angle)
+                .fold(Vec3::zero(), |sum, &g| sum + face_normals[g])
+                .unit_vector();
+            let key = [normal.x(), normal.y(), normal.z()].map(f64::to_bits);
+            *vertices.entry((v, key)).or_insert_with(|| {
+                out_positions.push(positions[v]);
+                normals.push(normal);
+                out_positions.len() - 1
+            })
+        });
+        indices.push(corner);
+    }
+
+    Ok(TriangleMesh::new(
+        out_positions,
+        normals,
+        Vec::new(),
+        indices,
+        material,
+    ))
+}
+
+// Binary STL: after the 84-byte header, 50 bytes per facet of a normal, three corners
+// (all little-endian f32) and a 2-byte attribute.
+fn read_binary(bytes: &[u8], count: usize) -> Vec<[Point3; 3]> {
+    let float = |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64;
+    (0..count)
+        .map(|i| {
+            let facet = &bytes[50 * i..50 * (i + 1)];
+            [0, 1, 2].map(|k| {
+                let corner = &facet[12 + 12 * k..24 + 12 * k];
+                Point3::new(
+                    float(&corner[0..]),
+                    float(&corner[4..]),
+                    float(&corner[8..]),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, bytes: &[u8]) -> io::Result<Vec<[Point3; 3]>> {
+        let path = std::env::temp_dir().join(format!("stl_{}.stl", name));
+        fs::write(&path, bytes).unwrap();
+        let result = load_stl(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn corners(triangles: &[[Point3; 3]]) -> Vec<[f64; 3]> {
+        triangles
+            .iter()
+            .flatten()
+            .map(|p| [p.x(), p.y(), p.z()])
+            .collect()
+    }
+
+    // One facet, with `header` at the start of the 80-byte header and `padding` after.
+    fn binary(header: &[u8], padding: usize) -> Vec<u8> {
+        let mut file = vec![0u8; 80];
+        file[..header.len()].copy_from_slice(header);
+        file.extend(1u32.to_le_bytes());
+        for value in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.5,
+        ] {
+            file.extend(value.to_le_bytes());
+        }
+        file.extend([0, 0]);
+        file.extend(vec![0; padding]);
+        file
+    }
+
+    const TRIANGLE: [[f64; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.0, 0.5]];
+
+    #[test]
+    fn reads_ascii() {
+        let text = "solid test\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                    vertex 0 2 0.5\nendloop\nendfacet\nendsolid test\n";
+        assert_eq!(corners(&load("ascii", text.as_bytes()).unwrap()), TRIANGLE);
+    }
+
+    #[test]
+    fn reads_binary() {
+        assert_eq!(corners(&load("binary", &binary(b"", 0)).unwrap()), TRIANGLE);
+    }
+
+    #[test]
+    fn reads_binary_whose_header_starts_with_solid() {
+        let file = binary(b"solid exported by a CAD tool", 0);
+        assert_eq!(corners(&load("binary_solid", &file).unwrap()), TRIANGLE);
+    }
+
+    #[test]
+    fn reads_binary_with_trailing_bytes() {
+        let file = binary(b"solid padded", 3);
+        assert_eq!(corners(&load("binary_padded", &file).unwrap()), TRIANGLE);
+    }
+
+    #[test]
+    fn rejects_ascii_without_facets() {
+        let message = load("empty", b"solid empty\nendsolid empty\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(message.ends_with(": no facets"), "{}", message);
+    }
+}
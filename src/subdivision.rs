@@ -0,0 +1,807 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use crate::material::Material;
+use crate::mesh::TriangleMesh;
+use crate::objloader::OBJLoader;
+use crate::util::{degrees_to_radians, PI};
+use crate::vec3::{Point3, Vec3};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SubdivisionScheme {
+    /// Catmull–Clark: any polygons in, quads out.
+    CatmullClark,
+    /// Loop: triangles in and out. Other polygons are fanned into triangles first.
+    Loop,
+}
+
+// Deepest level picked automatically; each level quadruples the face count.
+const MAX_AUTO_LEVELS: usize = 6;
+
+/// Polygon control mesh for subdivision surfaces. Edges can be given a crease
+/// sharpness: an edge of sharpness `s` is subdivided with the sharp rules for `s` levels
+/// (blending for the fraction) and smoothly after that. Open boundaries are always
+/// sharp. Texture coordinates are per face corner and interpolated linearly.
+#[derive(Clone, Debug, Default)]
+pub struct ControlMesh {
+    positions: Vec<Point3>,
+    faces: Vec<Vec<usize>>,
+    uvs: Option<Vec<Vec<(f64, f64)>>>,
+    creases: HashMap<(usize, usize), f64>,
+}
+
+impl ControlMesh {
+    /// Faces with fewer than three corners or a repeated vertex are dropped, since the
+    /// subdivision rules have no meaning for them.
+    pub fn new(positions: Vec<Point3>, faces: Vec<Vec<usize>>) -> Self {
+        ControlMesh {
+            positions,
+            faces: faces.into_iter().filter(|f| is_polygon(f)).collect(),
+            uvs: None,
+            creases: HashMap::new(),
+        }
+    }
+
+    /// Sets texture coordinates for each face corner. Ignored unless they match the
+    /// faces one for one.
+    pub fn with_uvs(mut self, uvs: Vec<Vec<(f64, f64)>>) -> Self {
+        let matches = uvs.len() == self.faces.len()
+            && uvs.iter().zip(&self.faces).all(|(u, f)| u.len() == f.len());
+        if matches {
+            self.uvs = Some(uvs);
+        }
+        self
+    }
+
+    /// Marks the edge between vertices `a` and `b` as a crease.
+    pub fn with_crease(mut self, a: usize, b: usize, sharpness: f64) -> Self {
+        if sharpness > 0.0 {
+            self.creases.insert(edge_key(a, b), sharpness);
+        } else {
+            self.creases.remove(&edge_key(a, b));
+        }
+        self
+    }
+
+    /// Loads the polygons of an OBJ file, keeping texture coordinates if every corner
+    /// has one. Normals are ignored since the limit surface provides its own.
+    pub fn from_obj(file_path: &str) -> io::Result<Self> {
+        let mut loader = OBJLoader::new();
+        loader.load_obj(file_path)?;
+
+        let positions: Vec<Point3> = loader
+            .vertices
+            .iter()
+            .map(|&(x, y, z)| Point3::new(x, y, z))
+            .collect();
+        let invalid = |what: &str, index: usize| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {} index {} out of range", file_path, what, index),
+            )
+        };
+
+        // Degenerate faces are skipped here too so the uvs stay matched to the faces.
+        let obj_faces: Vec<_> = loader
+            .faces
+            .iter()
+            .filter(|face| is_polygon(&face.iter().map(|corner| corner.0).collect::<Vec<_>>()))
+            .collect();
+
+        let mut faces = Vec::with_capacity(obj_faces.len());
+        for face in &obj_faces {
+            let corners = face
+                .iter()
+                .map(|&(v, _, _)| {
+                    v.checked_sub(1)
+                        .filter(|&i| i < positions.len())
+                        .ok_or_else(|| invalid("vertex", v))
+                })
+                .collect::<io::Result<Vec<usize>>>()?;
+            faces.push(corners);
+        }
+
+        let mut mesh = ControlMesh::new(positions, faces);
+        if obj_faces
+            .iter()
+            .copied()
+            .flatten()
+            .all(|corner| corner.1.is_some())
+        {
+            let mut uvs = Vec::with_capacity(obj_faces.len());
+            for face in &obj_faces {
+                let corners = face
+                    .iter()
+                    .map(|&(_, vt, _)| {
+                        let t = vt.unwrap();
+                        t.checked_sub(1)
+                            .and_then(|i| loader.texcoords.get(i))
+                            .copied()
+                            .ok_or_else(|| invalid("texcoord", t))
+                    })
+                    .collect::<io::Result<Vec<(f64, f64)>>>()?;
+                uvs.push(corners);
+            }
+            mesh = mesh.with_uvs(uvs);
+        }
+        Ok(mesh)
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn faces(&self) -> &[Vec<usize>] {
+        &self.faces
+    }
+
+    /// Sharpness of the edge between `a` and `b`, zero for smooth edges.
+    pub fn crease(&self, a: usize, b: usize) -> f64 {
+        self.creases.get(&edge_key(a, b)).copied().unwrap_or(0.0)
+    }
+
+    /// Number of levels after which no edge is longer than `max_length`, assuming each
+    /// level halves edge lengths.
+    pub fn levels_for_edge_length(&self, max_length: f64) -> usize {
+        let longest = self
+            .faces
+            .iter()
+            .flat_map(|face| (0..face.len()).map(move |k| (face[k], face[(k + 1) % face.len()])))
+            .map(|(a, b)| (self.positions[a] - self.positions[b]).length())
+            .fold(0.0, f64::max);
+        if max_length <= 0.0 {
+            return MAX_AUTO_LEVELS;
+        }
+        if longest <= max_length {
+            return 0;
+        }
+        ((longest / max_length).log2().ceil() as usize).min(MAX_AUTO_LEVELS)
+    }
+
+    /// Number of levels after which no edge covers more than `max_pixels` pixels when
+    /// seen from `eye` with a camera of vertical field of view `vfov` (degrees) and
+    /// `image_height` pixels, measured at the mesh's closest point to the eye.
+    pub fn levels_for_screen_size(
+        &self,
+        eye: Point3,
+        vfov: f64,
+        image_height: usize,
+        max_pixels: f64,
+    ) -> usize {
+        let inf = f64::INFINITY;
+        let (lo, hi) = self.positions.iter().fold(
+            (Point3::new(inf, inf, inf), Point3::new(-inf, -inf, -inf)),
+            |(lo, hi), p| {
+                (
+                    Point3::new(lo.x().min(p.x()), lo.y().min(p.y()), lo.z().min(p.z())),
+                    Point3::new(hi.x().max(p.x()), hi.y().max(p.y()), hi.z().max(p.z())),
+                )
+            },
+        );
+        let closest = Point3::new(
+            eye.x().clamp(lo.x(), hi.x()),
+            eye.y().clamp(lo.y(), hi.y()),
+            eye.z().clamp(lo.z(), hi.z()),
+        );
+        let distance = (closest - eye).length();
+        let pixel = 2.0 * distance * (degrees_to_radians(vfov) / 2.0).tan() / image_height as f64;
+        self.levels_for_edge_length(max_pixels * pixel)
+    }
+
+    /// One level of subdivision.
+    pub fn subdivide(&self, scheme: SubdivisionScheme) -> ControlMesh {
+        match scheme {
+            SubdivisionScheme::CatmullClark => self.catmull_clark_step(),
+            SubdivisionScheme::Loop => self.triangulated().loop_step(),
+        }
+    }
+
+    /// Subdivides `levels` times and returns the limit surface as a triangle mesh, with
+    /// vertices moved onto the limit surface and its exact normals for shading. Normals
+    /// are split along creases.
+    pub fn to_mesh(
+        &self,
+        scheme: SubdivisionScheme,
+        levels: usize,
+        mat: Arc<dyn Material>,
+    ) -> TriangleMesh {
+        let mut mesh = match scheme {
+            SubdivisionScheme::CatmullClark => self.clone(),
+            SubdivisionScheme::Loop => self.triangulated(),
+        };
+        for _ in 0..levels {
+            mesh = mesh.subdivide(scheme);
+        }
+        mesh.limit_mesh(scheme, mat)
+    }
+
+    fn triangulated(&self) -> ControlMesh {
+        if self.faces.iter().all(|f| f.len() == 3) {
+            return self.clone();
+        }
+
+        let mut faces = Vec::new();
+        let mut uvs = self.uvs.as_ref().map(|_| Vec::new());
+        for (f, face) in self.faces.iter().enumerate() {
+            for k in 1..face.len().saturating_sub(1) {
+                faces.push(vec![face[0], face[k], face[k + 1]]);
+                if let (Some(out), Some(src)) = (uvs.as_mut(), self.uvs.as_ref()) {
+                    out.push(vec![src[f][0], src[f][k], src[f][k + 1]]);
+                }
+            }
+        }
+        ControlMesh {
+            positions: self.positions.clone(),
+            faces,
+            uvs,
+            creases: self.creases.clone(),
+        }
+    }
+
+    fn sharpness(&self, topology: &Topology, a: usize, b: usize) -> f64 {
+        let key = edge_key(a, b);
+        if topology
+            .edges
+            .get(&key)
+            .is_none_or(|faces| faces.len() != 2)
+        {
+            f64::INFINITY
+        } else {
+            self.creases.get(&key).copied().unwrap_or(0.0)
+        }
+    }
+
+    // Position of a vertex after one step: `smooth` is the scheme's interior rule, and
+    // creases, corners and boundaries override it.
+    fn vertex_point(&self, topology: &Topology, v: usize, smooth: impl Fn() -> Point3) -> Point3 {
+        let p = self.positions[v];
+        if topology.vertex_faces[v].is_empty() {
+            return p;
+        }
+
+        let sharp: Vec<(usize, f64)> = topology.neighbors[v]
+            .iter()
+            .map(|&n| (n, self.sharpness(topology, v, n)))
+            .filter(|&(_, s)| s > 0.0)
+            .collect();
+        let boundary = sharp.iter().any(|&(_, s)| s.is_infinite());
+        if sharp.len() < 2 && !boundary {
+            return smooth();
+        }
+
+        let sharp_point = if sharp.len() == 2 {
+            let (a, b) = (self.positions[sharp[0].0], self.positions[sharp[1].0]);
+            (a + 6.0 * p + b) / 8.0
+        } else {
+            p
+        };
+        let mean = sharp.iter().map(|&(_, s)| s.min(1.0)).sum::<f64>() / sharp.len() as f64;
+        if !boundary && mean < 1.0 {
+            let smooth = smooth();
+            smooth + mean * (sharp_point - smooth)
+        } else {
+            sharp_point
+        }
+    }
+
+    // Crease sharpness of the two halves of each split edge.
+    fn child_creases(
+        &self,
+        edge_points: &HashMap<(usize, usize), usize>,
+        vertex_index: impl Fn(usize) -> usize,
+    ) -> HashMap<(usize, usize), f64> {
+        let mut creases = HashMap::new();
+        for (&(a, b), &s) in &self.creases {
+            let Some(&e) = edge_points.get(&(a, b)) else {
+                continue;
+            };
+            if s > 1.0 {
+                creases.insert(edge_key(vertex_index(a), e), s - 1.0);
+                creases.insert(edge_key(e, vertex_index(b)), s - 1.0);
+            }
+        }
+        creases
+    }
+
+    fn catmull_clark_step(&self) -> ControlMesh {
+        let topology = Topology::new(self);
+        let vertex_count = self.positions.len();
+        let face_base = vertex_count;
+
+        let face_points: Vec<Point3> = self
+            .faces
+            .iter()
+            .map(|f| average(f.iter().map(|&i| self.positions[i])))
+            .collect();
+
+        let mut edge_keys: Vec<(usize, usize)> = topology.edges.keys().copied().collect();
+        edge_keys.sort_unstable();
+        let edge_base = face_base + self.faces.len();
+        let edge_points: HashMap<(usize, usize), usize> = edge_keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| (key, edge_base + i))
+            .collect();
+
+        let mut positions = vec![Point3::zero(); edge_base + edge_keys.len()];
+        for (v, point) in positions.iter_mut().enumerate().take(vertex_count) {
+            *point = self.vertex_point(&topology, v, || {
+                let p = self.positions[v];
+                let faces = &topology.vertex_faces[v];
+                let neighbors = &topology.neighbors[v];
+                let n = neighbors.len() as f64;
+                let f = average(faces.iter().map(|&i| face_points[i]));
+                let r = average(neighbors.iter().map(|&i| 0.5 * (p + self.positions[i])));
+                (f + 2.0 * r + (n - 3.0) * p) / n
+            });
+        }
+        positions[face_base..edge_base].copy_from_slice(&face_points);
+        for (i, &(a, b)) in edge_keys.iter().enumerate() {
+            let mid = 0.5 * (self.positions[a] + self.positions[b]);
+            let faces = &topology.edges[&(a, b)];
+            let s = self.sharpness(&topology, a, b);
+            positions[edge_base + i] = if s >= 1.0 || faces.len() != 2 {
+                mid
+            } else {
+                let smooth = 0.25
+                    * (self.positions[a]
+                        + self.positions[b]
+                        + face_points[faces[0]]
+                        + face_points[faces[1]]);
+                smooth + s * (mid - smooth)
+            };
+        }
+
+        let mut faces = Vec::new();
+        let mut uvs = self.uvs.as_ref().map(|_| Vec::new());
+        for (f, face) in self.faces.iter().enumerate() {
+            let n = face.len();
+            let face_uvs = self.uvs.as_ref().map(|u| &u[f]);
+            let center_uv = face_uvs.map(|u| average_uv(u.iter().copied()));
+            for k in 0..n {
+                let (prev, curr, next) = (face[(k + n - 1) % n], face[k], face[(k + 1) % n]);
+                faces.push(vec![
+                    curr,
+                    edge_points[&edge_key(curr, next)],
+                    face_base + f,
+                    edge_points[&edge_key(prev, curr)],
+                ]);
+                if let (Some(out), Some(u), Some(center)) = (uvs.as_mut(), face_uvs, center_uv) {
+                    let (up, uc, un) = (u[(k + n - 1) % n], u[k], u[(k + 1) % n]);
+                    out.push(vec![uc, mid_uv(uc, un), center, mid_uv(up, uc)]);
+                }
+            }
+        }
+
+        ControlMesh {
+            positions,
+            faces,
+            uvs,
+            creases: self.child_creases(&edge_points, |v| v),
+        }
+    }
+
+    fn loop_step(&self) -> ControlMesh {
+        let topology = Topology::new(self);
+        let vertex_count = self.positions.len();
+
+        let mut edge_keys: Vec<(usize, usize)> = topology.edges.keys().copied().collect();
+        edge_keys.sort_unstable();
+        let edge_points: HashMap<(usize, usize), usize> = edge_keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| (key, vertex_count + i))
+            .collect();
+
+        let mut positions = vec![Point3::zero(); vertex_count + edge_keys.len()];
+        for (v, point) in positions.iter_mut().enumerate().take(vertex_count) {
+            *point = self.vertex_point(&topology, v, || {
+                let neighbors = &topology.neighbors[v];
+                let n = neighbors.len();
+                let beta = loop_beta(n);
+                let sum = neighbors
+                    .iter()
+                    .fold(Vec3::zero(), |acc, &i| acc + self.positions[i]);
+                (1.0 - n as f64 * beta) * self.positions[v] + beta * sum
+            });
+        }
+        for (i, &(a, b)) in edge_keys.iter().enumerate() {
+            let mid = 0.5 * (self.positions[a] + self.positions[b]);
+            let faces = &topology.edges[&(a, b)];
+            let s = self.sharpness(&topology, a, b);
+            positions[vertex_count + i] = if s >= 1.0 || faces.len() != 2 {
+                mid
+            } else {
+                let opposite = |f: usize| {
+                    let c = self.faces[f].iter().find(|&&c| c != a && c != b).unwrap();
+                    self.positions[*c]
+                };
+                let smooth = 0.375 * (self.positions[a] + self.positions[b])
+                    + 0.125 * (opposite(faces[0]) + opposite(faces[1]));
+                smooth + s * (mid - smooth)
+            };
+        }
+
+        let mut faces = Vec::with_capacity(4 * self.faces.len());
+        let mut uvs = self.uvs.as_ref().map(|_| Vec::new());
+        for (f, face) in self.faces.iter().enumerate() {
+            let [a, b, c] = [face[0], face[1], face[2]];
+            let ab = edge_points[&edge_key(a, b)];
+            let bc = edge_points[&edge_key(b, c)];
+            let ca = edge_points[&edge_key(c, a)];
+            faces.push(vec![a, ab, ca]);
+            faces.push(vec![ab, b, bc]);
+            faces.push(vec![ca, bc, c]);
+            faces.push(vec![ab, bc, ca]);
+            if let (Some(out), Some(src)) = (uvs.as_mut(), self.uvs.as_ref()) {
+                let [ua, ub, uc] = [src[f][0], src[f][1], src[f][2]];
+                let (uab, ubc, uca) = (mid_uv(ua, ub), mid_uv(ub, uc), mid_uv(uc, ua));
+                out.push(vec![ua, uab, uca]);
+                out.push(vec![uab, ub, ubc]);
+                out.push(vec![uca, ubc, uc]);
+                out.push(vec![uab, ubc, uca]);
+            }
+        }
+
+        ControlMesh {
+            positions,
+            faces,
+            uvs,
+            creases: self.child_creases(&edge_points, |v| v),
+        }
+    }
+
+    // Limit position and, for smooth interior vertices, limit normal of vertex `v`.
+    fn limit_vertex(
+        &self,
+        topology: &Topology,
+        scheme: SubdivisionScheme,
+        v: usize,
+    ) -> (Point3, Option<Vec3>) {
+        let p = self.positions[v];
+        if topology.vertex_faces[v].is_empty() {
+            return (p, None);
+        }
+
+        let sharp: Vec<usize> = topology.neighbors[v]
+            .iter()
+            .copied()
+            .filter(|&n| self.sharpness(topology, v, n) > 0.0)
+            .collect();
+        match sharp.len() {
+            0 | 1 => {}
+            2 => {
+                // Cubic B-spline limit along the crease.
+                let (a, b) = (self.positions[sharp[0]], self.positions[sharp[1]]);
+                return ((a + 4.0 * p + b) / 6.0, None);
+            }
+            _ => return (p, None),
+        }
+
+        let Some((edges, diagonals)) = topology.ring(self, v) else {
+            return (p, None);
+        };
+        let n = edges.len();
+        let nf = n as f64;
+        let angle = |j: usize| 2.0 * PI * j as f64 / nf;
+
+        match scheme {
+            SubdivisionScheme::CatmullClark => {
+                if diagonals.len() != n {
+                    return (p, None);
+                }
+                let sum_e = edges
+                    .iter()
+                    .fold(Vec3::zero(), |acc, &i| acc + self.positions[i]);
+                let sum_f = diagonals
+                    .iter()
+                    .fold(Vec3::zero(), |acc, &i| acc + self.positions[i]);
+                let limit = (nf * nf * p + 4.0 * sum_e + sum_f) / (nf * (nf + 5.0));
+
+                let a =
+                    1.0 + angle(1).cos() + (PI / nf).cos() * (2.0 * (9.0 + angle(1).cos())).sqrt();
+                let (mut t1, mut t2) = (Vec3::zero(), Vec3::zero());
+                for j in 0..n {
+                    let (e, f) = (self.positions[edges[j]], self.positions[diagonals[j]]);
+                    t1 = t1 + a * angle(j).cos() * e + (angle(j).cos() + angle(j + 1).cos()) * f;
+                    t2 = t2 + a * angle(j).sin() * e + (angle(j).sin() + angle(j + 1).sin()) * f;
+                }
+                (limit, Some(t1.cross(&t2)))
+            }
+            SubdivisionScheme::Loop => {
+                let beta = loop_beta(n);
+                let chi = 1.0 / (3.0 / (8.0 * beta) + nf);
+                let sum = edges
+                    .iter()
+                    .fold(Vec3::zero(), |acc, &i| acc + self.positions[i]);
+                let limit = (1.0 - nf * chi) * p + chi * sum;
+
+                let (mut t1, mut t2) = (Vec3::zero(), Vec3::zero());
+                for (j, &e) in edges.iter().enumerate() {
+                    t1 = t1 + angle(j).cos() * self.positions[e];
+                    t2 = t2 + angle(j).sin() * self.positions[e];
+                }
+                (limit, Some(t1.cross(&t2)))
+            }
+        }
+    }
+
+    fn limit_mesh(&self, scheme: SubdivisionScheme, mat: Arc<dyn Material>) -> TriangleMesh {
+        let topology = Topology::new(self);
+        let limits: Vec<(Point3, Option<Vec3>)> = (0..self.positions.len())
+            .map(|v| self.limit_vertex(&topology, scheme, v))
+            .collect();
+
+        // Area-weighted face normals on the limit positions.
+        let face_normals: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|face| {
+                let origin = limits[face[0]].0;
+                (1..face.len().saturating_sub(1)).fold(Vec3::zero(), |acc, k| {
+                    acc + (limits[face[k]].0 - origin).cross(&(limits[face[k + 1]].0 - origin))
+                })
+            })
+            .collect();
+
+        // Face corners around a vertex are joined across smooth edges into sectors, which
+        // share a normal; creases separate sectors.
+        let mut corner_base = Vec::with_capacity(self.faces.len());
+        let mut corner_count = 0;
+        for face in &self.faces {
+            corner_base.push(corner_count);
+            corner_count += face.len();
+        }
+        let corner_of = |f: usize, v: usize| {
+            corner_base[f] + self.faces[f].iter().position(|&c| c == v).unwrap()
+        };
+        let mut sectors = UnionFind::new(corner_count);
+        for (&(a, b), faces) in &topology.edges {
+            if faces.len() == 2 && self.sharpness(&topology, a, b) <= 0.0 {
+                let (f, g) = (faces[0], faces[1]);
+                sectors.union(corner_of(f, a), corner_of(g, a));
+                sectors.union(corner_of(f, b), corner_of(g, b));
+            }
+        }
+
+        let mut sector_normals: HashMap<usize, Vec3> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for k in 0..face.len() {
+                let root = sectors.find(corner_base[f] + k);
+                let normal = sector_normals.entry(root).or_insert(Vec3::zero());
+                *normal = *normal + face_normals[f];
+            }
+        }
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut vertex_ids: HashMap<(usize, u64, u64), usize> = HashMap::new();
+        let mut corner_ids = vec![0; corner_count];
+        for (f, face) in self.faces.iter().enumerate() {
+            for (k, &v) in face.iter().enumerate() {
+                let root = sectors.find(corner_base[f] + k);
+                let uv = self.uvs.as_ref().map_or((0.0, 0.0), |u| u[f][k]);
+                let key = (root, uv.0.to_bits(), uv.1.to_bits());
+                let id = *vertex_ids.entry(key).or_insert_with(|| {
+                    let average = sector_normals[&root];
+                    let (limit, exact) = limits[v];
+                    let normal = match exact {
+                        Some(n) if !n.near_zero() && n.dot(&average) < 0.0 => -n,
+                        Some(n) if !n.near_zero() => n,
+                        _ => average,
+                    };
+                    positions.push(limit);
+                    normals.push(if normal.near_zero() {
+                        Vec3::new(0.0, 1.0, 0.0)
+                    } else {
+                        normal
+                    });
+                    uvs.push(uv);
+                    positions.len() - 1
+                });
+                corner_ids[corner_base[f] + k] = id;
+            }
+        }
+
+        let mut indices = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let ids = &corner_ids[corner_base[f]..corner_base[f] + face.len()];
+            for k in 1..ids.len().saturating_sub(1) {
+                indices.push([ids[0], ids[k], ids[k + 1]]);
+            }
+        }
+
+        if self.uvs.is_none() {
+            uvs.clear();
+        }
+        TriangleMesh::new(positions, normals, uvs, indices, mat)
+    }
+}
+
+// Edge and vertex adjacency of a control mesh.
+struct Topology {
+    edges: HashMap<(usize, usize), Vec<usize>>, // Faces on each edge
+    directed: HashMap<(usize, usize), usize>,   // Face holding each directed edge
+    vertex_faces: Vec<Vec<usize>>,
+    neighbors: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn new(mesh: &ControlMesh) -> Self {
+        let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        let mut directed = HashMap::new();
+        let mut vertex_faces = vec![Vec::new(); mesh.positions.len()];
+        let mut neighbors = vec![Vec::new(); mesh.positions.len()];
+
+        for (f, face) in mesh.faces.iter().enumerate() {
+            let n = face.len();
+            for k in 0..n {
+                let (a, b) = (face[k], face[(k + 1) % n]);
+                vertex_faces[a].push(f);
+                directed.insert((a, b), f);
+                let faces = edges.entry(edge_key(a, b)).or_default();
+                if faces.is_empty() {
+                    neighbors[a].push(b);
+                    neighbors[b].push(a);
+                }
+                faces.push(f);
+            }
+        }
+
+        Topology {
+            edges,
+            directed,
+            vertex_faces,
+            neighbors,
+        }
+    }
+
+    // One-ring of an interior vertex in rotational order: the edge neighbours, and for
+    // quads the diagonal vertex of the face after each edge neighbour.
+    fn ring(&self, mesh: &ControlMesh, v: usize) -> Option<(Vec<usize>, Vec<usize>)> {
+        let start = *self.vertex_faces[v].first()?;
+        let valence = self.vertex_faces[v].len();
+        let mut edges = Vec::with_capacity(valence);
+        let mut diagonals = Vec::with_capacity(valence);
+
+        let mut f = start;
+        loop {
+            let face = &mesh.faces[f];
+            let n = face.len();
+            let k = face.iter().position(|&c| c == v)?;
+            edges.push(face[(k + 1) % n]);
+            if n == 4 {
+                diagonals.push(face[(k + 2) % 4]);
+            }
+            let prev = face[(k + n - 1) % n];
+            f = *self.directed.get(&(v, prev))?;
+            if f == start {
+                break;
+            }
+            if edges.len() > valence {
+                return None;
+            }
+        }
+
+        (edges.len() == valence && edges.len() >= 3).then_some((edges, diagonals))
+    }
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[ra] = rb;
+        }
+    }
+}
+
+// At least three corners, none repeated.
+fn is_polygon(face: &[usize]) -> bool {
+    face.len() >= 3 && (1..face.len()).all(|i| !face[..i].contains(&face[i]))
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+// Loop's vertex weight for valence `n`.
+fn loop_beta(n: usize) -> f64 {
+    let n = n.max(1) as f64;
+    let c = 0.375 + 0.25 * (2.0 * PI / n).cos();
+    (0.625 - c * c) / n
+}
+
+fn average(points: impl Iterator<Item = Point3>) -> Point3 {
+    let (sum, count) = points.fold((Vec3::zero(), 0), |(sum, count), p| (sum + p, count + 1));
+    if count == 0 {
+        sum
+    } else {
+        sum / count as f64
+    }
+}
+
+fn average_uv(uvs: impl Iterator<Item = (f64, f64)>) -> (f64, f64) {
+    let (u, v, count) = uvs.fold((0.0, 0.0, 0), |(u, v, count), uv| {
+        (u + uv.0, v + uv.1, count + 1)
+    });
+    if count == 0 {
+        (0.0, 0.0)
+    } else {
+        (u / count as f64, v / count as f64)
+    }
+}
+
+fn mid_uv(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (0.5 * (a.0 + b.0), 0.5 * (a.1 + b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_degenerate_faces() {
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        let faces = vec![
+            vec![0, 2, 1],
+            vec![0, 1, 3],
+            vec![1, 2, 3],
+            vec![0, 3, 2],
+            vec![0, 1, 1],
+            vec![2, 3],
+        ];
+        let mesh = ControlMesh::new(positions, faces);
+        assert_eq!(mesh.faces().len(), 4);
+
+        for scheme in [SubdivisionScheme::Loop, SubdivisionScheme::CatmullClark] {
+            let refined = mesh.subdivide(scheme);
+            assert!(refined.faces().len() > mesh.faces().len());
+        }
+    }
+
+    #[test]
+    fn skips_degenerate_obj_faces_with_their_uvs() {
+        let path = std::env::temp_dir().join("subdivision_degenerate.obj");
+        std::fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\n\
+             f 1/1 2/2 2/2\nf 1/1 2/2 3/3\n",
+        )
+        .unwrap();
+        let mesh = ControlMesh::from_obj(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let mesh = mesh.unwrap();
+        assert_eq!(mesh.faces(), &[vec![0, 1, 2]]);
+        assert_eq!(
+            mesh.uvs,
+            Some(vec![vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]])
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use image::{DynamicImage, RgbImage};
+
+use crate::color::Color;
+use crate::interval::Interval;
+use crate::scene_desc::{self, SceneExporter, TextureDesc};
+use crate::vec3::Point3;
+
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    /// Description of the texture for a scene file, see `scene::save_scene`.
+    fn to_scene(&self, _exporter: &mut SceneExporter) -> Result<TextureDesc, String> {
+        Err(scene_desc::unsupported::<Self>())
+    }
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        SolidColor { albedo }
+    }
+
+    pub fn from_rgb(red: f64, green: f64, blue: f64) -> Self {
+        SolidColor::new(Color::new(red, green, blue))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+
+    fn to_scene(&self, _exporter: &mut SceneExporter) -> Result<TextureDesc, String> {
+        Ok(TextureDesc::Solid {
+            color: self.albedo.into(),
+        })
+    }
+}
+
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        CheckerTexture::new(
+            scale,
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<TextureDesc, String> {
+        let mut solid = |texture: &Arc<dyn Texture>| match exporter.texture(texture)? {
+            TextureDesc::Solid { color } => Ok(color),
+            _ => Err("checker squares must be solid colors to be saved".to_string()),
+        };
+        Ok(TextureDesc::Checker {
+            scale: 1.0 / self.inv_scale,
+            even: solid(&self.even)?,
+            odd: solid(&self.odd)?,
+        })
+    }
+}
+
+/// Checkerboard in texture space, with `u_scale` by `v_scale` squares per unit of uv.
+pub struct UvCheckerTexture {
+    u_scale: f64,
+    v_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl UvCheckerTexture {
+    pub fn new(u_scale: f64, v_scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        UvCheckerTexture {
+            u_scale,
+            v_scale,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let i = (self.u_scale * u).floor() as i64;
+        let j = (self.v_scale * v).floor() as i64;
+
+        if (i + j) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Another texture multiplied by a constant color, e.g. a texture times a material factor.
+pub struct ScaledTexture {
+    tex: Arc<dyn Texture>,
+    scale: Color,
+}
+
+impl ScaledTexture {
+    pub fn new(tex: Arc<dyn Texture>, scale: Color) -> Self {
+        ScaledTexture { tex, scale }
+    }
+}
+
+impl Texture for ScaledTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.scale * self.tex.value(u, v, p)
+    }
+}
+
+pub struct ImageTexture {
+    image: RgbImage,
+    srgb: bool,
+    repeat: bool,
+    source: Option<String>, // File loaded as a color image, if any
+}
+
+impl ImageTexture {
+    pub fn new(image: RgbImage) -> Self {
+        ImageTexture {
+            image,
+            srgb: true,
+            repeat: false,
+            source: None,
+        }
+    }
+
+    /// Loads a color image; texels are converted from gamma space to linear.
+    pub fn load(filename: &str) -> image::ImageResult<Self> {
+        let mut texture = ImageTexture::from_image(&image::open(filename)?);
+        texture.source = Some(filename.to_string());
+        Ok(texture)
+    }
+
+    /// Loads a data image (thickness, height or normal maps) whose texels are used as-is.
+    pub fn load_data(filename: &str) -> image::ImageResult<Self> {
+        Ok(ImageTexture::data_from_image(&image::open(filename)?))
+    }
+
+    /// Loads the alpha channel of an image as a gray data texture, for cutout masks.
+    pub fn load_alpha(filename: &str) -> image::ImageResult<Self> {
+        Ok(ImageTexture::alpha_from_image(&image::open(filename)?))
+    }
+
+    /// Color texture from an already decoded image, like `load`.
+    pub fn from_image(image: &DynamicImage) -> Self {
+        ImageTexture::new(image.to_rgb8())
+    }
+
+    /// Data texture from an already decoded image, like `load_data`.
+    pub fn data_from_image(image: &DynamicImage) -> Self {
+        ImageTexture {
+            image: image.to_rgb8(),
+            srgb: false,
+            repeat: false,
+            source: None,
+        }
+    }
+
+    /// Alpha mask from an already decoded image, like `load_alpha`.
+    pub fn alpha_from_image(image: &DynamicImage) -> Self {
+        let rgba = image.to_rgba8();
+        let image = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let a = rgba.get_pixel(x, y)[3];
+            image::Rgb([a, a, a])
+        });
+        ImageTexture {
+            image,
+            srgb: false,
+            repeat: false,
+            source: None,
+        }
+    }
+
+    /// Tiles the image outside [0, 1] instead of clamping to its edges.
+    pub fn with_repeat(mut self) -> Self {
+        self.repeat = true;
+        self
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    /// Texel lookup with coordinates clamped to the image.
+    pub fn texel(&self, i: i64, j: i64) -> Color {
+        let i = i.clamp(0, self.image.width() as i64 - 1) as u32;
+        let j = j.clamp(0, self.image.height() as i64 - 1) as u32;
+        let pixel = self.image.get_pixel(i, j);
+        let scale = 1.0 / 255.0;
+        let decode = |c: u8| {
+            let c = c as f64 * scale;
+            if self.srgb {
+                srgb_to_linear(c)
+            } else {
+                c
+            }
+        };
+
+        Color::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2]))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        if self.image.width() == 0 || self.image.height() == 0 {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        let (u, v) = if self.repeat {
+            (u - u.floor(), v - v.floor())
+        } else {
+            (
+                Interval::new(0.0, 1.0).clamp(u),
+                Interval::new(0.0, 1.0).clamp(v),
+            )
+        };
+        let v = 1.0 - v;
+
+        let i = (u * self.image.width() as f64) as i64;
+        let j = (v * self.image.height() as f64) as i64;
+
+        self.texel(i, j)
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<TextureDesc, String> {
+        match &self.source {
+            Some(source) => Ok(TextureDesc::Image {
+                path: exporter.relative(source),
+                repeat: self.repeat,
+            }),
+            None => Err("only image textures loaded from a file can be saved".to_string()),
+        }
+    }
+}
+
+#[inline]
+fn srgb_to_linear(c: f64) -> f64 {
+    // Inverse of the gamma 2 curve used in `color::write_color`.
+    c * c
+}
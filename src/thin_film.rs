@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::texture::{SolidColor, Texture};
+use crate::util::PI;
+
+// Representative wavelengths (nm) for the red, green and blue channels.
+const WAVELENGTHS: [f64; 3] = [650.0, 532.0, 450.0];
+
+/// A thin transparent layer whose thickness is comparable to the wavelength of light.
+/// Interference between the two interfaces tints the reflectance, as on soap bubbles,
+/// oil slicks and anodized metal.
+#[derive(Clone)]
+pub struct ThinFilm {
+    ior: f64,
+    min_thickness: f64, // Film thickness in nanometers where the texture is 0
+    max_thickness: f64, // Film thickness in nanometers where the texture is 1
+    thickness: Arc<dyn Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(ior: f64, thickness: f64) -> Self {
+        ThinFilm {
+            ior,
+            min_thickness: thickness,
+            max_thickness: thickness,
+            thickness: Arc::new(SolidColor::from_rgb(1.0, 1.0, 1.0)),
+        }
+    }
+
+    /// Film whose thickness is interpolated between `min_thickness` and `max_thickness`
+    /// by the red channel of `thickness`.
+    pub fn textured(
+        ior: f64,
+        min_thickness: f64,
+        max_thickness: f64,
+        thickness: Arc<dyn Texture>,
+    ) -> Self {
+        ThinFilm {
+            ior,
+            min_thickness,
+            max_thickness,
+            thickness,
+        }
+    }
+
+    pub fn ior(&self) -> f64 {
+        self.ior
+    }
+
+    pub fn thickness_at(&self, rec: &HitRecord) -> f64 {
+        let t = self
+            .thickness
+            .value(rec.u, rec.v, &rec.p)
+            .x()
+            .clamp(0.0, 1.0);
+        self.min_thickness + t * (self.max_thickness - self.min_thickness)
+    }
+
+    /// Reflectance of the film lying between an incident medium of index `n_outer` and a
+    /// dielectric substrate of index `n_substrate`. `cos_i` is the incident angle cosine.
+    pub fn dielectric_reflectance(
+        &self,
+        cos_i: f64,
+        n_outer: f64,
+        n_substrate: f64,
+        thickness: f64,
+    ) -> Color {
+        self.reflectance(cos_i, n_outer, thickness, |cos_film| {
+            let sin2_sub = (self.ior / n_substrate).powi(2) * (1.0 - cos_film * cos_film);
+            if sin2_sub >= 1.0 {
+                return (1.0, 1.0);
+            }
+            let cos_sub = (1.0 - sin2_sub).sqrt();
+            fresnel_amplitudes(self.ior, n_substrate, cos_film, cos_sub)
+        })
+    }
+
+    /// Reflectance of the film over a metal. The metal is approximated by a real
+    /// reflection amplitude per channel derived from its normal-incidence `albedo`,
+    /// with the half-wave phase shift of a conductor.
+    pub fn metal_reflectance(
+        &self,
+        cos_i: f64,
+        n_outer: f64,
+        albedo: Color,
+        thickness: f64,
+    ) -> Color {
+        let amplitudes = [albedo.x(), albedo.y(), albedo.z()].map(|a| -a.max(0.0).sqrt());
+        let mut rgb = [0.0; 3];
+        for (c, value) in rgb.iter_mut().enumerate() {
+            let r23 = amplitudes[c];
+            *value = self.reflectance_channel(cos_i, n_outer, thickness, c, |_| (r23, r23));
+        }
+        Color::new(rgb[0], rgb[1], rgb[2])
+    }
+
+    fn reflectance<F>(&self, cos_i: f64, n_outer: f64, thickness: f64, substrate: F) -> Color
+    where
+        F: Fn(f64) -> (f64, f64),
+    {
+        Color::new(
+            self.reflectance_channel(cos_i, n_outer, thickness, 0, &substrate),
+            self.reflectance_channel(cos_i, n_outer, thickness, 1, &substrate),
+            self.reflectance_channel(cos_i, n_outer, thickness, 2, &substrate),
+        )
+    }
+
+    // Airy summation of the multiple reflections inside the film, averaged over the s and
+    // p polarizations. `substrate` maps the cosine inside the film to the (s, p) amplitude
+    // reflection coefficients at the film/substrate interface.
+    fn reflectance_channel<F>(
+        &self,
+        cos_i: f64,
+        n_outer: f64,
+        thickness: f64,
+        channel: usize,
+        substrate: F,
+    ) -> f64
+    where
+        F: Fn(f64) -> (f64, f64),
+    {
+        let cos_i = cos_i.clamp(0.0, 1.0);
+        let sin2_film = (n_outer / self.ior).powi(2) * (1.0 - cos_i * cos_i);
+        if sin2_film >= 1.0 {
+            return 1.0;
+        }
+        let cos_film = (1.0 - sin2_film).sqrt();
+
+        let (r12_s, r12_p) = fresnel_amplitudes(n_outer, self.ior, cos_i, cos_film);
+        let (r23_s, r23_p) = substrate(cos_film);
+
+        let delta = 4.0 * PI * self.ior * thickness * cos_film / WAVELENGTHS[channel];
+
+        0.5 * (airy(r12_s, r23_s, delta) + airy(r12_p, r23_p, delta))
+    }
+}
+
+fn fresnel_amplitudes(n1: f64, n2: f64, cos1: f64, cos2: f64) -> (f64, f64) {
+    let rs = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let rp = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    (rs, rp)
+}
+
+fn airy(r12: f64, r23: f64, delta: f64) -> f64 {
+    let cross = 2.0 * r12 * r23 * delta.cos();
+    let numerator = r12 * r12 + r23 * r23 + cross;
+    let denominator = 1.0 + r12 * r12 * r23 * r23 + cross;
+    (numerator / denominator).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_airy_reflectance_at_normal_incidence() {
+        // A film of zero thickness leaves the bare air/glass Fresnel reflectance.
+        let bare = ThinFilm::new(1.33, 0.0).dielectric_reflectance(1.0, 1.0, 1.5, 0.0);
+        assert!((bare.x() - 0.04).abs() < 1e-12);
+
+        // A quarter-wave coating of index √1.5 cancels the reflection off glass.
+        let n = 1.5f64.sqrt();
+        let coated = ThinFilm::new(n, 0.0).dielectric_reflectance(1.0, 1.0, 1.5, 532.0 / (4.0 * n));
+        assert!(coated.y().abs() < 1e-12);
+        assert!(coated.x() > 0.0 && coated.z() > 0.0);
+
+        // A quarter-wave soap film in air reflects 4r² / (1 + r²)² with r = 0.33 / 2.33.
+        let soap =
+            ThinFilm::new(1.33, 0.0).dielectric_reflectance(1.0, 1.0, 1.0, 650.0 / (4.0 * 1.33));
+        assert!((soap.x() - 0.077_112_570_305_521).abs() < 1e-12);
+    }
+}
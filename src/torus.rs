@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::quadric::{azimuth, sweep_radians};
+use crate::ray::Ray;
+use crate::scene_desc::{ObjectDesc, SceneExporter};
+use crate::util::PI;
+use crate::vec3::{Point3, Vec3};
+
+/// Torus around the +y axis: a tube of `minor_radius` swept around a circle of
+/// `major_radius` in the xz plane through `center`. u runs around the axis over the
+/// sweep and v around the tube, starting at the outer equator.
+pub struct Torus {
+    center: Point3,
+    major_radius: f64,
+    minor_radius: f64,
+    phi_max: f64,
+    mat: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3,
+        major_radius: f64,
+        minor_radius: f64,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        Torus {
+            center,
+            major_radius: major_radius.abs(),
+            minor_radius: minor_radius.abs(),
+            phi_max: 2.0 * PI,
+            mat,
+        }
+    }
+
+    /// Keeps only the part from the +x axis up to `degrees` toward +z.
+    pub fn with_sweep(mut self, degrees: f64) -> Self {
+        self.phi_max = sweep_radians(degrees);
+        self
+    }
+
+    fn local_box(&self) -> Aabb {
+        let (outer, tube) = (self.major_radius + self.minor_radius, self.minor_radius);
+        Aabb::from_points(
+            Point3::new(-outer, -tube, -outer),
+            Point3::new(outer, tube, outer),
+        )
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        // Solve in local space along a unit direction, starting where the ray enters the
+        // box so the quartic's coefficients stay well conditioned.
+        let length = r.direction().length();
+        if length == 0.0 {
+            return false;
+        }
+        let d = r.direction() / length;
+        let origin = r.origin() - self.center;
+        let local = Ray::new(origin, d);
+        let Some(span) = self.local_box().clip(
+            &local,
+            &Interval::new(ray_t.min * length, ray_t.max * length),
+        ) else {
+            return false;
+        };
+        let (entry, exit) = (span.min, span.max);
+
+        let o = origin + entry * d;
+        let (major2, minor2) = (
+            self.major_radius * self.major_radius,
+            self.minor_radius * self.minor_radius,
+        );
+        // (|p|² + R² - r²)² - 4R²(x² + z²) = 0 with p = o + s d and |d| = 1.
+        let b = 2.0 * o.dot(&d);
+        let c = o.length_squared() + major2 - minor2;
+        let planar_dd = d.x() * d.x() + d.z() * d.z();
+        let planar_od = o.x() * d.x() + o.z() * d.z();
+        let planar_oo = o.x() * o.x() + o.z() * o.z();
+        let coeffs = [
+            c * c - 4.0 * major2 * planar_oo,
+            2.0 * b * c - 8.0 * major2 * planar_od,
+            b * b + 2.0 * c - 4.0 * major2 * planar_dd,
+            2.0 * b,
+            1.0,
+        ];
+
+        for s in polynomial_roots(&coeffs, 0.0, exit - entry) {
+            let t = (entry + s) / length;
+            if !ray_t.surronds(t) {
+                continue;
+            }
+
+            let local_p = o + s * d;
+            let phi = azimuth(local_p.x(), local_p.z());
+            if phi > self.phi_max {
+                continue;
+            }
+            let planar = (local_p.x() * local_p.x() + local_p.z() * local_p.z()).sqrt();
+            let mut theta = local_p.y().atan2(planar - self.major_radius);
+            if theta < 0.0 {
+                theta += 2.0 * PI;
+            }
+
+            let p = r.at(t);
+            let (u, v) = (phi / self.phi_max, theta / (2.0 * PI));
+            if self.mat.masked(u, v, &p) {
+                continue;
+            }
+
+            // Away from the nearest point of the core circle.
+            let core = if planar > 0.0 {
+                Vec3::new(local_p.x(), 0.0, local_p.z()) * (self.major_radius / planar)
+            } else {
+                Vec3::default()
+            };
+            let outward_normal = (local_p - core).unit_vector();
+            rec.t = t;
+            rec.p = p;
+            rec.u = u;
+            rec.v = v;
+            rec.set_normal_face(r, &outward_normal);
+            let tangent = Vec3::new(-local_p.z(), 0.0, local_p.x());
+            rec.set_shading_frame(r, &outward_normal, &tangent);
+            rec.mat = self.mat.clone();
+            return true;
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let local = self.local_box();
+        Aabb::from_points(self.center + local.min(), self.center + local.max())
+    }
+
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.phi_max < 2.0 * PI {
+            return Err("partial tori cannot be saved in a scene file".to_string());
+        }
+        Ok(ObjectDesc::Torus {
+            center: self.center.into(),
+            major_radius: self.major_radius,
+            minor_radius: self.minor_radius,
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+// Real roots in [lo, hi] of the polynomial with coefficients in increasing degree, in
+// increasing order. The roots of the derivative split the range into monotonic pieces,
+// each holding at most one root, which is then found by bisection.
+fn polynomial_roots(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let degree = coeffs.len() - 1;
+    if degree == 0 {
+        return Vec::new();
+    }
+    if degree == 1 {
+        if coeffs[1] == 0.0 {
+            return Vec::new();
+        }
+        let root = -coeffs[0] / coeffs[1];
+        return if (lo..=hi).contains(&root) {
+            vec![root]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let derivative: Vec<f64> = (1..=degree).map(|i| i as f64 * coeffs[i]).collect();
+    let mut bounds = vec![lo];
+    bounds.extend(polynomial_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let eval = |x: f64| coeffs.iter().rev().fold(0.0, |acc, &c| acc * x + c);
+    let mut roots: Vec<f64> = Vec::new();
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (mut fa, fb) = (eval(a), eval(b));
+        if fa == 0.0 {
+            if roots.last().is_none_or(|&last| a - last > 1e-12) {
+                roots.push(a);
+            }
+            continue;
+        }
+        if fa * fb > 0.0 {
+            continue;
+        }
+        for _ in 0..64 {
+            let mid = 0.5 * (a + b);
+            let fm = eval(mid);
+            if fm == 0.0 || b - a < 1e-12 {
+                (a, b) = (mid, mid);
+                break;
+            }
+            if (fa < 0.0) == (fm < 0.0) {
+                (a, fa) = (mid, fm);
+            } else {
+                b = mid;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn hit_t(torus: &Torus, origin: Point3, direction: Vec3) -> Option<f64> {
+        let mut rec = HitRecord::default();
+        let interval = Interval::new(0.001, f64::INFINITY);
+        torus
+            .hit(&Ray::new(origin, direction), &interval, &mut rec)
+            .then_some(rec.t)
+    }
+
+    #[test]
+    fn hits_the_nearest_side_of_the_tube() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+        let torus = Torus::new(Point3::new(0.0, 0.0, 0.0), 2.0, 0.5, mat);
+        // Along the x axis the tube spans 1.5..2.5 on either side.
+        let t = hit_t(
+            &torus,
+            Point3::new(-5.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+        );
+        assert!((t.unwrap() - 1.25).abs() < 1e-9);
+        let t = hit_t(&torus, Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((t.unwrap() - 1.5).abs() < 1e-9);
+        let t = hit_t(
+            &torus,
+            Point3::new(2.0, 3.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert!((t.unwrap() - 2.5).abs() < 1e-9);
+        assert!(hit_t(
+            &torus,
+            Point3::new(0.0, 3.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0)
+        )
+        .is_none());
+
+        let half =
+            Torus::new(Point3::new(0.0, 0.0, 0.0), 2.0, 0.5, torus.mat.clone()).with_sweep(180.0);
+        assert!(hit_t(&half, Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).is_some());
+        // From -z the swept-away half is skipped.
+        let t = hit_t(&half, Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!((t.unwrap() - 6.5).abs() < 1e-9);
+    }
+}
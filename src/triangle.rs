@@ -1,18 +1,25 @@
-use std::f64::EPSILON;
 use std::sync::Arc;
 
+use crate::scene_desc::{ObjectDesc, SceneExporter};
 use crate::{
-    hittable::Hittable,
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
     material::Material,
+    ray::Ray,
+    util::random_double,
     vec3::{Point3, Vec3},
 };
-use std::rc::Rc;
 
 pub struct Triangle {
     pub p0: Point3,
     pub p1: Point3,
     pub p2: Point3,
     pub normal: Vec3,
+    pub vertex_normals: Option<[Vec3; 3]>,
+    pub uvs: [(f64, f64); 3],
+    pub tangent: Vec3,
+    pub tangent_sign: f64, // -1 where the UVs are mirrored, see `tangent_from_uvs`
     pub mat: Arc<dyn Material>,
 }
 
@@ -22,87 +29,249 @@ impl Triangle {
         let edge2 = p2 - p0;
         let normal = edge1.cross(&edge2).normalize();
 
-        Self {
+        let mut triangle = Self {
             p0,
             p1,
             p2,
             normal,
+            vertex_normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            tangent: Vec3::zero(),
+            tangent_sign: 1.0,
             mat,
-        }
+        };
+        (triangle.tangent, triangle.tangent_sign) = triangle.compute_tangent();
+        triangle
+    }
+
+    /// Per-vertex normals, interpolated across the face for smooth shading.
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.vertex_normals = Some(normals.map(|n| n.unit_vector()));
+        self
+    }
+
+    /// Per-vertex texture coordinates. The tangent frame is derived from them.
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = uvs;
+        (self.tangent, self.tangent_sign) = self.compute_tangent();
+        self
+    }
+
+    fn compute_tangent(&self) -> (Vec3, f64) {
+        tangent_from_uvs([&self.p0, &self.p1, &self.p2], self.uvs)
     }
 }
 
 impl Hittable for Triangle {
-    fn hit(
-        &self,
-        r: &crate::ray::Ray,
-        ray_t: &crate::interval::Interval,
-        rec: &mut crate::hittable::HitRecord,
-    ) -> bool {
-        let epsilon = 1e-8;
-
-        let edge1 = self.p1 - self.p0;
-        let edge2 = self.p2 - self.p0;
-        let h = r.direction().cross(&edge2);
-        let a = edge1.dot(&h);
-
-        if (a > -epsilon) && (a < epsilon) {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let Some((t, u, v)) = intersect(&self.p0, &self.p1, &self.p2, r) else {
+            return false;
+        };
+        if !ray_t.surronds(t) {
             return false;
         }
 
-        let f = 1.0 / a;
-        let s = r.origin() - self.p0;
-        let u = f * s.dot(&h);
-
-        if u < 0.0 || u > 1.0 {
+        let p = r.at(t);
+        let w = 1.0 - u - v;
+        let tex_u = w * self.uvs[0].0 + u * self.uvs[1].0 + v * self.uvs[2].0;
+        let tex_v = w * self.uvs[0].1 + u * self.uvs[1].1 + v * self.uvs[2].1;
+        if self.mat.masked(tex_u, tex_v, &p) {
             return false;
         }
 
-        let q = s.cross(&edge1);
-        let v = f * r.direction().dot(&q);
+        rec.t = t;
+        rec.p = p;
+        let outward_normal = self.normal;
+        rec.set_normal_face(r, &outward_normal);
+        rec.u = tex_u;
+        rec.v = tex_v;
 
-        if v < 0.0 || u + v > 1.0 {
-            return false;
+        let shading_normal = match &self.vertex_normals {
+            Some([n0, n1, n2]) => (w * *n0 + u * *n1 + v * *n2).unit_vector(),
+            None => self.normal,
+        };
+        rec.set_signed_shading_frame(r, &shading_normal, &self.tangent, self.tangent_sign);
+        rec.mat = self.mat.clone();
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bbox = Aabb::from_points(self.p0, self.p1);
+        Aabb::surrounding(&bbox, &Aabb::from_points(self.p2, self.p2))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, _time: f64) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(
+            &Ray::new(*origin, *direction),
+            &Interval::new(0.001, f64::INFINITY),
+            &mut rec,
+        ) {
+            return 0.0;
         }
 
-        let t = f * edge2.dot(&q);
+        let area = 0.5 * (self.p1 - self.p0).cross(&(self.p2 - self.p0)).length();
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length()).abs();
 
-        if t > epsilon && t < ray_t.max {
-            rec.t = t;
-            rec.p = r.at(t);
-            let outward_normal = self.normal;
-            rec.set_normal_face(r, &outward_normal);
-            rec.mat = self.mat.clone();
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        // Square-rooting one coordinate spreads the samples evenly over the area.
+        let s = random_double().sqrt();
+        let t = random_double();
+        let p = (1.0 - s) * self.p0 + (s * (1.0 - t)) * self.p1 + (s * t) * self.p2;
+        p - *origin
+    }
 
-            return true;
+    fn to_scene(&self, exporter: &mut SceneExporter) -> Result<ObjectDesc, String> {
+        if self.vertex_normals.is_some() || self.uvs != [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)] {
+            return Err("triangles with normals or uvs cannot be saved; use a mesh".to_string());
         }
+        Ok(ObjectDesc::Triangle {
+            vertices: [self.p0.into(), self.p1.into(), self.p2.into()],
+            material: exporter.material(&self.mat)?,
+            transform: None,
+        })
+    }
+}
+
+/// Möller–Trumbore ray/triangle test. Returns the ray parameter and the barycentric
+/// weights of `p1` and `p2`.
+pub fn intersect(p0: &Point3, p1: &Point3, p2: &Point3, r: &Ray) -> Option<(f64, f64, f64)> {
+    let epsilon = 1e-8;
+
+    let edge1 = *p1 - *p0;
+    let edge2 = *p2 - *p0;
+    let h = r.direction().cross(&edge2);
+    let a = edge1.dot(&h);
+
+    if (a > -epsilon) && (a < epsilon) {
+        return None;
+    }
+
+    let f = 1.0 / a;
+    let s = r.origin() - *p0;
+    let u = f * s.dot(&h);
+
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let v = f * r.direction().dot(&q);
+
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
 
-        false
+    let t = f * edge2.dot(&q);
+    if t > epsilon {
+        Some((t, u, v))
+    } else {
+        None
     }
 }
 
-pub fn create_cube(material: Arc<dyn Material>, size: f64) -> Vec<Triangle> {
-    let v0 = Point3::new(0.0, 0.0, 0.0);
-    let v1 = Point3::new(size, 0.0, 0.0);
-    let v2 = Point3::new(size, size, 0.0);
-    let v3 = Point3::new(0.0, size, 0.0);
-    let v4 = Point3::new(0.0, 0.0, size);
-    let v5 = Point3::new(size, 0.0, size);
-    let v6 = Point3::new(size, size, size);
-    let v7 = Point3::new(0.0, size, size);
-
-    vec![
-        Triangle::new(v0, v1, v3, material.clone()),
-        Triangle::new(v1, v2, v3, material.clone()),
-        Triangle::new(v4, v5, v7, material.clone()),
-        Triangle::new(v5, v6, v7, material.clone()),
-        Triangle::new(v0, v3, v4, material.clone()),
-        Triangle::new(v3, v7, v4, material.clone()),
-        Triangle::new(v1, v2, v5, material.clone()),
-        Triangle::new(v2, v6, v5, material.clone()),
-        Triangle::new(v3, v2, v7, material.clone()),
-        Triangle::new(v2, v6, v7, material.clone()),
-        Triangle::new(v0, v1, v4, material.clone()),
-        Triangle::new(v1, v5, v4, material.clone()),
-    ]
+/// Direction of increasing u across a face, or zero if the UV mapping is degenerate,
+/// with the handedness of the mapping: 1 if v increases along `normal × tangent` for
+/// the face's winding normal, -1 if the UVs are mirrored.
+pub fn tangent_from_uvs(p: [&Point3; 3], uvs: [(f64, f64); 3]) -> (Vec3, f64) {
+    let dp1 = *p[1] - *p[0];
+    let dp2 = *p[2] - *p[0];
+    let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+    let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < 1e-12 {
+        return (Vec3::zero(), 1.0);
+    }
+
+    let tangent = (dv2 * dp1 - dv1 * dp2) / det;
+    let sign = det.signum();
+    if tangent.near_zero() {
+        (Vec3::zero(), sign)
+    } else {
+        (tangent.unit_vector(), sign)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::mesh::TriangleMesh;
+    use crate::normal_map::NormalMap;
+    use crate::texture::SolidColor;
+
+    fn xyz(v: &Vec3) -> [f64; 3] {
+        [v.x(), v.y(), v.z()].map(|c| (c * 1e9).round() / 1e9)
+    }
+
+    // The unit right triangle in the xy plane, with u along +x or, mirrored, along -x;
+    // v always increases along +y.
+    fn faces(mirrored: bool) -> [Arc<dyn Hittable>; 2] {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let (p0, p1, p2) = (
+            Point3::zero(),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        );
+        let uvs = if mirrored {
+            [(1.0, 0.0), (0.0, 0.0), (1.0, 1.0)]
+        } else {
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]
+        };
+        let mesh = TriangleMesh::new(
+            vec![p0, p1, p2],
+            Vec::new(),
+            uvs.to_vec(),
+            vec![[0, 1, 2]],
+            mat.clone(),
+        );
+        [
+            Arc::new(Triangle::new(p0, p1, p2, mat).with_uvs(uvs)),
+            Arc::new(mesh),
+        ]
+    }
+
+    #[test]
+    fn bitangent_follows_v_on_mirrored_and_back_faces() {
+        for mirrored in [false, true] {
+            let u_direction = if mirrored { -1.0 } else { 1.0 };
+            for face in faces(mirrored) {
+                for z in [1.0, -1.0] {
+                    let r = Ray::new(Point3::new(0.25, 0.25, z), Vec3::new(0.0, 0.0, -z));
+                    let mut rec = HitRecord::default();
+                    assert!(face.hit(&r, &Interval::new(0.001, 10.0), &mut rec));
+                    assert_eq!(xyz(&rec.tangent), [u_direction, 0.0, 0.0]);
+                    assert_eq!(xyz(&rec.bitangent), [0.0, 1.0, 0.0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normal_maps_tilt_toward_v_on_mirrored_faces() {
+        // Green above one half tilts the normal toward increasing v, i.e. +y here.
+        let map = NormalMap::Tangent(Arc::new(SolidColor::new(Color::new(0.5, 0.9, 0.8))));
+        for mirrored in [false, true] {
+            for face in faces(mirrored) {
+                let r = Ray::new(Point3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+                let mut rec = HitRecord::default();
+                assert!(face.hit(&r, &Interval::new(0.001, 10.0), &mut rec));
+                map.apply(&r, &mut rec);
+                assert!(
+                    rec.normal.y() > 0.5,
+                    "mirrored {}: {:?}",
+                    mirrored,
+                    xyz(&rec.normal)
+                );
+                assert_eq!(xyz(&rec.bitangent).map(f64::signum)[1], 1.0);
+            }
+        }
+    }
 }
@@ -1,5 +1,4 @@
 use crate::util::{self};
-use rand::Rng;
 use std::default::Default;
 
 use std::ops::{Add, Div, Mul, Sub};
@@ -48,7 +47,7 @@ impl Vec3 {
     }
 
     pub fn length(&self) -> f64 {
-        self.dot(&self).sqrt()
+        self.dot(self).sqrt()
     }
 
     pub fn normalize(&self) -> Vec3 {
@@ -76,13 +75,8 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_sphere() -> Self {
-        let mut rng = rand::thread_rng();
         loop {
-            let p = Vec3::new(
-                rng.gen_range(-1.0..1.0),
-                rng.gen_range(-1.0..1.0),
-                rng.gen_range(-1.0..1.0),
-            );
+            let p = Vec3::random(-1.0, 1.0);
             if p.length_squared() < 1.0 {
                 return p;
             }
@@ -207,6 +201,23 @@ pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
     }
 }
 
+/// Two unit vectors that together with the unit vector `n` form a right-handed
+/// orthonormal basis.
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0_f64.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    let s = Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x());
+    let t = Vec3::new(b, sign + n.y() * n.y() * a, -n.y());
+    (s, t)
+}
+
 pub fn unit_vector(v: Vec3) -> Vec3 {
     v / (v.length())
 }
+
+impl From<Vec3> for [f64; 3] {
+    fn from(v: Vec3) -> Self {
+        v.e
+    }
+}